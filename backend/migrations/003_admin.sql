@@ -0,0 +1,16 @@
+-- Admins are promoted by hand: UPDATE players SET role = 'admin' WHERE username = '...';
+ALTER TABLE players
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'player' CHECK (role IN ('player', 'admin')),
+    ADD COLUMN hidden_by_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN banned_at TIMESTAMPTZ;
+
+CREATE TABLE admin_audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    admin_id UUID NOT NULL REFERENCES players(id),
+    action TEXT NOT NULL,
+    target_player_id UUID REFERENCES players(id),
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX admin_audit_log_target_idx ON admin_audit_log (target_player_id, created_at);
@@ -0,0 +1,9 @@
+-- A ban is active while ban_kind is set and ban_expires_at is NULL or in the future.
+-- 'hard' bans lock the player out of every authenticated endpoint; 'shadow'
+-- bans only hide them from other players' leaderboards.
+ALTER TABLE players
+    ADD COLUMN ban_kind TEXT CHECK (ban_kind IN ('hard', 'shadow')),
+    ADD COLUMN ban_reason TEXT,
+    ADD COLUMN ban_expires_at TIMESTAMPTZ;
+
+UPDATE players SET ban_kind = 'hard' WHERE banned_at IS NOT NULL;
@@ -0,0 +1,17 @@
+-- Large saves are stored as zstd-compressed JSON in save_data_zstd instead of
+-- save_data; exactly one of the two is set. size_bytes is the uncompressed
+-- JSON size and stored_bytes what the row actually holds.
+ALTER TABLE saves
+    ALTER COLUMN save_data DROP NOT NULL,
+    ADD COLUMN save_data_zstd BYTEA,
+    ADD COLUMN size_bytes INTEGER,
+    ADD COLUMN stored_bytes INTEGER;
+
+UPDATE saves
+SET size_bytes = octet_length(save_data::text),
+    stored_bytes = octet_length(save_data::text);
+
+ALTER TABLE saves
+    ALTER COLUMN size_bytes SET NOT NULL,
+    ALTER COLUMN stored_bytes SET NOT NULL,
+    ADD CONSTRAINT saves_one_encoding CHECK ((save_data IS NULL) <> (save_data_zstd IS NULL));
@@ -0,0 +1,4 @@
+-- Actions taken through the management CLI (`consultancy-tycoon-api player ...`)
+-- are audited without an admin account behind them.
+ALTER TABLE admin_audit_log
+    ALTER COLUMN admin_id DROP NOT NULL;
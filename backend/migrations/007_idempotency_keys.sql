@@ -0,0 +1,15 @@
+-- Responses to requests sent with an Idempotency-Key header, replayed when
+-- the client retries. status is NULL while the first request is in flight.
+CREATE TABLE idempotency_keys (
+    key TEXT NOT NULL,
+    -- The authenticated player, or the nil UUID for anonymous requests
+    player_id UUID NOT NULL,
+    fingerprint TEXT NOT NULL,
+    status SMALLINT,
+    content_type TEXT,
+    body BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (key, player_id)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,13 @@
+-- Messages for a player: being overtaken on the leaderboard, announcements
+-- from the admins. data holds the kind-specific fields.
+CREATE TABLE notifications (
+    id BIGSERIAL PRIMARY KEY,
+    player_id UUID NOT NULL REFERENCES players(id),
+    kind TEXT NOT NULL,
+    data JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    read_at TIMESTAMPTZ
+);
+
+CREATE INDEX notifications_player_idx ON notifications (player_id, id);
+CREATE INDEX notifications_created_at_idx ON notifications (created_at);
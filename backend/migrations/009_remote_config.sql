@@ -0,0 +1,10 @@
+-- Published remote config documents. The newest version is the live one;
+-- rolling back republishes an older document as a new version, so clients
+-- see the change and the history stays in order.
+CREATE TABLE config_versions (
+    version BIGSERIAL PRIMARY KEY,
+    document JSONB NOT NULL,
+    note TEXT NOT NULL DEFAULT '',
+    published_by UUID REFERENCES players(id),
+    published_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
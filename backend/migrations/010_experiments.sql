@@ -0,0 +1,19 @@
+-- A/B experiments. Players get a variant from a hash of their id, so nothing
+-- is stored per player until they're exposed to it.
+CREATE TABLE experiments (
+    key TEXT PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT '',
+    variants JSONB NOT NULL,
+    created_by UUID REFERENCES players(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ended_at TIMESTAMPTZ
+);
+
+-- When each player first saw their variant. Reports only count these.
+CREATE TABLE experiment_exposures (
+    experiment_key TEXT NOT NULL REFERENCES experiments(key),
+    player_id UUID NOT NULL REFERENCES players(id),
+    variant TEXT NOT NULL,
+    exposed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (experiment_key, player_id)
+);
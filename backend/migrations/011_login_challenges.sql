@@ -0,0 +1,12 @@
+-- Two-factor login challenges that haven't been redeemed. A challenge token
+-- only works while its row exists, so each is good for one login.
+CREATE TABLE login_challenges (
+    id UUID PRIMARY KEY,
+    player_id UUID NOT NULL REFERENCES players(id),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX login_challenges_player_id_idx ON login_challenges (player_id);
+
+-- Second-factor codes tried since the last successful two-factor login
+ALTER TABLE players ADD COLUMN two_factor_attempts INTEGER NOT NULL DEFAULT 0;
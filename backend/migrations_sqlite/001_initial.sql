@@ -0,0 +1,30 @@
+-- SQLite mirror of migrations/. Ids are stored as 16-byte BLOBs, JSON as
+-- TEXT and timestamps as RFC 3339 TEXT, which is how sqlx encodes them.
+CREATE TABLE players (
+    id BLOB PRIMARY KEY,
+    display_name TEXT NOT NULL,
+    passphrase TEXT NOT NULL UNIQUE,
+    username TEXT UNIQUE,
+    password_hash TEXT,
+    show_on_leaderboard BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE score_components (
+    player_id BLOB PRIMARY KEY REFERENCES players(id),
+    total_money_earned REAL NOT NULL DEFAULT 0,
+    reputation REAL NOT NULL DEFAULT 0,
+    skill_levels_sum INTEGER NOT NULL DEFAULT 0,
+    consultants_count INTEGER NOT NULL DEFAULT 0,
+    ai_tool_tiers_sum INTEGER NOT NULL DEFAULT 0,
+    manual_tasks_completed INTEGER NOT NULL DEFAULT 0,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE saves (
+    player_id BLOB PRIMARY KEY REFERENCES players(id),
+    save_data TEXT NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,11 @@
+ALTER TABLE players ADD COLUMN totp_secret TEXT;
+ALTER TABLE players ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE players ADD COLUMN totp_last_step INTEGER;
+
+CREATE TABLE backup_codes (
+    player_id BLOB NOT NULL REFERENCES players(id),
+    code_hash TEXT NOT NULL,
+    used_at TEXT,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (player_id, code_hash)
+);
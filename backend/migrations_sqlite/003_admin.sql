@@ -0,0 +1,14 @@
+ALTER TABLE players ADD COLUMN role TEXT NOT NULL DEFAULT 'player' CHECK (role IN ('player', 'admin'));
+ALTER TABLE players ADD COLUMN hidden_by_admin BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE players ADD COLUMN banned_at TEXT;
+
+CREATE TABLE admin_audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    admin_id BLOB NOT NULL REFERENCES players(id),
+    action TEXT NOT NULL,
+    target_player_id BLOB REFERENCES players(id),
+    details TEXT NOT NULL DEFAULT '{}',
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX admin_audit_log_target_idx ON admin_audit_log (target_player_id, created_at);
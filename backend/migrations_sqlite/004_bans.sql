@@ -0,0 +1,5 @@
+ALTER TABLE players ADD COLUMN ban_kind TEXT CHECK (ban_kind IN ('hard', 'shadow'));
+ALTER TABLE players ADD COLUMN ban_reason TEXT;
+ALTER TABLE players ADD COLUMN ban_expires_at TEXT;
+
+UPDATE players SET ban_kind = 'hard' WHERE banned_at IS NOT NULL;
@@ -0,0 +1,19 @@
+-- SQLite can't relax NOT NULL or add a CHECK in place, so the table is rebuilt.
+CREATE TABLE saves_new (
+    player_id BLOB PRIMARY KEY REFERENCES players(id),
+    save_data TEXT,
+    save_data_zstd BLOB,
+    size_bytes INTEGER NOT NULL,
+    stored_bytes INTEGER NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1,
+    updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT saves_one_encoding CHECK ((save_data IS NULL) <> (save_data_zstd IS NULL))
+);
+
+INSERT INTO saves_new (player_id, save_data, size_bytes, stored_bytes, version, updated_at)
+SELECT player_id, save_data, length(CAST(save_data AS BLOB)), length(CAST(save_data AS BLOB)),
+       version, updated_at
+FROM saves;
+
+DROP TABLE saves;
+ALTER TABLE saves_new RENAME TO saves;
@@ -0,0 +1,16 @@
+-- Rebuilt to make admin_id nullable, as in migrations/006_cli_audit.sql.
+CREATE TABLE admin_audit_log_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    admin_id BLOB REFERENCES players(id),
+    action TEXT NOT NULL,
+    target_player_id BLOB REFERENCES players(id),
+    details TEXT NOT NULL DEFAULT '{}',
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO admin_audit_log_new SELECT * FROM admin_audit_log;
+
+DROP TABLE admin_audit_log;
+ALTER TABLE admin_audit_log_new RENAME TO admin_audit_log;
+
+CREATE INDEX admin_audit_log_target_idx ON admin_audit_log (target_player_id, created_at);
@@ -0,0 +1,13 @@
+-- As in migrations/007_idempotency_keys.sql.
+CREATE TABLE idempotency_keys (
+    key TEXT NOT NULL,
+    player_id BLOB NOT NULL,
+    fingerprint TEXT NOT NULL,
+    status INTEGER,
+    content_type TEXT,
+    body BLOB,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (key, player_id)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys (created_at);
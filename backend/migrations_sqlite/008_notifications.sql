@@ -0,0 +1,12 @@
+-- As in migrations/008_notifications.sql.
+CREATE TABLE notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    player_id BLOB NOT NULL REFERENCES players(id),
+    kind TEXT NOT NULL,
+    data TEXT NOT NULL DEFAULT '{}',
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    read_at TEXT
+);
+
+CREATE INDEX notifications_player_idx ON notifications (player_id, id);
+CREATE INDEX notifications_created_at_idx ON notifications (created_at);
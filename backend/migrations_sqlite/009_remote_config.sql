@@ -0,0 +1,8 @@
+-- As in migrations/009_remote_config.sql.
+CREATE TABLE config_versions (
+    version INTEGER PRIMARY KEY AUTOINCREMENT,
+    document TEXT NOT NULL,
+    note TEXT NOT NULL DEFAULT '',
+    published_by BLOB REFERENCES players(id),
+    published_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,17 @@
+-- As in migrations/010_experiments.sql.
+CREATE TABLE experiments (
+    key TEXT PRIMARY KEY,
+    description TEXT NOT NULL DEFAULT '',
+    variants TEXT NOT NULL,
+    created_by BLOB REFERENCES players(id),
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ended_at TEXT
+);
+
+CREATE TABLE experiment_exposures (
+    experiment_key TEXT NOT NULL REFERENCES experiments(key),
+    player_id BLOB NOT NULL REFERENCES players(id),
+    variant TEXT NOT NULL,
+    exposed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (experiment_key, player_id)
+);
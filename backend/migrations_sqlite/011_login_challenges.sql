@@ -0,0 +1,10 @@
+-- As in migrations/011_login_challenges.sql.
+CREATE TABLE login_challenges (
+    id BLOB PRIMARY KEY,
+    player_id BLOB NOT NULL REFERENCES players(id),
+    expires_at TEXT NOT NULL
+);
+
+CREATE INDEX login_challenges_player_id_idx ON login_challenges (player_id);
+
+ALTER TABLE players ADD COLUMN two_factor_attempts INTEGER NOT NULL DEFAULT 0;
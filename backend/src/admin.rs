@@ -0,0 +1,613 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    auth::AdminPlayer,
+    error::{ApiError, ErrorBody, FieldErrors, Json, Path, Query},
+    experiments,
+    models::*,
+    notifications, remote_config,
+    repo::Repository,
+    AppState,
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 500;
+
+/// Routes mounted under /api/admin. Every route requires the admin role.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/players", get(search_players))
+        .route("/players/{id}", get(get_player))
+        .route("/players/{id}/hide", post(hide_player))
+        .route("/players/{id}/unhide", post(unhide_player))
+        .route("/players/{id}/reset-scores", post(reset_scores))
+        .route("/players/{id}/ban", post(ban_player))
+        .route("/players/{id}/unban", post(unban_player))
+        .route("/audit", get(get_audit_log))
+        .route("/announcements", post(announce))
+        .route("/config", get(list_config_versions).post(publish_config))
+        .route("/config/rollback", post(rollback_config))
+        .route(
+            "/experiments",
+            get(list_experiments).post(create_experiment),
+        )
+        .route("/experiments/{key}/end", post(end_experiment))
+        .route("/experiments/{key}/report", get(experiment_report))
+}
+
+fn clamp_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// GET /api/admin/players?q= — Search players.
+#[utoipa::path(
+    get,
+    path = "/api/admin/players",
+    tag = "admin",
+    params(AdminPlayerSearch),
+    responses(
+        (status = 200, body = Vec<AdminPlayerSummary>),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn search_players(
+    _admin: AdminPlayer,
+    State(state): State<AppState>,
+    Query(search): Query<AdminPlayerSearch>,
+) -> Result<impl IntoResponse, ApiError> {
+    let players = state
+        .db
+        .search_players(search.q.as_deref().unwrap_or(""), clamp_limit(search.limit))
+        .await?;
+
+    Ok(Json(players))
+}
+
+/// GET /api/admin/players/{id} — Player details with score components and save metadata.
+#[utoipa::path(
+    get,
+    path = "/api/admin/players/{id}",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Player id")),
+    responses(
+        (status = 200, body = AdminPlayerDetail),
+        (status = 404, description = "No such player", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn get_player(
+    _admin: AdminPlayer,
+    State(state): State<AppState>,
+    Path(player_id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let detail = player_detail(state.db.as_ref(), player_id)
+        .await?
+        .ok_or_else(player_not_found)?;
+
+    Ok(Json(detail))
+}
+
+/// Everything an admin sees about a player. Shared with the `player show` command.
+pub async fn player_detail(
+    repo: &dyn Repository,
+    player_id: Uuid,
+) -> Result<Option<AdminPlayerDetail>, sqlx::Error> {
+    let Some(player) = repo.find_player_by_id(player_id).await? else {
+        return Ok(None);
+    };
+
+    let scores = repo.get_score_components(player_id).await?;
+    let rank = repo.get_player_rank(player_id).await?.map(|(rank, _)| rank);
+    let save = repo.get_save_metadata(player_id).await?;
+
+    Ok(Some(AdminPlayerDetail {
+        player: AdminPlayerSummary {
+            id: player.id,
+            display_name: player.display_name,
+            username: player.username,
+            role: player.role,
+            show_on_leaderboard: player.show_on_leaderboard,
+            hidden_by_admin: player.hidden_by_admin,
+            banned_at: player.banned_at,
+            ban_kind: player.ban_kind,
+            ban_reason: player.ban_reason,
+            ban_expires_at: player.ban_expires_at,
+            created_at: player.created_at,
+        },
+        totp_enabled: player.totp_enabled,
+        rank,
+        scores,
+        save,
+    }))
+}
+
+/// POST /api/admin/players/{id}/hide — Hide a player from the leaderboard.
+#[utoipa::path(
+    post,
+    path = "/api/admin/players/{id}/hide",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Player id")),
+    request_body = AdminActionRequest,
+    responses(
+        (status = 200, description = "Hidden"),
+        (status = 404, description = "No such player", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn hide_player(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Path(player_id): Path<Uuid>,
+    Json(req): Json<AdminActionRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let details = reason_details(req);
+    let found = state
+        .db
+        .set_hidden_by_admin(Some(admin_id), player_id, true, details)
+        .await?;
+    state.leaderboard.changed();
+
+    found_or_404(found)
+}
+
+/// POST /api/admin/players/{id}/unhide — Undo a hide.
+#[utoipa::path(
+    post,
+    path = "/api/admin/players/{id}/unhide",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Player id")),
+    request_body = AdminActionRequest,
+    responses(
+        (status = 200, description = "Unhidden"),
+        (status = 404, description = "No such player", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn unhide_player(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Path(player_id): Path<Uuid>,
+    Json(req): Json<AdminActionRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let details = reason_details(req);
+    let found = state
+        .db
+        .set_hidden_by_admin(Some(admin_id), player_id, false, details)
+        .await?;
+    state.leaderboard.changed();
+
+    found_or_404(found)
+}
+
+/// POST /api/admin/players/{id}/reset-scores — Zero all score components.
+#[utoipa::path(
+    post,
+    path = "/api/admin/players/{id}/reset-scores",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Player id")),
+    request_body = AdminActionRequest,
+    responses(
+        (status = 200, description = "Scores reset"),
+        (status = 404, description = "No such player", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn reset_scores(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Path(player_id): Path<Uuid>,
+    Json(req): Json<AdminActionRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    // Keep the old values in the audit log so a mistaken reset can be undone by hand
+    let previous = state.db.get_score_components(player_id).await?;
+    let mut details = reason_details(req);
+    details["previous"] = json!(previous);
+
+    let found = state
+        .db
+        .reset_scores(Some(admin_id), player_id, details)
+        .await?;
+    state.leaderboard.changed();
+
+    found_or_404(found)
+}
+
+/// POST /api/admin/players/{id}/ban — Hard- or shadow-ban a player, optionally until a given time.
+#[utoipa::path(
+    post,
+    path = "/api/admin/players/{id}/ban",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Player id")),
+    request_body = BanRequest,
+    responses(
+        (status = 200, description = "Banned"),
+        (status = 404, description = "No such player", body = ErrorBody),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn ban_player(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Path(player_id): Path<Uuid>,
+    Json(req): Json<BanRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut errors = FieldErrors::default();
+    errors.check("reason", !req.reason.trim().is_empty(), "must not be empty");
+    errors.check(
+        "expires_at",
+        req.expires_at.is_none_or(|t| t > chrono::Utc::now()),
+        "must be in the future",
+    );
+    errors.into_result()?;
+
+    let found = state
+        .db
+        .ban_player(
+            Some(admin_id),
+            player_id,
+            req.kind,
+            req.reason.trim(),
+            req.expires_at,
+        )
+        .await?;
+    state.leaderboard.changed();
+
+    found_or_404(found)
+}
+
+/// POST /api/admin/players/{id}/unban — Lift a ban.
+#[utoipa::path(
+    post,
+    path = "/api/admin/players/{id}/unban",
+    tag = "admin",
+    params(("id" = Uuid, Path, description = "Player id")),
+    request_body = AdminActionRequest,
+    responses(
+        (status = 200, description = "Unbanned"),
+        (status = 404, description = "No such player", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn unban_player(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Path(player_id): Path<Uuid>,
+    Json(req): Json<AdminActionRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let details = reason_details(req);
+    let found = state
+        .db
+        .unban_player(Some(admin_id), player_id, details)
+        .await?;
+    state.leaderboard.changed();
+
+    found_or_404(found)
+}
+
+/// GET /api/admin/audit — Recent admin actions, optionally for one player.
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit",
+    tag = "admin",
+    params(AuditLogQuery),
+    responses(
+        (status = 200, body = Vec<AuditLogEntry>),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn get_audit_log(
+    _admin: AdminPlayer,
+    State(state): State<AppState>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let entries = state
+        .db
+        .get_audit_log(query.player_id, clamp_limit(query.limit))
+        .await?;
+
+    Ok(Json(entries))
+}
+
+/// POST /api/admin/announcements — Send every player a notification, e.g. that a season is ending.
+#[utoipa::path(
+    post,
+    path = "/api/admin/announcements",
+    tag = "admin",
+    request_body = AnnouncementRequest,
+    responses(
+        (status = 200, body = AnnouncementSent),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn announce(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Json(req): Json<AnnouncementRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (title, message) = (req.title.trim(), req.message.trim());
+    let mut errors = FieldErrors::default();
+    for (field, value, max) in [("title", title, 100), ("message", message, 1000)] {
+        errors.check(
+            field,
+            !value.is_empty() && value.chars().count() <= max,
+            format!("must be 1 to {max} characters"),
+        );
+    }
+    errors.into_result()?;
+
+    let notified = notifications::announce(&state, admin_id, title, message).await?;
+    tracing::info!(%admin_id, notified, title, "announcement sent");
+
+    Ok(Json(AnnouncementSent { notified }))
+}
+
+/// GET /api/admin/config — Published config versions, newest (live) first.
+#[utoipa::path(
+    get,
+    path = "/api/admin/config",
+    tag = "admin",
+    params(ConfigVersionQuery),
+    responses(
+        (status = 200, body = Vec<ConfigVersion>),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn list_config_versions(
+    _admin: AdminPlayer,
+    State(state): State<AppState>,
+    Query(query): Query<ConfigVersionQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let versions = state
+        .db
+        .list_config_versions(clamp_limit(query.limit))
+        .await?;
+
+    Ok(Json(versions))
+}
+
+/// POST /api/admin/config — Publish a config document as the new live version.
+#[utoipa::path(
+    post,
+    path = "/api/admin/config",
+    tag = "admin",
+    request_body = PublishConfigRequest,
+    responses(
+        (status = 200, body = ConfigVersion),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn publish_config(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Json(req): Json<PublishConfigRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut errors = FieldErrors::default();
+    remote_config::check_document(&mut errors, &req.document);
+    errors.into_result()?;
+
+    let published = state
+        .db
+        .publish_config(Some(admin_id), &req.document, req.note.trim(), None)
+        .await?;
+    tracing::info!(%admin_id, version = published.version, "config published");
+
+    Ok(Json(published))
+}
+
+/// POST /api/admin/config/rollback — Publish an earlier version's document again.
+#[utoipa::path(
+    post,
+    path = "/api/admin/config/rollback",
+    tag = "admin",
+    request_body = RollbackConfigRequest,
+    responses(
+        (status = 200, description = "The new live version", body = ConfigVersion),
+        (status = 404, description = "No such version", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn rollback_config(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Json(req): Json<RollbackConfigRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let published = rollback(
+        state.db.as_ref(),
+        Some(admin_id),
+        req.version,
+        req.note.trim(),
+    )
+    .await?
+    .ok_or_else(|| {
+        ApiError::not_found(
+            "config_version_not_found",
+            format!("No config version {}", req.version),
+        )
+    })?;
+    tracing::info!(%admin_id, version = published.version, to = req.version, "config rolled back");
+
+    Ok(Json(published))
+}
+
+/// Republish `version`'s document, noting where it came from. `None` if
+/// there's no such version. Shared with the management CLI.
+pub async fn rollback(
+    db: &dyn Repository,
+    admin_id: Option<Uuid>,
+    version: i64,
+    note: &str,
+) -> Result<Option<ConfigVersion>, sqlx::Error> {
+    let Some(old) = db.get_config_version(Some(version)).await? else {
+        return Ok(None);
+    };
+    let note = match note {
+        "" => format!("Rollback to version {version}"),
+        note => format!("Rollback to version {version}: {note}"),
+    };
+    db.publish_config(admin_id, &old.document, &note, Some(version))
+        .await
+        .map(Some)
+}
+
+/// GET /api/admin/experiments — Every experiment, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/admin/experiments",
+    tag = "admin",
+    responses(
+        (status = 200, body = Vec<Experiment>),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn list_experiments(
+    _admin: AdminPlayer,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(state.db.list_experiments(false).await?))
+}
+
+/// POST /api/admin/experiments — Start an experiment. Its variants can't be changed afterwards.
+#[utoipa::path(
+    post,
+    path = "/api/admin/experiments",
+    tag = "admin",
+    request_body = CreateExperimentRequest,
+    responses(
+        (status = 200, body = Experiment),
+        (status = 409, description = "An experiment with this key exists", body = ErrorBody),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn create_experiment(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Json(req): Json<CreateExperimentRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut errors = FieldErrors::default();
+    experiments::check_experiment(&mut errors, &req);
+    errors.into_result()?;
+
+    let experiment = state
+        .db
+        .create_experiment(
+            Some(admin_id),
+            &req.key,
+            req.description.trim(),
+            &req.variants,
+        )
+        .await?;
+    tracing::info!(%admin_id, key = %req.key, "experiment started");
+
+    Ok(Json(experiment))
+}
+
+/// POST /api/admin/experiments/{key}/end — Stop assigning players; the report stays available.
+#[utoipa::path(
+    post,
+    path = "/api/admin/experiments/{key}/end",
+    tag = "admin",
+    params(("key" = String, Path, description = "Experiment key")),
+    responses(
+        (status = 200, body = Experiment),
+        (status = 404, description = "No such experiment", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn end_experiment(
+    AdminPlayer(admin_id): AdminPlayer,
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let experiment = state
+        .db
+        .end_experiment(Some(admin_id), &key)
+        .await?
+        .ok_or_else(experiments::experiment_not_found)?;
+    tracing::info!(%admin_id, key, "experiment ended");
+
+    Ok(Json(experiment))
+}
+
+/// GET /api/admin/experiments/{key}/report — Exposed players' score components per variant, without banned or hidden players.
+#[utoipa::path(
+    get,
+    path = "/api/admin/experiments/{key}/report",
+    tag = "admin",
+    params(("key" = String, Path, description = "Experiment key")),
+    responses(
+        (status = 200, body = ExperimentReport),
+        (status = 404, description = "No such experiment", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+async fn experiment_report(
+    _admin: AdminPlayer,
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let report = experiments::report(state.db.as_ref(), &key)
+        .await?
+        .ok_or_else(experiments::experiment_not_found)?;
+
+    Ok(Json(report))
+}
+
+fn reason_details(req: AdminActionRequest) -> serde_json::Value {
+    json!({ "reason": req.reason })
+}
+
+fn player_not_found() -> ApiError {
+    ApiError::not_found("player_not_found", "No player with that id")
+}
+
+fn found_or_404(found: bool) -> Result<StatusCode, ApiError> {
+    if found {
+        Ok(StatusCode::OK)
+    } else {
+        Err(player_not_found())
+    }
+}
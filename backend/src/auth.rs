@@ -1,97 +1,221 @@
 use axum::{
-    extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::request::Parts,
 };
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, decode_header, encode};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use uuid::Uuid;
 
-use crate::AppState;
+use crate::{
+    error::ApiError,
+    keys::JwtKeys,
+    models::{BanKind, Role},
+    AppState,
+};
+
+/// What a token may be used for. Tokens issued before this field existed
+/// carry no `kind` and are treated as session tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    #[default]
+    Session,
+    TwoFactorChallenge,
+}
+
+impl TokenKind {
+    fn is_session(&self) -> bool {
+        *self == TokenKind::Session
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: Uuid,
     pub exp: usize,
+    #[serde(default, skip_serializing_if = "TokenKind::is_session")]
+    pub kind: TokenKind,
+    /// Names the stored challenge a challenge token redeems.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<Uuid>,
 }
 
-pub fn create_token(player_id: Uuid, secret: &str) -> Result<String, jsonwebtoken::errors::Error> {
+fn sign(
+    player_id: Uuid,
+    lifetime: chrono::TimeDelta,
+    kind: TokenKind,
+    jti: Option<Uuid>,
+    keys: &JwtKeys,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let expiry = chrono::Utc::now()
-        .checked_add_signed(chrono::TimeDelta::days(365))
+        .checked_add_signed(lifetime)
         .unwrap()
         .timestamp() as usize;
     let claims = Claims {
         sub: player_id,
         exp: expiry,
+        kind,
+        jti,
     };
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
+    let (header, key) = keys.signing();
+    encode(header, &claims, key)
 }
 
-pub fn verify_token(token: &str, secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    )?;
+fn verify(
+    token: &str,
+    kind: TokenKind,
+    keys: &JwtKeys,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let header = decode_header(token)?;
+    let (key, validation) = keys
+        .verification(&header)
+        .ok_or(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat)?;
+    let token_data = decode::<Claims>(token, key, &validation)?;
+    if token_data.claims.kind != kind {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
     Ok(token_data.claims)
 }
 
+pub fn create_token(
+    player_id: Uuid,
+    keys: &JwtKeys,
+    lifetime: chrono::TimeDelta,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    sign(player_id, lifetime, TokenKind::Session, None, keys)
+}
+
+pub fn verify_token(token: &str, keys: &JwtKeys) -> Result<Claims, jsonwebtoken::errors::Error> {
+    verify(token, TokenKind::Session, keys)
+}
+
+/// Short-lived token proving the password step of a two-factor login
+/// succeeded. `challenge_id` is the stored challenge it can redeem once.
+pub fn create_challenge_token(
+    player_id: Uuid,
+    challenge_id: Uuid,
+    keys: &JwtKeys,
+    lifetime: chrono::TimeDelta,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    sign(
+        player_id,
+        lifetime,
+        TokenKind::TwoFactorChallenge,
+        Some(challenge_id),
+        keys,
+    )
+}
+
+pub fn verify_challenge_token(
+    token: &str,
+    keys: &JwtKeys,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    verify(token, TokenKind::TwoFactorChallenge, keys)
+}
+
+/// Pull the token out of an `Authorization: Bearer ...` header, if there is one.
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Rejects a hard-banned player with 403, saying why and until when.
+pub(crate) async fn reject_banned(state: &AppState, player_id: Uuid) -> Result<(), ApiError> {
+    let ban = state.db.get_active_ban(player_id).await?;
+    match ban.filter(|b| b.kind == BanKind::Hard) {
+        Some(ban) => Err(
+            ApiError::forbidden("account_banned", "This account has been banned").with_details(
+                json!({
+                    "reason": ban.reason,
+                    "expires_at": ban.expires_at,
+                }),
+            ),
+        ),
+        None => Ok(()),
+    }
+}
+
 /// Extractor that validates Bearer token and returns player UUID.
+/// Hard-banned players are rejected with 403; shadow bans are invisible here.
 pub struct AuthPlayer(pub Uuid);
 
 impl FromRequestParts<AppState> for AuthPlayer {
-    type Rejection = StatusCode;
+    type Rejection = ApiError;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let auth_header = parts
-            .headers
-            .get("authorization")
-            .and_then(|v| v.to_str().ok())
-            .ok_or(StatusCode::UNAUTHORIZED)?;
+        let token = bearer_token(parts).ok_or_else(ApiError::missing_auth)?;
+        let claims = verify_token(token, &state.jwt).map_err(|_| ApiError::missing_auth())?;
 
-        let token = auth_header
-            .strip_prefix("Bearer ")
-            .ok_or(StatusCode::UNAUTHORIZED)?;
-
-        let claims =
-            verify_token(token, &state.jwt_secret).map_err(|_| StatusCode::UNAUTHORIZED)?;
+        reject_banned(state, claims.sub).await?;
 
         Ok(AuthPlayer(claims.sub))
     }
 }
 
-/// Optional auth extractor — returns None if no auth header present.
+/// `Option<AuthPlayer>`: `None` without a token, but a token that is sent
+/// must be valid and its player not hard-banned.
+impl OptionalFromRequestParts<AppState> for AuthPlayer {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        if bearer_token(parts).is_none() {
+            return Ok(None);
+        }
+        <AuthPlayer as FromRequestParts<AppState>>::from_request_parts(parts, state)
+            .await
+            .map(Some)
+    }
+}
+
+/// Extractor for admin routes: a valid Bearer token whose player currently
+/// has the admin role. The role is read from the database on every request
+/// so demoting an admin takes effect immediately.
+pub struct AdminPlayer(pub Uuid);
+
+impl FromRequestParts<AppState> for AdminPlayer {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthPlayer(player_id) =
+            <AuthPlayer as FromRequestParts<AppState>>::from_request_parts(parts, state).await?;
+
+        match state.db.get_player_role(player_id).await? {
+            Some(Role::Admin) => Ok(AdminPlayer(player_id)),
+            _ => Err(ApiError::forbidden(
+                "admin_required",
+                "This endpoint requires the admin role",
+            )),
+        }
+    }
+}
+
+/// Optional auth extractor — returns None if no valid auth header is present.
 pub struct OptionalAuthPlayer(pub Option<Uuid>);
 
 impl FromRequestParts<AppState> for OptionalAuthPlayer {
-    type Rejection = StatusCode;
+    type Rejection = ApiError;
 
     async fn from_request_parts(
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let auth_header = parts
-            .headers
-            .get("authorization")
-            .and_then(|v| v.to_str().ok());
-
-        let Some(auth_header) = auth_header else {
-            return Ok(OptionalAuthPlayer(None));
-        };
-
-        let Some(token) = auth_header.strip_prefix("Bearer ") else {
-            return Ok(OptionalAuthPlayer(None));
-        };
-
-        match verify_token(token, &state.jwt_secret) {
-            Ok(claims) => Ok(OptionalAuthPlayer(Some(claims.sub))),
-            Err(_) => Ok(OptionalAuthPlayer(None)),
-        }
+        let player_id = bearer_token(parts)
+            .and_then(|token| verify_token(token, &state.jwt).ok())
+            .map(|claims| claims.sub);
+
+        Ok(OptionalAuthPlayer(player_id))
     }
 }
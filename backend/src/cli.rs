@@ -0,0 +1,547 @@
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use chrono::{TimeDelta, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    admin,
+    config::Config,
+    error::FieldErrors,
+    handlers,
+    models::{BanKind, ConfigDocument, LeaderboardEntry, Role, ScoreSubmission},
+    repo::{self, Repository},
+};
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+/// Consultancy Tycoon API server. Without a command it serves; the other
+/// commands are for operating it and use the same configuration.
+#[derive(Debug, Parser)]
+#[command(version = concat!(env!("CARGO_PKG_VERSION"), " (", env!("GIT_HASH"), ")"))]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the API server (the default).
+    Serve,
+    /// Apply pending database migrations without serving.
+    Migrate {
+        /// Only list migrations and whether they have been applied.
+        #[arg(long)]
+        status: bool,
+    },
+    /// Create an admin account, or promote an existing account to admin.
+    /// A new account's password is read from stdin.
+    CreateAdmin { username: String },
+    /// Inspect or moderate a player.
+    Player {
+        #[command(subcommand)]
+        command: PlayerCommand,
+    },
+    /// Fill in score components from players' cloud saves. Like score
+    /// submissions, this never lowers a component.
+    RecomputeScores {
+        /// Only this player (id or username).
+        #[arg(long)]
+        player: Option<String>,
+        /// Show what would change without writing.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Delete cloud saves that haven't been written for a while.
+    PruneSaves {
+        #[arg(long)]
+        older_than_days: u32,
+        /// Only count the saves that would be deleted.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Publish or roll back the remote config.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Write the public leaderboard to stdout or a file.
+    ExportLeaderboard {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        #[arg(long, default_value_t = 100)]
+        limit: i64,
+        /// Write here instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Players are given by id or username.
+#[derive(Debug, Subcommand)]
+pub enum PlayerCommand {
+    /// Print the admin view of a player as JSON.
+    Show { player: String },
+    /// Ban a player, replacing any existing ban.
+    Ban {
+        player: String,
+        #[arg(long)]
+        reason: String,
+        /// Hide them from everyone else's leaderboard instead of locking them out.
+        #[arg(long)]
+        shadow: bool,
+        /// Lift the ban after this many days; permanent if omitted.
+        #[arg(long)]
+        days: Option<u32>,
+    },
+    /// Lift a player's ban.
+    Unban {
+        player: String,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Zero a player's score components. The old values go in the audit log.
+    Reset {
+        player: String,
+        #[arg(long)]
+        reason: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the live config document, or an earlier version's, as JSON.
+    Show { version: Option<i64> },
+    /// Publish a JSON config document (`values` and `overrides`) as the
+    /// new live version.
+    Publish {
+        file: PathBuf,
+        #[arg(long, default_value = "")]
+        note: String,
+    },
+    /// Publish an earlier version's document again.
+    Rollback {
+        version: i64,
+        #[arg(long, default_value = "")]
+        note: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Run a management command.
+pub async fn run(command: Command, config: &Config) -> CliResult {
+    let db = repo::connect(config).await?;
+    let db = db.as_ref();
+
+    if let Command::Migrate { status } = command {
+        return migrate(db, status).await;
+    }
+    let pending = db.pending_migrations().await?;
+    if !pending.is_empty() {
+        return Err(format!("migrations {pending:?} are pending; run `migrate` first").into());
+    }
+
+    match command {
+        Command::Serve | Command::Migrate { .. } => unreachable!("handled by the caller"),
+        Command::CreateAdmin { username } => create_admin(db, &username).await,
+        Command::Player { command } => player(db, command).await,
+        Command::RecomputeScores { player, dry_run } => {
+            recompute_scores(db, player.as_deref(), dry_run).await
+        }
+        Command::PruneSaves {
+            older_than_days,
+            dry_run,
+        } => prune_saves(db, older_than_days, dry_run).await,
+        Command::Config { command } => remote_config(db, command).await,
+        Command::ExportLeaderboard {
+            format,
+            limit,
+            output,
+        } => export_leaderboard(db, format, limit, output).await,
+    }
+}
+
+async fn migrate(db: &dyn Repository, status: bool) -> CliResult {
+    let pending = db.pending_migrations().await.unwrap_or_else(|_| {
+        // No _sqlx_migrations table yet, so nothing is applied
+        db.migrator().iter().map(|m| m.version).collect()
+    });
+
+    if status {
+        for migration in db.migrator().iter() {
+            let state = if pending.contains(&migration.version) {
+                "pending"
+            } else {
+                "applied"
+            };
+            println!(
+                "{:03} {:<8} {}",
+                migration.version, state, migration.description
+            );
+        }
+        return Ok(());
+    }
+
+    db.run_migrations().await?;
+    println!("Applied {} migration(s)", pending.len());
+    Ok(())
+}
+
+async fn create_admin(db: &dyn Repository, username: &str) -> CliResult {
+    if let Some(player) = db.find_player_by_username(username).await? {
+        if player.role == Role::Admin {
+            println!("{username} ({}) is already an admin", player.id);
+        } else {
+            db.set_role(None, player.id, Role::Admin).await?;
+            println!("Promoted {username} ({}) to admin", player.id);
+        }
+        return Ok(());
+    }
+
+    eprintln!("New password for {username}:");
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+
+    let mut errors = FieldErrors::default();
+    handlers::check_credentials(&mut errors, username, password);
+    if !errors.is_empty() {
+        return Err(errors.into());
+    }
+
+    let password_hash = handlers::hash_password(password).map_err(|e| e.to_string())?;
+    let id = Uuid::new_v4();
+    db.create_player(id, username, &handlers::generate_passphrase())
+        .await?;
+    db.set_credentials(id, username, &password_hash).await?;
+    db.set_role(None, id, Role::Admin).await?;
+    println!("Created admin {username} ({id})");
+    Ok(())
+}
+
+/// A player id, or else a username.
+async fn resolve_player(db: &dyn Repository, player: &str) -> Result<Uuid, Box<dyn Error>> {
+    if let Ok(id) = Uuid::parse_str(player) {
+        return Ok(id);
+    }
+    match db.find_player_by_username(player).await? {
+        Some(found) => Ok(found.id),
+        None => Err(format!("no player with id or username {player:?}").into()),
+    }
+}
+
+fn found(found: bool, player: Uuid) -> CliResult {
+    if found {
+        Ok(())
+    } else {
+        Err(format!("no player with id {player}").into())
+    }
+}
+
+async fn player(db: &dyn Repository, command: PlayerCommand) -> CliResult {
+    match command {
+        PlayerCommand::Show { player } => {
+            let id = resolve_player(db, &player).await?;
+            let detail = admin::player_detail(db, id)
+                .await?
+                .ok_or_else(|| format!("no player with id {id}"))?;
+            println!("{}", serde_json::to_string_pretty(&detail)?);
+        }
+        PlayerCommand::Ban {
+            player,
+            reason,
+            shadow,
+            days,
+        } => {
+            let id = resolve_player(db, &player).await?;
+            if reason.trim().is_empty() {
+                return Err("--reason must not be empty".into());
+            }
+            let kind = if shadow {
+                BanKind::Shadow
+            } else {
+                BanKind::Hard
+            };
+            let expires_at = days.map(|d| Utc::now() + TimeDelta::days(d.into()));
+            found(
+                db.ban_player(None, id, kind, reason.trim(), expires_at)
+                    .await?,
+                id,
+            )?;
+            match expires_at {
+                Some(t) => println!("Banned {id} until {t}"),
+                None => println!("Banned {id}"),
+            }
+        }
+        PlayerCommand::Unban { player, reason } => {
+            let id = resolve_player(db, &player).await?;
+            let details = json!({ "reason": reason });
+            found(db.unban_player(None, id, details).await?, id)?;
+            println!("Unbanned {id}");
+        }
+        PlayerCommand::Reset { player, reason } => {
+            let id = resolve_player(db, &player).await?;
+            let previous = db.get_score_components(id).await?;
+            let details = json!({ "reason": reason, "previous": previous });
+            found(db.reset_scores(None, id, details).await?, id)?;
+            println!("Reset scores of {id}");
+        }
+    }
+    Ok(())
+}
+
+async fn recompute_scores(db: &dyn Repository, player: Option<&str>, dry_run: bool) -> CliResult {
+    let players = match player {
+        Some(player) => vec![resolve_player(db, player).await?],
+        None => db.players_with_saves().await?,
+    };
+
+    let mut updated = 0;
+    for id in players {
+        let Some(save) = db.get_save(id).await? else {
+            eprintln!("{id}: no cloud save");
+            continue;
+        };
+        let Some(scores) = scores_from_save(&save.save_data) else {
+            eprintln!("{id}: save has no game_state, skipped");
+            continue;
+        };
+        if dry_run {
+            println!("{id}: {}", serde_json::to_string(&scores)?);
+        } else {
+            db.upsert_scores(id, &scores).await?;
+        }
+        updated += 1;
+    }
+
+    let verb = if dry_run { "Would update" } else { "Updated" };
+    println!("{verb} scores of {updated} player(s)");
+    Ok(())
+}
+
+/// The score components the game would submit for this save; see
+/// `GameState.get_score_components` in the Godot project.
+fn scores_from_save(save: &Value) -> Option<ScoreSubmission> {
+    let state = save.get("game_state")?;
+    let float = |key: &str| state.get(key).and_then(Value::as_f64).unwrap_or(0.0);
+    let sum = |value: Option<&Value>| -> i32 {
+        value.and_then(Value::as_object).map_or(0, |map| {
+            map.values().filter_map(Value::as_f64).sum::<f64>() as i32
+        })
+    };
+    let consultants = save
+        .get("consultants")
+        .and_then(Value::as_array)
+        .map_or(0, Vec::len);
+
+    Some(ScoreSubmission {
+        total_money_earned: float("total_money_earned"),
+        reputation: float("reputation"),
+        skill_levels_sum: sum(state.get("skills")),
+        consultants_count: consultants as i32,
+        ai_tool_tiers_sum: sum(state.get("ai_tools")),
+        manual_tasks_completed: float("total_manual_tasks_completed") as i32,
+    })
+}
+
+async fn prune_saves(db: &dyn Repository, older_than_days: u32, dry_run: bool) -> CliResult {
+    let cutoff = Utc::now() - TimeDelta::days(older_than_days.into());
+
+    if dry_run {
+        let count = db.count_saves_before(cutoff).await?;
+        println!("Would delete {count} save(s) last written before {cutoff}");
+    } else {
+        let count = db.delete_saves_before(cutoff).await?;
+        println!("Deleted {count} save(s) last written before {cutoff}");
+    }
+    Ok(())
+}
+
+async fn remote_config(db: &dyn Repository, command: ConfigCommand) -> CliResult {
+    match command {
+        ConfigCommand::Show { version } => {
+            let found = db
+                .get_config_version(version)
+                .await?
+                .ok_or("no such config version")?;
+            println!("{}", serde_json::to_string_pretty(&found.document)?);
+        }
+        ConfigCommand::Publish { file, note } => {
+            let document: ConfigDocument = serde_json::from_slice(&std::fs::read(&file)?)?;
+            let mut errors = FieldErrors::default();
+            crate::remote_config::check_document(&mut errors, &document);
+            if !errors.is_empty() {
+                return Err(errors.into());
+            }
+            let published = db
+                .publish_config(None, &document, note.trim(), None)
+                .await?;
+            println!("Published config version {}", published.version);
+        }
+        ConfigCommand::Rollback { version, note } => {
+            let published = admin::rollback(db, None, version, note.trim())
+                .await?
+                .ok_or_else(|| format!("no config version {version}"))?;
+            println!(
+                "Published version {}'s config as version {}",
+                version, published.version
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn export_leaderboard(
+    db: &dyn Repository,
+    format: ExportFormat,
+    limit: i64,
+    output: Option<PathBuf>,
+) -> CliResult {
+    let entries = db.get_leaderboard(limit.max(1), None).await?;
+
+    let mut out: Box<dyn Write> = match &output {
+        Some(path) => Box::new(io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, &entries)?;
+            writeln!(out)?;
+        }
+        ExportFormat::Csv => write_csv(&mut out, &entries)?,
+    }
+    out.flush()?;
+
+    if let Some(path) = output {
+        eprintln!("Wrote {} entries to {}", entries.len(), path.display());
+    }
+    Ok(())
+}
+
+fn write_csv(out: &mut dyn Write, entries: &[LeaderboardEntry]) -> io::Result<()> {
+    writeln!(
+        out,
+        "rank,display_name,score,total_money_earned,reputation,skill_levels_sum,\
+         consultants_count,ai_tool_tiers_sum,manual_tasks_completed"
+    )?;
+    for e in entries {
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{},{}",
+            e.rank,
+            csv_field(&e.display_name),
+            e.score,
+            e.total_money_earned,
+            e.reputation,
+            e.skill_levels_sum,
+            e.consultants_count,
+            e.ai_tool_tiers_sum,
+            e.manual_tasks_completed
+        )?;
+    }
+    Ok(())
+}
+
+/// Quote a field if it needs it. Display names are player-chosen, so a
+/// leading `=`, `+`, `-` or `@` is also neutralised before the file reaches
+/// a spreadsheet.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_from_save_matches_the_game() {
+        let save = json!({
+            "version": 3,
+            "game_state": {
+                "total_money_earned": 350000.0,
+                "reputation": 42.5,
+                "skills": { "python": 3, "sql": 2 },
+                "ai_tools": { "copilot": 1, "agent": 2 },
+                "total_manual_tasks_completed": 75,
+            },
+            "consultants": [{}, {}, {}],
+        });
+
+        let scores = scores_from_save(&save).unwrap();
+        assert_eq!(scores.total_money_earned, 350000.0);
+        assert_eq!(scores.reputation, 42.5);
+        assert_eq!(scores.skill_levels_sum, 5);
+        assert_eq!(scores.consultants_count, 3);
+        assert_eq!(scores.ai_tool_tiers_sum, 3);
+        assert_eq!(scores.manual_tasks_completed, 75);
+
+        assert!(scores_from_save(&json!({ "version": 3 })).is_none());
+    }
+
+    #[tokio::test]
+    async fn config_changes_are_audited_without_an_admin() {
+        let db = crate::repo::memory::MemoryRepository::new();
+        let file = std::env::temp_dir().join(format!("config-{}.json", Uuid::new_v4()));
+        std::fs::write(&file, r#"{ "values": { "new_events": true } }"#).unwrap();
+        let publish = ConfigCommand::Publish {
+            file: file.clone(),
+            note: " launch ".into(),
+        };
+        let published = remote_config(&db, publish).await;
+        std::fs::remove_file(&file).unwrap();
+        published.unwrap();
+        let rollback = ConfigCommand::Rollback {
+            version: 1,
+            note: String::new(),
+        };
+        remote_config(&db, rollback).await.unwrap();
+
+        let audit = db.get_audit_log(None, 10).await.unwrap();
+        let entries: Vec<_> = audit
+            .iter()
+            .map(|e| (e.admin_id, e.action.as_str(), &e.details))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    None,
+                    "rollback_config",
+                    &json!({ "version": 2, "note": "Rollback to version 1", "rollback_of": 1 })
+                ),
+                (
+                    None,
+                    "publish_config",
+                    &json!({ "version": 1, "note": "launch" })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_and_defused() {
+        assert_eq!(csv_field("Ada"), "Ada");
+        assert_eq!(csv_field("Smith, J"), "\"Smith, J\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("=HYPERLINK(1)"), "'=HYPERLINK(1)");
+    }
+}
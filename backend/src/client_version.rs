@@ -0,0 +1,129 @@
+//! Turning away game builds too old for the current API. The game sends its
+//! version (`application/config/version` in `project.godot`) in
+//! `X-Client-Version` on every request, and that header is all that's
+//! trusted when present. Native builds from before it existed are told apart
+//! from other clients by Godot's default `User-Agent`. Web builds that old
+//! can't be: the browser sends its own `User-Agent`, so they look like any
+//! other client and are let through.
+
+use std::{fmt, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+use crate::error::ApiError;
+
+pub const CLIENT_VERSION_HEADER: HeaderName = HeaderName::from_static("x-client-version");
+
+/// `major.minor.patch`, with missing parts read as 0 and anything after a
+/// `-` or `+` (pre-release or build tags) ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClientVersion(u32, u32, u32);
+
+impl FromStr for ClientVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let core = s.trim().split(['-', '+']).next().unwrap_or_default();
+        let mut parts = [0; 3];
+        for (i, part) in core.split('.').enumerate() {
+            let slot = parts
+                .get_mut(i)
+                .ok_or_else(|| format!("{s:?} has more than three parts"))?;
+            *slot = part
+                .parse()
+                .map_err(|_| format!("{s:?} is not a version like 1.4.0"))?;
+        }
+        Ok(ClientVersion(parts[0], parts[1], parts[2]))
+    }
+}
+
+impl fmt::Display for ClientVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// The version a request claims to come from. `None` for clients that aren't
+/// the game, or say nothing we can read; they're let through.
+pub fn requesting_version(headers: &HeaderMap) -> Option<ClientVersion> {
+    if let Some(version) = headers.get(CLIENT_VERSION_HEADER) {
+        return version.to_str().ok()?.parse().ok();
+    }
+    let user_agent = headers.get(header::USER_AGENT)?.to_str().ok()?;
+    user_agent
+        .starts_with("GodotEngine/")
+        .then_some(ClientVersion(0, 0, 0))
+}
+
+/// Middleware answering 426 Upgrade Required to game builds older than `min`.
+pub async fn require(State(min): State<Arc<ClientVersion>>, req: Request, next: Next) -> Response {
+    match requesting_version(req.headers()) {
+        Some(version) if version < *min => ApiError::new(
+            StatusCode::UPGRADE_REQUIRED,
+            "client_outdated",
+            "This version of the game is no longer supported; please update",
+        )
+        .with_details(json!({ "min_client_version": min.to_string() }))
+        .into_response(),
+        _ => next.run(req).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn parses_and_orders_versions() {
+        let v = |s: &str| s.parse::<ClientVersion>().unwrap();
+        assert_eq!(v("1.4.0"), ClientVersion(1, 4, 0));
+        assert_eq!(v("2"), ClientVersion(2, 0, 0));
+        assert_eq!(v(" 1.4.2-beta.1 "), ClientVersion(1, 4, 2));
+        assert_eq!(v("1.4+web"), ClientVersion(1, 4, 0));
+        assert!(v("1.10.0") > v("1.9.9"));
+        assert!(v("0.9") < v("1.0.0"));
+
+        for bad in ["", "1.x", "1.2.3.4", "v1.2"] {
+            assert!(bad.parse::<ClientVersion>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn old_web_builds_are_let_through() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::from_static("Mozilla/5.0 (X11; Linux x86_64) Firefox/131.0"),
+        );
+        assert_eq!(requesting_version(&headers), None);
+
+        headers.insert(CLIENT_VERSION_HEADER, HeaderValue::from_static("0.9.0"));
+        assert_eq!(requesting_version(&headers), Some(ClientVersion(0, 9, 0)));
+    }
+
+    #[test]
+    fn godot_without_a_version_is_the_oldest_client() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(requesting_version(&headers), None);
+
+        headers.insert(header::USER_AGENT, HeaderValue::from_static("curl/8.5.0"));
+        assert_eq!(requesting_version(&headers), None);
+
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::from_static("GodotEngine/4.6.stable.official (Linux)"),
+        );
+        assert_eq!(requesting_version(&headers), Some(ClientVersion(0, 0, 0)));
+
+        headers.insert(CLIENT_VERSION_HEADER, HeaderValue::from_static("1.2.0"));
+        assert_eq!(requesting_version(&headers), Some(ClientVersion(1, 2, 0)));
+    }
+}
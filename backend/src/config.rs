@@ -0,0 +1,882 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use axum::http::{HeaderName, Method};
+use serde::Deserialize;
+
+use crate::client_version::ClientVersion;
+
+/// Shortest JWT_SECRET we accept. 32 bytes of hex from `openssl rand -hex 32` is 64.
+const MIN_JWT_SECRET_LEN: usize = 32;
+
+/// Server configuration.
+///
+/// Built from defaults, then the TOML file named by `CONFIG_FILE` (if set),
+/// then environment variables, each layer overriding the previous one. See
+/// `.env.example` for the variable names and `config.example.toml` for the
+/// file layout.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Picks defaults that differ between a developer machine and the server.
+    pub environment: Environment,
+    pub database_url: String,
+    pub listen_addr: SocketAddr,
+    /// Listen on this Unix socket instead of `listen_addr`, for a reverse
+    /// proxy on the same host.
+    pub listen_socket: Option<PathBuf>,
+    /// Serve `/metrics` here instead of on `listen_addr`.
+    pub metrics_addr: Option<SocketAddr>,
+    /// After SIGTERM, how long in-flight requests get to finish.
+    pub drain_timeout_secs: u64,
+    /// Serve Swagger UI at `/api/docs`. Unset means on in development only.
+    pub swagger_ui: Option<bool>,
+    /// Game builds reporting an older `X-Client-Version` get 426 Upgrade
+    /// Required. Unset lets every version through. Web builds from before
+    /// the header can't be recognized and are always let through.
+    pub min_client_version: Option<String>,
+    /// Serve the content catalog in this TOML file instead of the built-in
+    /// one. Reread on SIGHUP.
+    pub content_path: Option<PathBuf>,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub cors: CorsConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub body_limits: BodyLimitConfig,
+    pub saves: SaveConfig,
+    pub idempotency: IdempotencyConfig,
+    pub leaderboard: LeaderboardConfig,
+    pub notifications: NotificationConfig,
+}
+
+/// Terminate TLS ourselves rather than behind a proxy. Both paths or neither.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first. Reread on SIGHUP.
+    pub cert_path: Option<PathBuf>,
+    /// PEM private key for the leaf certificate. Reread on SIGHUP.
+    pub key_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    /// How long a request waits for a free connection before failing.
+    pub acquire_timeout_secs: u64,
+    /// Idle connections above `min_connections` are closed after this long.
+    pub idle_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Development,
+    #[default]
+    Production,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API from a browser, e.g.
+    /// `https://tycoon.jpro.dev`, or `*` for any. Unset means the default
+    /// for the environment.
+    pub allowed_origins: Option<Vec<String>>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers a browser may send cross-origin.
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache a preflight response.
+    pub max_age_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// HS256 secret. Optional once `jwt_keys_dir` is set, but keeps tokens
+    /// issued with it valid while it is.
+    pub jwt_secret: Option<String>,
+    /// Directory of `<kid>.pub.pem` / `<kid>.pem` key pairs.
+    pub jwt_keys_dir: Option<PathBuf>,
+    pub jwt_signing_kid: Option<String>,
+    pub session_lifetime_days: i64,
+    pub challenge_lifetime_secs: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Sustained requests per minute per client IP on the login, recovery
+    /// and player creation endpoints. 0 disables rate limiting.
+    pub auth_per_minute: u32,
+    /// Requests a client may make in a quick burst before the rate applies.
+    pub auth_burst: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BodyLimitConfig {
+    /// Largest request body on the login, registration and 2FA endpoints.
+    pub auth_bytes: usize,
+    /// Largest request body on routes without a specific limit.
+    pub default_bytes: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SaveConfig {
+    /// Largest accepted cloud save upload, in bytes after decompression.
+    pub max_bytes: usize,
+    /// Saves with more JSON than this are stored compressed.
+    pub compress_above_bytes: usize,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdempotencyConfig {
+    /// How long a response to a request with an `Idempotency-Key` is kept
+    /// for retries to replay. 0 ignores the header.
+    pub ttl_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeaderboardConfig {
+    /// Shortest gap between two updates on `/api/leaderboard/stream`; score
+    /// changes in between are sent together.
+    pub stream_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Notifications are deleted this long after they were sent, read or
+    /// not. 0 keeps them forever.
+    pub retention_days: i64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            environment: Environment::default(),
+            database_url: String::new(),
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 3080)),
+            listen_socket: None,
+            metrics_addr: None,
+            drain_timeout_secs: 30,
+            swagger_ui: None,
+            min_client_version: None,
+            content_path: None,
+            tls: TlsConfig::default(),
+            database: DatabaseConfig::default(),
+            cors: CorsConfig::default(),
+            auth: AuthConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            body_limits: BodyLimitConfig::default(),
+            saves: SaveConfig::default(),
+            idempotency: IdempotencyConfig::default(),
+            leaderboard: LeaderboardConfig::default(),
+            notifications: NotificationConfig::default(),
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: None,
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            allowed_headers: [
+                "authorization",
+                "content-type",
+                "idempotency-key",
+                "if-none-match",
+                "x-client-version",
+                "x-request-id",
+            ]
+            .map(String::from)
+            .to_vec(),
+            max_age_secs: 3600,
+        }
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout_secs: 30,
+            idle_timeout_secs: 600,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_secret: None,
+            jwt_keys_dir: None,
+            jwt_signing_kid: None,
+            session_lifetime_days: 365,
+            challenge_lifetime_secs: 300,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            auth_per_minute: 20,
+            auth_burst: 10,
+        }
+    }
+}
+
+impl Default for BodyLimitConfig {
+    fn default() -> Self {
+        BodyLimitConfig {
+            auth_bytes: 16 * 1024,
+            default_bytes: 64 * 1024,
+        }
+    }
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        SaveConfig {
+            max_bytes: 2 * 1024 * 1024,
+            compress_above_bytes: 16 * 1024,
+        }
+    }
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        IdempotencyConfig {
+            ttl_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl Default for LeaderboardConfig {
+    fn default() -> Self {
+        LeaderboardConfig {
+            stream_interval_ms: 1000,
+        }
+    }
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig { retention_days: 90 }
+    }
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "development" => Ok(Environment::Development),
+            "production" => Ok(Environment::Production),
+            _ => Err("expected development or production".to_string()),
+        }
+    }
+}
+
+impl Environment {
+    /// Where the web build is served from: `make serve` locally, the
+    /// deployed site otherwise.
+    fn default_cors_origins(self) -> Vec<String> {
+        let origins: &[&str] = match self {
+            Environment::Development => &["http://localhost:8060", "http://127.0.0.1:8060"],
+            Environment::Production => &["https://tycoon.jpro.dev"],
+        };
+        origins.iter().map(|o| o.to_string()).collect()
+    }
+}
+
+impl CorsConfig {
+    /// The configured origins; filled in from the environment's defaults on load.
+    pub fn origins(&self) -> &[String] {
+        self.allowed_origins.as_deref().unwrap_or_default()
+    }
+}
+
+impl Config {
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_timeout_secs)
+    }
+
+    pub fn swagger_ui_enabled(&self) -> bool {
+        self.swagger_ui
+            .unwrap_or(self.environment == Environment::Development)
+    }
+
+    pub fn min_client_version(&self) -> Option<ClientVersion> {
+        self.min_client_version.as_deref()?.parse().ok()
+    }
+}
+
+impl TlsConfig {
+    /// Certificate and key paths when TLS is on.
+    pub fn paths(&self) -> Option<(&Path, &Path)> {
+        Some((self.cert_path.as_deref()?, self.key_path.as_deref()?))
+    }
+}
+
+impl DatabaseConfig {
+    pub fn acquire_timeout(&self) -> Duration {
+        Duration::from_secs(self.acquire_timeout_secs)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+}
+
+impl IdempotencyConfig {
+    pub fn ttl(&self) -> chrono::TimeDelta {
+        chrono::TimeDelta::seconds(self.ttl_secs as i64)
+    }
+}
+
+impl LeaderboardConfig {
+    pub fn stream_interval(&self) -> Duration {
+        Duration::from_millis(self.stream_interval_ms)
+    }
+}
+
+impl NotificationConfig {
+    pub fn retention(&self) -> chrono::TimeDelta {
+        chrono::TimeDelta::days(self.retention_days)
+    }
+}
+
+impl AuthConfig {
+    pub fn session_lifetime(&self) -> chrono::TimeDelta {
+        chrono::TimeDelta::days(self.session_lifetime_days)
+    }
+
+    pub fn challenge_lifetime(&self) -> chrono::TimeDelta {
+        chrono::TimeDelta::seconds(self.challenge_lifetime_secs)
+    }
+}
+
+/// Everything wrong with the configuration, reported together so a broken
+/// deploy doesn't take one restart per mistake.
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load from `CONFIG_FILE` and the process environment.
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Some(
+                std::fs::read_to_string(&path)
+                    .map_err(|e| ConfigError(vec![format!("CONFIG_FILE {path}: {e}")]))?,
+            ),
+            Err(_) => None,
+        };
+        Self::from_sources(file.as_deref(), |name| std::env::var(name).ok())
+    }
+
+    /// Layer `toml` (if any) and then `env` over the defaults, and validate.
+    pub fn from_sources(
+        toml: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut config = match toml {
+            Some(text) => toml::from_str(text)
+                .map_err(|e| ConfigError(vec![format!("config file: {}", e.message())]))?,
+            None => Config::default(),
+        };
+
+        let mut problems = Vec::new();
+        let mut var = |name: &str, apply: &mut dyn FnMut(String) -> Result<(), String>| {
+            if let Some(value) = env(name)
+                && let Err(e) = apply(value)
+            {
+                problems.push(format!("{name}: {e}"));
+            }
+        };
+
+        var("APP_ENV", &mut |v| parse_into(&v, &mut config.environment));
+        var("DATABASE_URL", &mut |v| {
+            parse_into(&v, &mut config.database_url)
+        });
+        var("LISTEN_ADDR", &mut |v| {
+            parse_into(&v, &mut config.listen_addr)
+        });
+        var("LISTEN_SOCKET", &mut |v| {
+            parse_some(&v, &mut config.listen_socket)
+        });
+        var("METRICS_ADDR", &mut |v| {
+            parse_some(&v, &mut config.metrics_addr)
+        });
+        var("DRAIN_TIMEOUT_SECS", &mut |v| {
+            parse_into(&v, &mut config.drain_timeout_secs)
+        });
+        var("TLS_CERT_PATH", &mut |v| {
+            parse_some(&v, &mut config.tls.cert_path)
+        });
+        var("TLS_KEY_PATH", &mut |v| {
+            parse_some(&v, &mut config.tls.key_path)
+        });
+        var("SWAGGER_UI", &mut |v| {
+            parse_some(&v, &mut config.swagger_ui)
+        });
+        var("MIN_CLIENT_VERSION", &mut |v| {
+            parse_some(&v, &mut config.min_client_version)
+        });
+        var("CONTENT_PATH", &mut |v| {
+            parse_some(&v, &mut config.content_path)
+        });
+        var("DB_MAX_CONNECTIONS", &mut |v| {
+            parse_into(&v, &mut config.database.max_connections)
+        });
+        var("DB_MIN_CONNECTIONS", &mut |v| {
+            parse_into(&v, &mut config.database.min_connections)
+        });
+        var("DB_ACQUIRE_TIMEOUT_SECS", &mut |v| {
+            parse_into(&v, &mut config.database.acquire_timeout_secs)
+        });
+        var("DB_IDLE_TIMEOUT_SECS", &mut |v| {
+            parse_into(&v, &mut config.database.idle_timeout_secs)
+        });
+        var("CORS_ALLOWED_ORIGINS", &mut |v| {
+            config.cors.allowed_origins = Some(split_list(&v));
+            Ok(())
+        });
+        var("CORS_ALLOWED_METHODS", &mut |v| {
+            config.cors.allowed_methods = split_list(&v);
+            Ok(())
+        });
+        var("CORS_ALLOWED_HEADERS", &mut |v| {
+            config.cors.allowed_headers = split_list(&v);
+            Ok(())
+        });
+        var("CORS_MAX_AGE_SECS", &mut |v| {
+            parse_into(&v, &mut config.cors.max_age_secs)
+        });
+        var("JWT_SECRET", &mut |v| {
+            parse_some(&v, &mut config.auth.jwt_secret)
+        });
+        var("JWT_KEYS_DIR", &mut |v| {
+            parse_some(&v, &mut config.auth.jwt_keys_dir)
+        });
+        var("JWT_SIGNING_KID", &mut |v| {
+            parse_some(&v, &mut config.auth.jwt_signing_kid)
+        });
+        var("SESSION_LIFETIME_DAYS", &mut |v| {
+            parse_into(&v, &mut config.auth.session_lifetime_days)
+        });
+        var("CHALLENGE_LIFETIME_SECS", &mut |v| {
+            parse_into(&v, &mut config.auth.challenge_lifetime_secs)
+        });
+        var("RATE_LIMIT_AUTH_PER_MINUTE", &mut |v| {
+            parse_into(&v, &mut config.rate_limit.auth_per_minute)
+        });
+        var("RATE_LIMIT_AUTH_BURST", &mut |v| {
+            parse_into(&v, &mut config.rate_limit.auth_burst)
+        });
+        var("BODY_LIMIT_AUTH_BYTES", &mut |v| {
+            parse_into(&v, &mut config.body_limits.auth_bytes)
+        });
+        var("BODY_LIMIT_DEFAULT_BYTES", &mut |v| {
+            parse_into(&v, &mut config.body_limits.default_bytes)
+        });
+        var("SAVE_MAX_BYTES", &mut |v| {
+            parse_into(&v, &mut config.saves.max_bytes)
+        });
+        var("SAVE_COMPRESS_ABOVE_BYTES", &mut |v| {
+            parse_into(&v, &mut config.saves.compress_above_bytes)
+        });
+        var("IDEMPOTENCY_TTL_SECS", &mut |v| {
+            parse_into(&v, &mut config.idempotency.ttl_secs)
+        });
+        var("LEADERBOARD_STREAM_INTERVAL_MS", &mut |v| {
+            parse_into(&v, &mut config.leaderboard.stream_interval_ms)
+        });
+        var("NOTIFICATION_RETENTION_DAYS", &mut |v| {
+            parse_into(&v, &mut config.notifications.retention_days)
+        });
+
+        if config.cors.allowed_origins.is_none() {
+            config.cors.allowed_origins = Some(config.environment.default_cors_origins());
+        }
+
+        problems.extend(config.problems());
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(problems))
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        check(
+            !self.database_url.is_empty(),
+            "database_url (DATABASE_URL) must be set",
+        );
+        let sqlite = cfg!(feature = "sqlite") && self.database_url.starts_with("sqlite:");
+        check(
+            self.database_url.is_empty()
+                || self.database_url.starts_with("postgres://")
+                || self.database_url.starts_with("postgresql://")
+                || sqlite,
+            if cfg!(feature = "sqlite") {
+                "database_url must be a postgres:// or sqlite: URL"
+            } else {
+                "database_url must be a postgres:// URL (sqlite: needs the sqlite feature)"
+            },
+        );
+
+        check(
+            self.drain_timeout_secs > 0,
+            "drain_timeout_secs must be at least 1",
+        );
+
+        if let Some(version) = &self.min_client_version
+            && let Err(e) = version.parse::<ClientVersion>()
+        {
+            check(false, &format!("min_client_version: {e}"));
+        }
+
+        let tls = &self.tls;
+        check(
+            tls.cert_path.is_some() == tls.key_path.is_some(),
+            "tls.cert_path (TLS_CERT_PATH) and tls.key_path (TLS_KEY_PATH) must be set together",
+        );
+        check(
+            tls.paths().is_none() || self.listen_socket.is_none(),
+            "tls can't be combined with listen_socket; the proxy in front terminates TLS",
+        );
+
+        let db = &self.database;
+        check(
+            db.max_connections > 0,
+            "database.max_connections must be at least 1",
+        );
+        check(
+            db.min_connections <= db.max_connections,
+            "database.min_connections must not exceed max_connections",
+        );
+        check(
+            db.acquire_timeout_secs > 0,
+            "database.acquire_timeout_secs must be at least 1",
+        );
+
+        let cors = &self.cors;
+        for origin in cors.origins() {
+            check(
+                origin == "*" || is_origin(origin),
+                &format!(
+                    "cors.allowed_origins: {origin:?} is not an origin like https://example.com"
+                ),
+            );
+        }
+        check(
+            !cors.origins().iter().any(|o| o == "*") || cors.origins().len() == 1,
+            "cors.allowed_origins: \"*\" can't be combined with other origins",
+        );
+        for method in &cors.allowed_methods {
+            check(
+                Method::from_str(method).is_ok(),
+                &format!("cors.allowed_methods: {method:?} is not an HTTP method"),
+            );
+        }
+        for name in &cors.allowed_headers {
+            check(
+                HeaderName::from_str(name).is_ok(),
+                &format!("cors.allowed_headers: {name:?} is not a header name"),
+            );
+        }
+
+        let auth = &self.auth;
+        match (&auth.jwt_secret, &auth.jwt_keys_dir) {
+            (None, None) => check(false, "auth.jwt_secret (JWT_SECRET) or auth.jwt_keys_dir (JWT_KEYS_DIR) must be set"),
+            (Some(secret), _) if secret.contains("change-me") => check(
+                false,
+                "auth.jwt_secret is still the example value; generate one with `openssl rand -hex 32`",
+            ),
+            (Some(secret), _) if secret.len() < MIN_JWT_SECRET_LEN => check(
+                false,
+                &format!("auth.jwt_secret must be at least {MIN_JWT_SECRET_LEN} characters; generate one with `openssl rand -hex 32`"),
+            ),
+            _ => {}
+        }
+        check(
+            auth.jwt_keys_dir.is_none() || auth.jwt_signing_kid.is_some(),
+            "auth.jwt_signing_kid (JWT_SIGNING_KID) must be set with jwt_keys_dir",
+        );
+        check(
+            auth.session_lifetime_days > 0,
+            "auth.session_lifetime_days must be at least 1",
+        );
+        check(
+            (1..=3600).contains(&auth.challenge_lifetime_secs),
+            "auth.challenge_lifetime_secs must be between 1 and 3600",
+        );
+
+        check(
+            self.rate_limit.auth_per_minute == 0 || self.rate_limit.auth_burst > 0,
+            "rate_limit.auth_burst must be at least 1 when rate limiting is on",
+        );
+
+        check(
+            self.body_limits.auth_bytes >= 1024,
+            "body_limits.auth_bytes must be at least 1024",
+        );
+        check(
+            self.body_limits.default_bytes >= 1024,
+            "body_limits.default_bytes must be at least 1024",
+        );
+        check(
+            self.saves.max_bytes >= 1024,
+            "saves.max_bytes must be at least 1024",
+        );
+        check(
+            self.idempotency.ttl_secs <= 30 * 24 * 60 * 60,
+            "idempotency.ttl_secs must be at most 30 days",
+        );
+        check(
+            self.leaderboard.stream_interval_ms <= 60_000,
+            "leaderboard.stream_interval_ms must be at most a minute",
+        );
+        check(
+            self.notifications.retention_days >= 0,
+            "notifications.retention_days must not be negative",
+        );
+
+        problems
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value.trim().parse().map_err(|e| format!("{value:?}: {e}"))
+}
+
+fn parse_into<T: FromStr>(value: &str, target: &mut T) -> Result<(), String>
+where
+    T::Err: fmt::Display,
+{
+    *target = parse(value)?;
+    Ok(())
+}
+
+/// Comma-separated list, ignoring blanks around and between entries.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_some<T: FromStr>(value: &str, target: &mut Option<T>) -> Result<(), String>
+where
+    T::Err: fmt::Display,
+{
+    *target = Some(parse(value)?);
+    Ok(())
+}
+
+/// `scheme://host[:port]` with nothing after it, as browsers send in `Origin`.
+fn is_origin(origin: &str) -> bool {
+    let Some((scheme, rest)) = origin.split_once("://") else {
+        return false;
+    };
+    matches!(scheme, "http" | "https") && !rest.is_empty() && !rest.contains(['/', '?', '#'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let toml = r#"
+            database_url = "postgres://file/db"
+            listen_addr = "0.0.0.0:8080"
+
+            [database]
+            max_connections = 20
+
+            [auth]
+            jwt_secret = "0123456789abcdef0123456789abcdef"
+        "#;
+        let config = Config::from_sources(
+            Some(toml),
+            env(&[
+                ("DATABASE_URL", "postgres://env/db"),
+                (
+                    "CORS_ALLOWED_ORIGINS",
+                    "https://a.example, http://localhost:8060",
+                ),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.database_url, "postgres://env/db");
+        assert_eq!(config.listen_addr, "0.0.0.0:8080".parse().unwrap());
+        assert_eq!(config.database.max_connections, 20);
+        assert_eq!(config.database.idle_timeout_secs, 600);
+        assert_eq!(
+            config.cors.origins(),
+            ["https://a.example", "http://localhost:8060"]
+        );
+    }
+
+    #[test]
+    fn cors_origins_default_per_environment() {
+        let load = |app_env| {
+            Config::from_sources(
+                None,
+                env(&[
+                    ("DATABASE_URL", "postgres://localhost/db"),
+                    ("JWT_SECRET", SECRET),
+                    ("APP_ENV", app_env),
+                ]),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            load("production").cors.origins(),
+            ["https://tycoon.jpro.dev"]
+        );
+        assert!(load("development")
+            .cors
+            .origins()
+            .contains(&"http://localhost:8060".to_string()));
+    }
+
+    #[test]
+    fn rejects_example_secret() {
+        let err = Config::from_sources(
+            None,
+            env(&[
+                ("DATABASE_URL", "postgres://localhost/db"),
+                ("JWT_SECRET", "change-me-to-a-random-string"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("example value"), "{err}");
+    }
+
+    #[test]
+    fn rejects_unparseable_min_client_version() {
+        let vars = [
+            ("DATABASE_URL", "postgres://localhost/db"),
+            ("JWT_SECRET", "0123456789abcdef0123456789abcdef"),
+            ("MIN_CLIENT_VERSION", "1.x"),
+        ];
+        let err = Config::from_sources(None, env(&vars)).unwrap_err();
+        assert!(err.to_string().contains("min_client_version"), "{err}");
+
+        let config = Config::from_sources(
+            None,
+            env(&[vars[0], vars[1], ("MIN_CLIENT_VERSION", "1.4")]),
+        )
+        .unwrap();
+        assert_eq!(config.min_client_version(), Some("1.4.0".parse().unwrap()));
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let err = Config::from_sources(
+            None,
+            env(&[
+                ("JWT_SECRET", SECRET),
+                ("DB_MAX_CONNECTIONS", "lots"),
+                (
+                    "CORS_ALLOWED_ORIGINS",
+                    "https://ok.example,example.com/path",
+                ),
+            ]),
+        )
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("DATABASE_URL"), "{message}");
+        assert!(message.contains("DB_MAX_CONNECTIONS"), "{message}");
+        assert!(message.contains("example.com/path"), "{message}");
+        assert_eq!(err.0.len(), 3, "{message}");
+    }
+
+    #[test]
+    fn tls_needs_cert_and_key_over_tcp() {
+        let base = [
+            ("DATABASE_URL", "postgres://localhost/db"),
+            ("JWT_SECRET", SECRET),
+            ("TLS_CERT_PATH", "/etc/tycoon/fullchain.pem"),
+        ];
+        let err = Config::from_sources(None, env(&base)).unwrap_err();
+        assert!(err.to_string().contains("TLS_KEY_PATH"), "{err}");
+
+        let vars = [
+            base.as_slice(),
+            &[
+                ("TLS_KEY_PATH", "/etc/tycoon/privkey.pem"),
+                ("LISTEN_SOCKET", "/run/tycoon/api.sock"),
+            ],
+        ]
+        .concat();
+        let err = Config::from_sources(None, env(&vars)).unwrap_err();
+        assert!(err.to_string().contains("listen_socket"), "{err}");
+
+        let config = Config::from_sources(None, env(&vars[..4])).unwrap();
+        assert!(config.tls.paths().is_some());
+    }
+
+    #[test]
+    fn rejects_unknown_file_keys() {
+        let err =
+            Config::from_sources(Some("[database]\nmax_conections = 3\n"), env(&[])).unwrap_err();
+        assert!(err.to_string().contains("max_conections"), "{err}");
+    }
+}
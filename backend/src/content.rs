@@ -0,0 +1,451 @@
+//! The game's content: skills, AI tools and the contracts on offer. The
+//! catalog is built in from `content/catalog.toml`, or read from
+//! `content_path` so it can change without a server build. Either way it's
+//! checked at load and served at `/api/content`, letting content updates
+//! reach players without a client release.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use tokio::signal::unix::SignalKind;
+use utoipa::ToSchema;
+
+use crate::{etag, AppState};
+
+const BUILT_IN: &str = include_str!("../content/catalog.toml");
+
+/// Everything the game would otherwise hard-code.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Catalog {
+    /// Raised with every change, so clients can tell catalogs apart.
+    pub version: u32,
+    pub skills: Vec<Skill>,
+    pub ai_tools: Vec<AiTool>,
+    pub contracts: ContractContent,
+}
+
+/// A skill players level up. Level `n` costs `cost * cost_multiplier^n`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Skill {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub category: SkillCategory,
+    pub cost: f64,
+    pub cost_multiplier: f64,
+    pub max_level: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SkillCategory {
+    Language,
+    Framework,
+    SoftSkill,
+}
+
+/// An AI tool that works one stage of a task on its own. Tier `n` costs
+/// `base_cost * cost_multiplier^n`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AiTool {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub target_state: TargetState,
+    pub base_cost: f64,
+    pub cost_multiplier: f64,
+    pub max_tier: u32,
+    /// Seconds between attempts.
+    pub cooldown: f64,
+    /// Chance an attempt succeeds, before tier bonuses.
+    pub reliability: f64,
+}
+
+/// The stage of a coding task a tool works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetState {
+    Writing,
+    Reviewing,
+    Conflict,
+    Ci,
+}
+
+/// What generated contracts are made of.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ContractContent {
+    pub client_names: Vec<String>,
+    pub project_types: Vec<String>,
+    /// Skill ids contracts can require.
+    pub skill_pool: Vec<String>,
+    /// Skill ids in `skill_pool` that aren't in `skills`: players can't buy
+    /// them, only rent consultants who have them.
+    #[serde(default)]
+    pub consultant_skills: Vec<String>,
+    /// Numbered from 1, in order.
+    pub tiers: Vec<ContractTier>,
+}
+
+/// The ranges a contract of one tier is rolled from, all inclusive.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ContractTier {
+    pub tier: u32,
+    pub name: String,
+    pub min_tasks: u32,
+    pub max_tasks: u32,
+    pub min_payout_per_task: f64,
+    pub max_payout_per_task: f64,
+    pub max_required_skills: u32,
+    pub max_required_level: u32,
+    /// Seconds the offer stays on the board.
+    pub offer_duration: f64,
+}
+
+/// Why a catalog was rejected.
+#[derive(Debug)]
+pub struct ContentError(Vec<String>);
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid content catalog:")?;
+        for problem in &self.0 {
+            writeln!(f, "  - {problem}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ContentError {}
+
+impl Catalog {
+    /// Parse and validate a catalog in TOML.
+    pub fn parse(text: &str) -> Result<Self, ContentError> {
+        let catalog: Catalog =
+            toml::from_str(text).map_err(|e| ContentError(vec![e.message().to_owned()]))?;
+        let problems = catalog.problems();
+        if !problems.is_empty() {
+            return Err(ContentError(problems));
+        }
+        Ok(catalog)
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, msg: String| {
+            if !ok {
+                problems.push(msg);
+            }
+        };
+
+        check(self.version > 0, "version must be at least 1".into());
+
+        let ids = |ids: Vec<&str>, what: &str, check: &mut dyn FnMut(bool, String)| {
+            check(!ids.is_empty(), format!("{what}: must not be empty"));
+            for (i, id) in ids.iter().enumerate() {
+                check(
+                    !id.trim().is_empty(),
+                    format!("{what} #{i}: id must be set"),
+                );
+                check(
+                    !ids[..i].contains(id),
+                    format!("{what}: {id:?} is listed twice"),
+                );
+            }
+        };
+        ids(
+            self.skills.iter().map(|s| s.id.as_str()).collect(),
+            "skills",
+            &mut check,
+        );
+        ids(
+            self.ai_tools.iter().map(|t| t.id.as_str()).collect(),
+            "ai_tools",
+            &mut check,
+        );
+
+        for s in &self.skills {
+            let id = &s.id;
+            check(s.cost > 0.0, format!("skill {id:?}: cost must be positive"));
+            check(
+                s.cost_multiplier >= 1.0,
+                format!("skill {id:?}: cost_multiplier must be at least 1"),
+            );
+            check(
+                s.max_level > 0,
+                format!("skill {id:?}: max_level must be at least 1"),
+            );
+        }
+        for t in &self.ai_tools {
+            let id = &t.id;
+            check(
+                t.base_cost > 0.0,
+                format!("AI tool {id:?}: base_cost must be positive"),
+            );
+            check(
+                t.cost_multiplier >= 1.0,
+                format!("AI tool {id:?}: cost_multiplier must be at least 1"),
+            );
+            check(
+                t.max_tier > 0,
+                format!("AI tool {id:?}: max_tier must be at least 1"),
+            );
+            check(
+                t.cooldown > 0.0,
+                format!("AI tool {id:?}: cooldown must be positive"),
+            );
+            check(
+                t.reliability > 0.0 && t.reliability <= 1.0,
+                format!("AI tool {id:?}: reliability must be above 0 and at most 1"),
+            );
+        }
+
+        let contracts = &self.contracts;
+        check(
+            !contracts.client_names.is_empty(),
+            "contracts.client_names must not be empty".into(),
+        );
+        check(
+            !contracts.project_types.is_empty(),
+            "contracts.project_types must not be empty".into(),
+        );
+        check(
+            !contracts.skill_pool.is_empty(),
+            "contracts.skill_pool must not be empty".into(),
+        );
+        for id in &contracts.skill_pool {
+            check(
+                self.skills.iter().any(|s| &s.id == id) || contracts.consultant_skills.contains(id),
+                format!("contracts.skill_pool: {id:?} is not a skill or consultant skill"),
+            );
+        }
+        for (i, id) in contracts.consultant_skills.iter().enumerate() {
+            check(
+                !self.skills.iter().any(|s| &s.id == id),
+                format!("contracts.consultant_skills: {id:?} is also a skill"),
+            );
+            check(
+                !contracts.consultant_skills[..i].contains(id),
+                format!("contracts.consultant_skills: {id:?} is listed twice"),
+            );
+        }
+        check(
+            !contracts.tiers.is_empty(),
+            "contracts.tiers must not be empty".into(),
+        );
+        for (i, t) in contracts.tiers.iter().enumerate() {
+            let n = t.tier;
+            check(
+                n as usize == i + 1,
+                format!("contract tier {n}: tiers must be numbered 1, 2, 3... in order"),
+            );
+            check(
+                0 < t.min_tasks && t.min_tasks <= t.max_tasks,
+                format!("contract tier {n}: tasks must be 1 <= min_tasks <= max_tasks"),
+            );
+            check(
+                0.0 < t.min_payout_per_task && t.min_payout_per_task <= t.max_payout_per_task,
+                format!(
+                    "contract tier {n}: payout must be 0 < min_payout_per_task <= max_payout_per_task"
+                ),
+            );
+            check(
+                t.max_required_skills > 0,
+                format!("contract tier {n}: max_required_skills must be at least 1"),
+            );
+            check(
+                t.max_required_level > 0,
+                format!("contract tier {n}: max_required_level must be at least 1"),
+            );
+            check(
+                t.offer_duration > 0.0,
+                format!("contract tier {n}: offer_duration must be positive"),
+            );
+        }
+
+        problems
+    }
+}
+
+/// The catalog being served.
+pub struct Content {
+    path: Option<PathBuf>,
+    current: RwLock<Arc<Loaded>>,
+}
+
+struct Loaded {
+    version: u32,
+    /// Serialized once, since every request gets the same body.
+    json: Bytes,
+}
+
+impl Content {
+    /// Load the catalog at `path`, or the built-in one.
+    pub fn load(path: Option<&Path>) -> Result<Self, ContentError> {
+        Ok(Content {
+            path: path.map(Path::to_path_buf),
+            current: RwLock::new(Arc::new(read(path)?)),
+        })
+    }
+
+    /// Reread the file. On failure the old catalog stays in use.
+    fn reload(&self) -> Result<u32, ContentError> {
+        let loaded = read(self.path.as_deref())?;
+        let version = loaded.version;
+        *self.current.write().unwrap() = Arc::new(loaded);
+        Ok(version)
+    }
+
+    /// Reload on SIGHUP, if the catalog comes from a file.
+    pub fn reload_on_sighup(self: &Arc<Self>) {
+        if self.path.is_none() {
+            return;
+        }
+        let content = self.clone();
+        let mut hup = tokio::signal::unix::signal(SignalKind::hangup())
+            .expect("Failed to install SIGHUP handler");
+        tokio::spawn(async move {
+            while hup.recv().await.is_some() {
+                match content.reload() {
+                    Ok(version) => tracing::info!(version, "Reloaded content catalog"),
+                    Err(e) => {
+                        tracing::error!(error = %e, "Content catalog reload failed, keeping the old one")
+                    }
+                }
+            }
+        });
+    }
+
+    fn current(&self) -> Arc<Loaded> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+fn read(path: Option<&Path>) -> Result<Loaded, ContentError> {
+    let catalog = match path {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| ContentError(vec![format!("{}: {e}", path.display())]))?;
+            Catalog::parse(&text).map_err(|ContentError(problems)| {
+                ContentError(
+                    problems
+                        .into_iter()
+                        .map(|p| format!("{}: {p}", path.display()))
+                        .collect(),
+                )
+            })?
+        }
+        None => Catalog::parse(BUILT_IN)?,
+    };
+    Ok(Loaded {
+        version: catalog.version,
+        json: serde_json::to_vec(&catalog)
+            .expect("catalog serializes")
+            .into(),
+    })
+}
+
+/// GET /api/content — The content catalog.
+#[utoipa::path(
+    get,
+    path = "/api/content",
+    tag = "content",
+    params(
+        ("If-None-Match" = Option<String>, Header, description = "ETag of the catalog the client has"),
+    ),
+    responses(
+        (status = 200, body = Catalog, headers(("ETag" = String))),
+        (status = 304, description = "The client's copy is current"),
+    ),
+)]
+pub async fn get_content(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    etag::json_or_not_modified(
+        &headers,
+        state.content.current().json.clone(),
+        [(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"))],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_catalog_is_valid() {
+        let catalog = Catalog::parse(BUILT_IN).unwrap();
+        assert!(catalog.skills.iter().any(|s| s.id == "coding_speed"));
+        assert_eq!(catalog.contracts.tiers.len(), 4);
+        assert!(catalog.contracts.skill_pool.iter().any(|id| id == "rust"));
+    }
+
+    #[test]
+    fn rejects_inconsistent_catalogs() {
+        for (edit, expected) in [
+            (
+                ("id = \"python\"", "id = \"javascript\""),
+                "\"javascript\" is listed twice",
+            ),
+            (("reliability = 0.45", "reliability = 1.5"), "reliability"),
+            (
+                ("cost_multiplier = 1.8", "cost_multiplier = 0.5"),
+                "cost_multiplier",
+            ),
+            (
+                ("\"devops\", \"frameworks\"]", "\"cobol\"]"),
+                "\"cobol\" is not a skill",
+            ),
+            (
+                (
+                    "consultant_skills = [\"rust\"",
+                    "consultant_skills = [\"python\"",
+                ),
+                "\"python\" is also a skill",
+            ),
+            (("tier = 3", "tier = 5"), "numbered"),
+            (
+                ("max_tasks = 25", "max_tasks = 5"),
+                "min_tasks <= max_tasks",
+            ),
+            (
+                ("max_level = 3", "max_level = 3\nprice = 1"),
+                "unknown field",
+            ),
+        ] {
+            let text = BUILT_IN.replacen(edit.0, edit.1, 1);
+            assert_ne!(text, BUILT_IN, "{edit:?} didn't apply");
+            let err = Catalog::parse(&text).unwrap_err().to_string();
+            assert!(err.contains(expected), "{edit:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_old_catalog() {
+        let path = std::env::temp_dir().join(format!("content-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, BUILT_IN.replacen("version = 1", "version = 2", 1)).unwrap();
+        let content = Content::load(Some(&path)).unwrap();
+        assert_eq!(content.current().version, 2);
+
+        std::fs::write(&path, BUILT_IN.replacen("version = 1", "version = 0", 1)).unwrap();
+        assert!(content.reload().is_err());
+        assert_eq!(content.current().version, 2);
+
+        std::fs::write(&path, BUILT_IN.replacen("version = 1", "version = 3", 1)).unwrap();
+        assert_eq!(content.reload().unwrap(), 3);
+        assert_eq!(content.current().version, 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+use std::time::Duration;
+
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::{config::CorsConfig, idempotency::REPLAYED_HEADER, telemetry::REQUEST_ID_HEADER};
+
+/// CORS for browser clients, i.e. the web build. The native game doesn't
+/// send `Origin` and is unaffected.
+///
+/// Tokens travel in the `Authorization` header rather than cookies, so
+/// credentials are never allowed.
+pub fn layer(config: &CorsConfig) -> CorsLayer {
+    let origins = config.origins();
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            origins
+                .iter()
+                .map(|o| HeaderValue::from_str(o).expect("origins are validated in Config")),
+        )
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(
+            config
+                .allowed_methods
+                .iter()
+                .map(|m| {
+                    m.parse::<Method>()
+                        .expect("methods are validated in Config")
+                })
+                .collect::<Vec<_>>(),
+        )
+        .allow_headers(
+            config
+                .allowed_headers
+                .iter()
+                .map(|h| {
+                    h.parse::<HeaderName>()
+                        .expect("headers are validated in Config")
+                })
+                .collect::<Vec<_>>(),
+        )
+        .expose_headers([
+            REQUEST_ID_HEADER,
+            REPLAYED_HEADER,
+            header::RETRY_AFTER,
+            header::ETAG,
+        ])
+        .max_age(Duration::from_secs(config.max_age_secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request, routing::put, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const WEB_ORIGIN: &str = "https://tycoon.jpro.dev";
+
+    fn app(origins: &[&str]) -> Router {
+        let config = CorsConfig {
+            allowed_origins: Some(origins.iter().map(|o| o.to_string()).collect()),
+            ..CorsConfig::default()
+        };
+        Router::new()
+            .route("/api/scores", put(|| async { "ok" }))
+            .layer(layer(&config))
+    }
+
+    fn preflight(origin: &str, method: &str, headers: &str) -> Request<Body> {
+        Request::options("/api/scores")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn header_str(res: &axum::response::Response, name: HeaderName) -> Option<&str> {
+        res.headers().get(name).map(|v| v.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn preflight_from_allowed_origin() {
+        let res = app(&[WEB_ORIGIN])
+            .oneshot(preflight(
+                WEB_ORIGIN,
+                "PUT",
+                "authorization,content-type,if-none-match",
+            ))
+            .await
+            .unwrap();
+
+        assert!(res.status().is_success());
+        assert_eq!(
+            header_str(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(WEB_ORIGIN)
+        );
+        let methods = header_str(&res, header::ACCESS_CONTROL_ALLOW_METHODS).unwrap();
+        assert!(methods.contains("PUT"), "{methods}");
+        let headers = header_str(&res, header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap();
+        assert!(headers.contains("authorization"), "{headers}");
+        assert!(headers.contains("if-none-match"), "{headers}");
+        assert_eq!(
+            header_str(&res, header::ACCESS_CONTROL_MAX_AGE),
+            Some("3600")
+        );
+        assert_eq!(
+            header_str(&res, header::ACCESS_CONTROL_ALLOW_CREDENTIALS),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn preflight_from_other_origin_is_not_allowed() {
+        let res = app(&[WEB_ORIGIN])
+            .oneshot(preflight("https://evil.example", "PUT", "authorization"))
+            .await
+            .unwrap();
+
+        assert_eq!(header_str(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN), None);
+    }
+
+    #[tokio::test]
+    async fn actual_request_exposes_request_id() {
+        let req = Request::put("/api/scores")
+            .header(header::ORIGIN, WEB_ORIGIN)
+            .body(Body::empty())
+            .unwrap();
+        let res = app(&[WEB_ORIGIN]).oneshot(req).await.unwrap();
+
+        assert_eq!(
+            header_str(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(WEB_ORIGIN)
+        );
+        let exposed = header_str(&res, header::ACCESS_CONTROL_EXPOSE_HEADERS).unwrap();
+        assert!(exposed.contains("x-request-id"), "{exposed}");
+        assert!(exposed.contains("etag"), "{exposed}");
+    }
+
+    #[tokio::test]
+    async fn wildcard_allows_any_origin() {
+        let res = app(&["*"])
+            .oneshot(preflight("http://localhost:9999", "PUT", "content-type"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            header_str(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("*")
+        );
+    }
+}
@@ -1,7 +1,33 @@
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{migrate::Migrator, postgres::PgPoolOptions, types::Json, PgPool};
 use uuid::Uuid;
 
-use crate::models::{LeaderboardEntry, Player, SaveDownload, ScoreSubmission};
+use crate::{
+    config::Config,
+    models::{
+        ActiveBan, AdminPlayerSummary, AuditLogEntry, BanKind, ConfigDocument, ConfigVersion,
+        Experiment, ExperimentVariant, IdempotentRequest, LeaderboardEntry, Notification,
+        NotificationKind, Player, Role, SaveDownload, SaveMetadata, ScoreComponents,
+        ScoreSubmission, VariantMetrics, ViewerStanding,
+    },
+};
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(all(test, feature = "postgres-tests"))]
+mod tests;
+
+/// Open the connection pool. Migrations are left to the caller.
+pub async fn connect(config: &Config) -> Result<PgPool, sqlx::Error> {
+    let db = &config.database;
+    PgPoolOptions::new()
+        .max_connections(db.max_connections)
+        .min_connections(db.min_connections)
+        .acquire_timeout(db.acquire_timeout())
+        .idle_timeout(db.idle_timeout())
+        .connect(&config.database_url)
+        .await
+}
 
 /// Insert a new player and an empty score_components row in a transaction.
 pub async fn create_player(
@@ -46,7 +72,9 @@ pub async fn find_player_by_passphrase(
     sqlx::query_as::<_, Player>(
         r#"
         SELECT id, display_name, passphrase, username, password_hash,
-               show_on_leaderboard, created_at, updated_at
+               show_on_leaderboard, totp_secret, totp_enabled, totp_last_step,
+               role, hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at, updated_at
         FROM players
         WHERE passphrase = $1
         "#,
@@ -64,7 +92,9 @@ pub async fn find_player_by_username(
     sqlx::query_as::<_, Player>(
         r#"
         SELECT id, display_name, passphrase, username, password_hash,
-               show_on_leaderboard, created_at, updated_at
+               show_on_leaderboard, totp_secret, totp_enabled, totp_last_step,
+               role, hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at, updated_at
         FROM players
         WHERE username = $1
         "#,
@@ -74,6 +104,26 @@ pub async fn find_player_by_username(
     .await
 }
 
+/// Find a player by id.
+pub async fn find_player_by_id(
+    pool: &PgPool,
+    player_id: Uuid,
+) -> Result<Option<Player>, sqlx::Error> {
+    sqlx::query_as::<_, Player>(
+        r#"
+        SELECT id, display_name, passphrase, username, password_hash,
+               show_on_leaderboard, totp_secret, totp_enabled, totp_last_step,
+               role, hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at, updated_at
+        FROM players
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await
+}
+
 /// Update a player's display_name and/or show_on_leaderboard.
 pub async fn update_player(
     pool: &PgPool,
@@ -124,6 +174,231 @@ pub async fn set_credentials(
     Ok(())
 }
 
+/// Store a not-yet-confirmed TOTP secret. 2FA stays disabled until a code is verified.
+pub async fn set_pending_totp_secret(
+    pool: &PgPool,
+    player_id: Uuid,
+    secret: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_secret = $2,
+            totp_enabled = false,
+            totp_last_step = NULL,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .bind(secret)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Turn on 2FA and replace any existing backup codes, in a transaction.
+pub async fn enable_totp(
+    pool: &PgPool,
+    player_id: Uuid,
+    verified_step: i64,
+    backup_code_hashes: &[String],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_enabled = true,
+            totp_last_step = $2,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .bind(verified_step)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM backup_codes WHERE player_id = $1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for hash in backup_code_hashes {
+        sqlx::query(
+            r#"
+            INSERT INTO backup_codes (player_id, code_hash)
+            VALUES ($1, $2)
+            "#,
+        )
+        .bind(player_id)
+        .bind(hash)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Turn off 2FA, forgetting the secret and all backup codes.
+pub async fn disable_totp(pool: &PgPool, player_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_secret = NULL,
+            totp_enabled = false,
+            totp_last_step = NULL,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM backup_codes WHERE player_id = $1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Record the time step of an accepted TOTP code. Returns false if that step
+/// (or a later one) was already used, i.e. the code is being replayed.
+pub async fn record_totp_step(
+    pool: &PgPool,
+    player_id: Uuid,
+    step: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_last_step = $2
+        WHERE id = $1
+          AND (totp_last_step IS NULL OR totp_last_step < $2)
+        "#,
+    )
+    .bind(player_id)
+    .bind(step)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Mark a backup code as used. Returns false if it doesn't exist or was already used.
+pub async fn consume_backup_code(
+    pool: &PgPool,
+    player_id: Uuid,
+    code_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE backup_codes
+        SET used_at = NOW()
+        WHERE player_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+    )
+    .bind(player_id)
+    .bind(code_hash)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Store a challenge issued at the password step. The player's expired
+/// challenges are dropped at the same time.
+pub async fn create_login_challenge(
+    pool: &PgPool,
+    id: Uuid,
+    player_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM login_challenges WHERE player_id = $1 AND expires_at <= NOW()")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO login_challenges (id, player_id, expires_at) VALUES ($1, $2, $3)")
+        .bind(id)
+        .bind(player_id)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// The player a challenge was issued to, if it's unexpired and unused.
+pub async fn login_challenge_player(pool: &PgPool, id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT player_id FROM login_challenges WHERE id = $1 AND expires_at > NOW()",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Count a second-factor attempt. Returns the attempts since the last
+/// successful two-factor login, including this one.
+pub async fn record_two_factor_attempt(pool: &PgPool, player_id: Uuid) -> Result<i64, sqlx::Error> {
+    let attempts: i32 = sqlx::query_scalar(
+        r#"
+        UPDATE players
+        SET two_factor_attempts = two_factor_attempts + 1
+        WHERE id = $1
+        RETURNING two_factor_attempts
+        "#,
+    )
+    .bind(player_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(attempts.into())
+}
+
+/// Redeem a challenge and reset its player's attempts. Returns false if it
+/// was already redeemed or revoked.
+pub async fn consume_login_challenge(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        WITH redeemed AS (
+            DELETE FROM login_challenges WHERE id = $1 RETURNING player_id
+        )
+        UPDATE players SET two_factor_attempts = 0
+        FROM redeemed
+        WHERE players.id = redeemed.player_id
+        "#,
+    )
+    .bind(id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Drop all of a player's challenges and reset their attempts, so the
+/// next try starts again from the password.
+pub async fn revoke_login_challenges(pool: &PgPool, player_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM login_challenges WHERE player_id = $1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE players SET two_factor_attempts = 0 WHERE id = $1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
 /// Insert or update score components, using GREATEST to prevent score regression.
 pub async fn upsert_scores(
     pool: &PgPool,
@@ -169,15 +444,29 @@ const SCORE_FORMULA: &str = r#"
      + sc.manual_tasks_completed * 50)
 "#;
 
-/// Get the top N leaderboard entries with computed score and rank.
+/// Which players take part in rankings. Shadow-banned players are left out
+/// for everyone except themselves, with the viewing player's id bound as $1.
+///
+/// Every ranking orders by score and then `sc.player_id`, so tied
+/// players get the same distinct ranks from every query.
+const RANKED_PLAYERS: &str = r#"
+    p.show_on_leaderboard = true
+    AND NOT p.hidden_by_admin
+    AND (p.ban_kind IS NULL
+         OR p.ban_expires_at <= NOW()
+         OR (p.ban_kind = 'shadow' AND p.id = $1))
+"#;
+
+/// Get the top N leaderboard entries with computed score and rank, as seen by `viewer`.
 pub async fn get_leaderboard(
     pool: &PgPool,
     limit: i64,
+    viewer: Option<Uuid>,
 ) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
     let query = format!(
         r#"
         SELECT
-            ROW_NUMBER() OVER (ORDER BY {score} DESC) AS rank,
+            ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
             p.display_name,
             {score} AS score,
             sc.total_money_earned,
@@ -188,20 +477,22 @@ pub async fn get_leaderboard(
             sc.manual_tasks_completed
         FROM score_components sc
         JOIN players p ON p.id = sc.player_id
-        WHERE p.show_on_leaderboard = true
-        ORDER BY score DESC
-        LIMIT $1
+        WHERE {ranked}
+        ORDER BY rank
+        LIMIT $2
         "#,
-        score = SCORE_FORMULA
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
     );
 
     sqlx::query_as::<_, LeaderboardEntry>(&query)
+        .bind(viewer)
         .bind(limit)
         .fetch_all(pool)
         .await
 }
 
-/// Get a single player's rank and score.
+/// Get a single player's rank and score, as seen by that player.
 pub async fn get_player_rank(
     pool: &PgPool,
     player_id: Uuid,
@@ -211,17 +502,18 @@ pub async fn get_player_rank(
         WITH ranked AS (
             SELECT
                 sc.player_id,
-                ROW_NUMBER() OVER (ORDER BY {score} DESC) AS rank,
+                ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
                 {score} AS score
             FROM score_components sc
             JOIN players p ON p.id = sc.player_id
-            WHERE p.show_on_leaderboard = true
+            WHERE {ranked}
         )
         SELECT rank, score
         FROM ranked
         WHERE player_id = $1
         "#,
-        score = SCORE_FORMULA
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
     );
 
     let row: Option<(i64, f64)> = sqlx::query_as(&query)
@@ -232,45 +524,1116 @@ pub async fn get_player_rank(
     Ok(row)
 }
 
-/// Upsert a cloud save.
+/// Ranks and scores of each of `viewers`, as each sees it, from a single
+/// ranking. Shadow-banned viewers are ranked among everyone else as if
+/// they were visible. Viewers who aren't ranked are left out.
+pub async fn viewer_standings(
+    pool: &PgPool,
+    viewers: &[Uuid],
+) -> Result<Vec<ViewerStanding>, sqlx::Error> {
+    let query = format!(
+        r#"
+        WITH ranked AS (
+            SELECT
+                sc.player_id,
+                ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
+                {score} AS score
+            FROM score_components sc
+            JOIN players p ON p.id = sc.player_id
+            WHERE {ranked}
+        ),
+        shadow_banned AS (
+            SELECT sc.player_id, {score} AS score
+            FROM score_components sc
+            JOIN players p ON p.id = sc.player_id
+            WHERE p.id = ANY($2)
+              AND p.show_on_leaderboard = true
+              AND NOT p.hidden_by_admin
+              AND p.ban_kind = 'shadow'
+              AND (p.ban_expires_at IS NULL OR p.ban_expires_at > NOW())
+        )
+        SELECT player_id, rank, score, false AS shadow_banned
+        FROM ranked
+        WHERE player_id = ANY($2)
+        UNION ALL
+        SELECT
+            s.player_id,
+            1 + (
+                SELECT COUNT(*)
+                FROM ranked r
+                WHERE r.score > s.score OR (r.score = s.score AND r.player_id < s.player_id)
+            ) AS rank,
+            s.score,
+            true AS shadow_banned
+        FROM shadow_banned s
+        "#,
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
+    );
+
+    sqlx::query_as(&query)
+        .bind(None::<Uuid>)
+        .bind(viewers)
+        .fetch_all(pool)
+        .await
+}
+
+/// Players in the public top `limit` with a score between `old_score` and
+/// `new_score`, what `player_id` had before and after a submission, so each
+/// submission only reports who it passed. Returns each with their rank now.
+/// Empty if `player_id` isn't on the public leaderboard, since nobody saw
+/// them pass.
+pub async fn overtaken_by(
+    pool: &PgPool,
+    player_id: Uuid,
+    old_score: f64,
+    new_score: f64,
+    limit: i64,
+) -> Result<Vec<(Uuid, i64)>, sqlx::Error> {
+    let query = format!(
+        r#"
+        WITH ranked AS (
+            SELECT
+                sc.player_id,
+                ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
+                {score} AS score
+            FROM score_components sc
+            JOIN players p ON p.id = sc.player_id
+            WHERE {ranked}
+        )
+        SELECT passed.player_id, passed.rank
+        FROM ranked passed
+        JOIN ranked passer ON passer.player_id = $2
+        WHERE passed.score > $3
+          AND passed.score < $4
+          AND passed.player_id <> passer.player_id
+          AND passed.rank <= $5
+        ORDER BY passed.rank
+        "#,
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
+    );
+
+    sqlx::query_as(&query)
+        .bind(None::<Uuid>)
+        .bind(player_id)
+        .bind(old_score)
+        .bind(new_score)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+/// zstd level for stored saves; cheap to compress and JSON shrinks well even at low levels.
+pub(crate) const SAVE_ZSTD_LEVEL: i32 = 3;
+
+/// Upsert a cloud save. Saves whose JSON is larger than `compress_above`
+/// bytes are stored zstd-compressed. Returns the uncompressed and stored sizes.
 pub async fn upsert_save(
     pool: &PgPool,
     player_id: Uuid,
     save_data: &serde_json::Value,
     version: i32,
-) -> Result<(), sqlx::Error> {
+    compress_above: usize,
+) -> Result<(usize, usize), sqlx::Error> {
+    let json = serde_json::to_vec(save_data).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let size = json.len();
+
+    let (plain, compressed) = if size > compress_above {
+        let bytes = zstd::encode_all(json.as_slice(), SAVE_ZSTD_LEVEL)
+            .map_err(|e| sqlx::Error::Encode(e.into()))?;
+        (None, Some(bytes))
+    } else {
+        (Some(save_data), None)
+    };
+    let stored = compressed.as_ref().map_or(size, Vec::len);
+
     sqlx::query(
         r#"
-        INSERT INTO saves (player_id, save_data, version)
-        VALUES ($1, $2, $3)
+        INSERT INTO saves (player_id, save_data, save_data_zstd, size_bytes, stored_bytes, version)
+        VALUES ($1, $2, $3, $4, $5, $6)
         ON CONFLICT (player_id) DO UPDATE SET
             save_data = EXCLUDED.save_data,
+            save_data_zstd = EXCLUDED.save_data_zstd,
+            size_bytes = EXCLUDED.size_bytes,
+            stored_bytes = EXCLUDED.stored_bytes,
             version = EXCLUDED.version,
             updated_at = NOW()
         "#,
     )
     .bind(player_id)
-    .bind(save_data)
+    .bind(plain)
+    .bind(compressed)
+    .bind(size as i32)
+    .bind(stored as i32)
     .bind(version)
     .execute(pool)
     .await?;
 
-    Ok(())
+    Ok((size, stored))
 }
 
-/// Download a player's cloud save.
+#[derive(sqlx::FromRow)]
+struct StoredSave {
+    save_data: Option<serde_json::Value>,
+    save_data_zstd: Option<Vec<u8>>,
+    version: i32,
+    updated_at: DateTime<Utc>,
+}
+
+/// Download a player's cloud save, decompressing it if needed.
 pub async fn get_save(
     pool: &PgPool,
     player_id: Uuid,
 ) -> Result<Option<SaveDownload>, sqlx::Error> {
-    sqlx::query_as::<_, SaveDownload>(
+    let row = sqlx::query_as::<_, StoredSave>(
         r#"
-        SELECT save_data, version, updated_at
+        SELECT save_data, save_data_zstd, version, updated_at
         FROM saves
         WHERE player_id = $1
         "#,
     )
     .bind(player_id)
     .fetch_optional(pool)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let save_data = match (row.save_data, row.save_data_zstd) {
+        (Some(data), _) => data,
+        (None, Some(bytes)) => {
+            let json =
+                zstd::decode_all(bytes.as_slice()).map_err(|e| sqlx::Error::Decode(e.into()))?;
+            serde_json::from_slice(&json).map_err(|e| sqlx::Error::Decode(e.into()))?
+        }
+        (None, None) => return Err(sqlx::Error::Decode("save row has no data".into())),
+    };
+
+    Ok(Some(SaveDownload {
+        save_data,
+        version: row.version,
+        updated_at: row.updated_at,
+    }))
+}
+
+// ── Admin ──
+
+/// The ban currently in effect for a player, ignoring expired bans.
+pub async fn get_active_ban(
+    pool: &PgPool,
+    player_id: Uuid,
+) -> Result<Option<ActiveBan>, sqlx::Error> {
+    sqlx::query_as::<_, ActiveBan>(
+        r#"
+        SELECT ban_kind, ban_reason, ban_expires_at
+        FROM players
+        WHERE id = $1
+          AND ban_kind IS NOT NULL
+          AND (ban_expires_at IS NULL OR ban_expires_at > NOW())
+        "#,
+    )
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Look up a player's role; `None` if the player doesn't exist.
+pub async fn get_player_role(pool: &PgPool, player_id: Uuid) -> Result<Option<Role>, sqlx::Error> {
+    sqlx::query_scalar("SELECT role FROM players WHERE id = $1")
+        .bind(player_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Search players by display name, username or exact id, newest first.
+pub async fn search_players(
+    pool: &PgPool,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<AdminPlayerSummary>, sqlx::Error> {
+    let exact_id = Uuid::parse_str(query.trim()).ok();
+    let pattern = format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+
+    sqlx::query_as::<_, AdminPlayerSummary>(
+        r#"
+        SELECT id, display_name, username, role, show_on_leaderboard,
+               hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at
+        FROM players
+        WHERE id = $1
+           OR display_name ILIKE $2
+           OR username ILIKE $2
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(exact_id)
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(pool)
     .await
 }
+
+/// Get a player's score components with the computed score.
+pub async fn get_score_components(
+    pool: &PgPool,
+    player_id: Uuid,
+) -> Result<Option<ScoreComponents>, sqlx::Error> {
+    let query = format!(
+        r#"
+        SELECT
+            {score} AS score,
+            sc.total_money_earned,
+            sc.reputation,
+            sc.skill_levels_sum,
+            sc.consultants_count,
+            sc.ai_tool_tiers_sum,
+            sc.manual_tasks_completed,
+            sc.updated_at
+        FROM score_components sc
+        WHERE sc.player_id = $1
+        "#,
+        score = SCORE_FORMULA
+    );
+
+    sqlx::query_as::<_, ScoreComponents>(&query)
+        .bind(player_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Get version, size and timestamp of a player's cloud save without loading it.
+pub async fn get_save_metadata(
+    pool: &PgPool,
+    player_id: Uuid,
+) -> Result<Option<SaveMetadata>, sqlx::Error> {
+    sqlx::query_as::<_, SaveMetadata>(
+        r#"
+        SELECT version, size_bytes::BIGINT, stored_bytes::BIGINT,
+               save_data_zstd IS NOT NULL AS compressed, updated_at
+        FROM saves
+        WHERE player_id = $1
+        "#,
+    )
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Write an audit log entry as part of an admin action's transaction.
+/// `admin_id` is `None` for actions taken through the management CLI.
+async fn insert_audit(
+    conn: &mut sqlx::PgConnection,
+    admin_id: Option<Uuid>,
+    action: &str,
+    target_player_id: Option<Uuid>,
+    details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO admin_audit_log (admin_id, action, target_player_id, details)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(admin_id)
+    .bind(action)
+    .bind(target_player_id)
+    .bind(details)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Hide or unhide a player from the leaderboard, independent of their own setting.
+/// Returns false if the player doesn't exist.
+pub async fn set_hidden_by_admin(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    hidden: bool,
+    details: serde_json::Value,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET hidden_by_admin = $2,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .bind(hidden)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let action = if hidden { "hide" } else { "unhide" };
+    insert_audit(&mut tx, admin_id, action, Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Zero all score components. This is the only way scores can go down,
+/// since `upsert_scores` never lowers a value. Returns false if the player doesn't exist.
+pub async fn reset_scores(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    details: serde_json::Value,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE score_components
+        SET total_money_earned = 0,
+            reputation = 0,
+            skill_levels_sum = 0,
+            consultants_count = 0,
+            ai_tool_tiers_sum = 0,
+            manual_tasks_completed = 0,
+            updated_at = NOW()
+        WHERE player_id = $1
+        "#,
+    )
+    .bind(player_id)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_audit(&mut tx, admin_id, "reset_scores", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Ban a player, replacing any existing ban. Returns false if the player doesn't exist.
+pub async fn ban_player(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    kind: BanKind,
+    reason: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET banned_at = NOW(),
+            ban_kind = $2,
+            ban_reason = $3,
+            ban_expires_at = $4,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .bind(kind)
+    .bind(reason)
+    .bind(expires_at)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let details = serde_json::json!({
+        "kind": kind,
+        "reason": reason,
+        "expires_at": expires_at,
+    });
+    insert_audit(&mut tx, admin_id, "ban", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Lift a player's ban. Returns false if the player doesn't exist.
+pub async fn unban_player(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    details: serde_json::Value,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET banned_at = NULL,
+            ban_kind = NULL,
+            ban_reason = NULL,
+            ban_expires_at = NULL,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_audit(&mut tx, admin_id, "unban", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Change a player's role. Returns false if the player doesn't exist.
+pub async fn set_role(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    role: Role,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET role = $2,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(player_id)
+    .bind(role)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let details = serde_json::json!({ "role": role });
+    insert_audit(&mut tx, admin_id, "set_role", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Most recent audit log entries, optionally for a single target player.
+/// Audit an admin action that isn't part of one of the changes above.
+pub async fn add_audit_entry(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    action: &str,
+    target_player_id: Option<Uuid>,
+    details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    insert_audit(&mut conn, admin_id, action, target_player_id, details).await
+}
+
+pub async fn get_audit_log(
+    pool: &PgPool,
+    target_player_id: Option<Uuid>,
+    limit: i64,
+) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AuditLogEntry>(
+        r#"
+        SELECT id, admin_id, action, target_player_id, details, created_at
+        FROM admin_audit_log
+        WHERE $1::UUID IS NULL OR target_player_id = $1
+        ORDER BY id DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(target_player_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+// ── Idempotency ──
+
+/// Claim `key` for a new request by `player_id` (nil for anonymous requests).
+/// Returns `None` when the claim succeeded, otherwise the request that holds
+/// the key. Claims older than `expired_before`, and claims still without a
+/// response older than `abandoned_before`, are cleared first.
+pub async fn claim_idempotency_key(
+    pool: &PgPool,
+    key: &str,
+    player_id: Uuid,
+    fingerprint: &str,
+    expired_before: DateTime<Utc>,
+    abandoned_before: DateTime<Utc>,
+) -> Result<Option<IdempotentRequest>, sqlx::Error> {
+    sqlx::query(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE key = $1 AND player_id = $2
+          AND (created_at < $3 OR (status IS NULL AND created_at < $4))
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(expired_before)
+    .bind(abandoned_before)
+    .execute(pool)
+    .await?;
+
+    let claimed = sqlx::query(
+        r#"
+        INSERT INTO idempotency_keys (key, player_id, fingerprint)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (key, player_id) DO NOTHING
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(fingerprint)
+    .execute(pool)
+    .await?;
+    if claimed.rows_affected() == 1 {
+        return Ok(None);
+    }
+
+    let holder = sqlx::query_as::<_, IdempotentRequest>(
+        r#"
+        SELECT fingerprint, status, content_type, body
+        FROM idempotency_keys
+        WHERE key = $1 AND player_id = $2
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await?;
+
+    // Released between the insert and the select: still contended, so
+    // report it as in flight rather than pretend it was claimed
+    Ok(Some(holder.unwrap_or_else(|| IdempotentRequest {
+        fingerprint: fingerprint.to_string(),
+        status: None,
+        content_type: None,
+        body: None,
+    })))
+}
+
+/// Store the response for a claimed key, for retries to replay.
+pub async fn complete_idempotency_key(
+    pool: &PgPool,
+    key: &str,
+    player_id: Uuid,
+    status: i16,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE idempotency_keys
+        SET status = $3,
+            content_type = $4,
+            body = $5
+        WHERE key = $1 AND player_id = $2
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(status)
+    .bind(content_type)
+    .bind(body)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Give up a claimed key without a response, so a retry runs the request again.
+pub async fn release_idempotency_key(
+    pool: &PgPool,
+    key: &str,
+    player_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE key = $1 AND player_id = $2")
+        .bind(key)
+        .bind(player_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Delete keys claimed before `cutoff`. Returns how many went.
+pub async fn prune_idempotency_keys(
+    pool: &PgPool,
+    cutoff: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE created_at < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ── Notifications ──
+
+pub async fn add_notification(
+    pool: &PgPool,
+    player_id: Uuid,
+    kind: NotificationKind,
+    data: serde_json::Value,
+) -> Result<Notification, sqlx::Error> {
+    sqlx::query_as::<_, Notification>(
+        r#"
+        INSERT INTO notifications (player_id, kind, data)
+        VALUES ($1, $2, $3)
+        RETURNING id, player_id, kind, data, created_at, read_at
+        "#,
+    )
+    .bind(player_id)
+    .bind(kind)
+    .bind(data)
+    .fetch_one(pool)
+    .await
+}
+
+/// A notification of `kind` for each player and data pair whose player
+/// exists, in one insert. Returns them, for pushing to players who are
+/// connected.
+pub async fn add_notifications(
+    pool: &PgPool,
+    kind: NotificationKind,
+    notifications: &[(Uuid, serde_json::Value)],
+) -> Result<Vec<Notification>, sqlx::Error> {
+    let (player_ids, data): (Vec<Uuid>, Vec<serde_json::Value>) =
+        notifications.iter().cloned().unzip();
+    sqlx::query_as::<_, Notification>(
+        r#"
+        INSERT INTO notifications (player_id, kind, data)
+        SELECT p.id, $3, n.data
+        FROM UNNEST($1::UUID[], $2::JSONB[]) AS n(player_id, data)
+        JOIN players p ON p.id = n.player_id
+        RETURNING id, player_id, kind, data, created_at, read_at
+        "#,
+    )
+    .bind(player_ids)
+    .bind(data)
+    .bind(kind)
+    .fetch_all(pool)
+    .await
+}
+
+/// The same notification for the next `limit` players by id after `after`,
+/// except those in `skip`, so everyone can be notified a batch at a time.
+/// Returns how many were added and the last player id the batch covered,
+/// which is `None` once there are no players left.
+pub async fn add_notification_batch(
+    pool: &PgPool,
+    kind: NotificationKind,
+    data: serde_json::Value,
+    after: Option<Uuid>,
+    skip: &[Uuid],
+    limit: i64,
+) -> Result<(u64, Option<Uuid>), sqlx::Error> {
+    let (added, last): (i64, Option<Uuid>) = sqlx::query_as(
+        r#"
+        WITH batch AS (
+            SELECT id FROM players
+            WHERE $1::UUID IS NULL OR id > $1
+            ORDER BY id
+            LIMIT $2
+        ),
+        added AS (
+            INSERT INTO notifications (player_id, kind, data)
+            SELECT id, $4, $5 FROM batch WHERE id <> ALL($3)
+            RETURNING 1
+        )
+        SELECT
+            (SELECT COUNT(*) FROM added),
+            (SELECT id FROM batch ORDER BY id DESC LIMIT 1)
+        "#,
+    )
+    .bind(after)
+    .bind(limit)
+    .bind(skip)
+    .bind(kind)
+    .bind(data)
+    .fetch_one(pool)
+    .await?;
+
+    Ok((added as u64, last))
+}
+
+/// A player's notifications, newest first, optionally only unread ones and
+/// only those older than the id `before`.
+pub async fn list_notifications(
+    pool: &PgPool,
+    player_id: Uuid,
+    unread_only: bool,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<Notification>, sqlx::Error> {
+    sqlx::query_as::<_, Notification>(
+        r#"
+        SELECT id, player_id, kind, data, created_at, read_at
+        FROM notifications
+        WHERE player_id = $1
+          AND (NOT $2 OR read_at IS NULL)
+          AND ($3::BIGINT IS NULL OR id < $3)
+        ORDER BY id DESC
+        LIMIT $4
+        "#,
+    )
+    .bind(player_id)
+    .bind(unread_only)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn count_unread_notifications(
+    pool: &PgPool,
+    player_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM notifications WHERE player_id = $1 AND read_at IS NULL",
+    )
+    .bind(player_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// Mark the player's notifications with these ids read, or all of them for
+/// `None`. Ids of other players' notifications are ignored. Returns how many
+/// were unread.
+pub async fn mark_notifications_read(
+    pool: &PgPool,
+    player_id: Uuid,
+    ids: Option<&[i64]>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE notifications
+        SET read_at = NOW()
+        WHERE player_id = $1
+          AND read_at IS NULL
+          AND ($2::BIGINT[] IS NULL OR id = ANY($2))
+        "#,
+    )
+    .bind(player_id)
+    .bind(ids)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Delete notifications created before `cutoff`, read or not. Returns how
+/// many went.
+pub async fn prune_notifications(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM notifications WHERE created_at < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ── Remote config ──
+
+/// Store `document` as the newest, and so live, config version, and audit
+/// it. `rollback_of` is the version it was copied from, for rollbacks.
+pub async fn publish_config(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    document: &ConfigDocument,
+    note: &str,
+    rollback_of: Option<i64>,
+) -> Result<ConfigVersion, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let published = sqlx::query_as::<_, ConfigVersion>(
+        r#"
+        INSERT INTO config_versions (document, note, published_by)
+        VALUES ($1, $2, $3)
+        RETURNING version, document, note, published_by, published_at
+        "#,
+    )
+    .bind(Json(document))
+    .bind(note)
+    .bind(admin_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let (action, details) = config_audit(&published, rollback_of);
+    insert_audit(&mut tx, admin_id, action, None, details).await?;
+
+    tx.commit().await?;
+    Ok(published)
+}
+
+/// The audit entry for publishing `published`.
+pub(crate) fn config_audit(
+    published: &ConfigVersion,
+    rollback_of: Option<i64>,
+) -> (&'static str, serde_json::Value) {
+    let details = serde_json::json!({
+        "version": published.version,
+        "note": published.note,
+    });
+    match rollback_of {
+        None => ("publish_config", details),
+        Some(from) => {
+            let mut details = details;
+            details["rollback_of"] = from.into();
+            ("rollback_config", details)
+        }
+    }
+}
+
+/// A config version, or the live one for `None`.
+pub async fn get_config_version(
+    pool: &PgPool,
+    version: Option<i64>,
+) -> Result<Option<ConfigVersion>, sqlx::Error> {
+    sqlx::query_as::<_, ConfigVersion>(
+        r#"
+        SELECT version, document, note, published_by, published_at
+        FROM config_versions
+        WHERE $1::BIGINT IS NULL OR version = $1
+        ORDER BY version DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(version)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Config versions, newest first.
+pub async fn list_config_versions(
+    pool: &PgPool,
+    limit: i64,
+) -> Result<Vec<ConfigVersion>, sqlx::Error> {
+    sqlx::query_as::<_, ConfigVersion>(
+        r#"
+        SELECT version, document, note, published_by, published_at
+        FROM config_versions
+        ORDER BY version DESC
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+// ── Experiments ──
+
+pub async fn create_experiment(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    key: &str,
+    description: &str,
+    variants: &[ExperimentVariant],
+) -> Result<Experiment, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        r#"
+        INSERT INTO experiments (key, description, variants, created_by)
+        VALUES ($1, $2, $3, $4)
+        RETURNING key, description, variants, created_by, created_at, ended_at
+        "#,
+    )
+    .bind(key)
+    .bind(description)
+    .bind(Json(variants))
+    .bind(admin_id)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_experiment(pool: &PgPool, key: &str) -> Result<Option<Experiment>, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        r#"
+        SELECT key, description, variants, created_by, created_at, ended_at
+        FROM experiments
+        WHERE key = $1
+        "#,
+    )
+    .bind(key)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Experiments, newest first.
+pub async fn list_experiments(
+    pool: &PgPool,
+    running_only: bool,
+) -> Result<Vec<Experiment>, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        r#"
+        SELECT key, description, variants, created_by, created_at, ended_at
+        FROM experiments
+        WHERE NOT $1 OR ended_at IS NULL
+        ORDER BY created_at DESC, key
+        "#,
+    )
+    .bind(running_only)
+    .fetch_all(pool)
+    .await
+}
+
+/// Stop assigning players to an experiment. Ending it again keeps the
+/// first end time. `None` if there's no such experiment.
+pub async fn end_experiment(
+    pool: &PgPool,
+    admin_id: Option<Uuid>,
+    key: &str,
+) -> Result<Option<Experiment>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let ended = sqlx::query_as::<_, Experiment>(
+        r#"
+        UPDATE experiments
+        SET ended_at = COALESCE(ended_at, NOW())
+        WHERE key = $1
+        RETURNING key, description, variants, created_by, created_at, ended_at
+        "#,
+    )
+    .bind(key)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(ended) = ended else {
+        return Ok(None);
+    };
+
+    let details = serde_json::json!({ "key": key, "ended_at": ended.ended_at });
+    insert_audit(&mut tx, admin_id, "end_experiment", None, details).await?;
+
+    tx.commit().await?;
+    Ok(Some(ended))
+}
+
+/// Note that a player was shown their variant. Only the first time counts.
+pub async fn record_exposure(
+    pool: &PgPool,
+    key: &str,
+    player_id: Uuid,
+    variant: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO experiment_exposures (experiment_key, player_id, variant)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (experiment_key, player_id) DO NOTHING
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(variant)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Per-variant averages of the exposed players' score components. Only
+/// players who take part in rankings count, so banned and hidden players'
+/// scores don't skew the comparison. Variants nobody counted was exposed
+/// to are left out.
+pub async fn experiment_metrics(
+    pool: &PgPool,
+    key: &str,
+) -> Result<Vec<VariantMetrics>, sqlx::Error> {
+    let query = format!(
+        r#"
+        SELECT
+            e.variant,
+            COUNT(*) AS players,
+            AVG({score})::FLOAT8 AS avg_score,
+            AVG(sc.total_money_earned)::FLOAT8 AS avg_total_money_earned,
+            AVG(sc.reputation)::FLOAT8 AS avg_reputation,
+            AVG(sc.skill_levels_sum)::FLOAT8 AS avg_skill_levels_sum,
+            AVG(sc.consultants_count)::FLOAT8 AS avg_consultants_count,
+            AVG(sc.ai_tool_tiers_sum)::FLOAT8 AS avg_ai_tool_tiers_sum,
+            AVG(sc.manual_tasks_completed)::FLOAT8 AS avg_manual_tasks_completed
+        FROM experiment_exposures e
+        JOIN score_components sc ON sc.player_id = e.player_id
+        JOIN players p ON p.id = e.player_id
+        WHERE e.experiment_key = $2 AND {ranked}
+        GROUP BY e.variant
+        "#,
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
+    );
+
+    sqlx::query_as::<_, VariantMetrics>(&query)
+        .bind(None::<Uuid>)
+        .bind(key)
+        .fetch_all(pool)
+        .await
+}
+
+// ── Maintenance ──
+
+/// Players with a cloud save, oldest save first.
+pub async fn players_with_saves(pool: &PgPool) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar("SELECT player_id FROM saves ORDER BY updated_at")
+        .fetch_all(pool)
+        .await
+}
+
+/// Number of cloud saves last written before `cutoff`.
+pub async fn count_saves_before(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM saves WHERE updated_at < $1")
+        .bind(cutoff)
+        .fetch_one(pool)
+        .await
+}
+
+/// Delete cloud saves last written before `cutoff`. Returns how many went.
+pub async fn delete_saves_before(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM saves WHERE updated_at < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ── Health ──
+
+/// Migrations embedded at build time; run at startup and checked by readiness.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Cheapest possible round trip to the database.
+pub async fn ping(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+/// Versions of embedded migrations that haven't been applied successfully.
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let applied: Vec<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .map(|m| m.version)
+        .filter(|v| !applied.contains(v))
+        .collect())
+}
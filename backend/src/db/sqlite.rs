@@ -0,0 +1,1650 @@
+//! The queries in `db`, for SQLite. Each function does what its Postgres
+//! namesake does; differences in SQL are noted where they matter.
+//!
+//! Timestamps are always bound from Rust rather than taken from SQLite's
+//! clock, so every stored value has the same RFC 3339 format and compares
+//! correctly as text.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use sqlx::{
+    migrate::Migrator,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    types::Json,
+    SqlitePool,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    models::{
+        ActiveBan, AdminPlayerSummary, AuditLogEntry, BanKind, ConfigDocument, ConfigVersion,
+        Experiment, ExperimentVariant, IdempotentRequest, LeaderboardEntry, Notification,
+        NotificationKind, Player, Role, SaveDownload, SaveMetadata, ScoreComponents,
+        ScoreSubmission, VariantMetrics, ViewerStanding,
+    },
+};
+
+/// Whether `DATABASE_URL` points at SQLite rather than Postgres.
+pub fn is_sqlite_url(url: &str) -> bool {
+    url.starts_with("sqlite:")
+}
+
+/// Open the connection pool, creating the database file if needed.
+/// Migrations are left to the caller.
+pub async fn connect(config: &Config) -> Result<SqlitePool, sqlx::Error> {
+    let db = &config.database;
+    let options = SqliteConnectOptions::from_str(&config.database_url)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
+
+    // An in-memory database disappears with its last connection, so it gets
+    // exactly one that is never closed
+    let in_memory =
+        config.database_url.contains(":memory:") || config.database_url.contains("mode=memory");
+    let pool = if in_memory {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new()
+            .max_connections(db.max_connections)
+            .min_connections(db.min_connections)
+            .idle_timeout(db.idle_timeout())
+    };
+    pool.acquire_timeout(db.acquire_timeout())
+        .connect_with(options)
+        .await
+}
+
+pub async fn create_player(
+    pool: &SqlitePool,
+    id: Uuid,
+    display_name: &str,
+    passphrase: &str,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO players (id, display_name, passphrase, created_at, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?4)
+        "#,
+    )
+    .bind(id)
+    .bind(display_name)
+    .bind(passphrase)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO score_components (player_id, updated_at)
+        VALUES (?1, ?2)
+        "#,
+    )
+    .bind(id)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn find_player_by_passphrase(
+    pool: &SqlitePool,
+    passphrase: &str,
+) -> Result<Option<Player>, sqlx::Error> {
+    sqlx::query_as::<_, Player>(
+        r#"
+        SELECT id, display_name, passphrase, username, password_hash,
+               show_on_leaderboard, totp_secret, totp_enabled, totp_last_step,
+               role, hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at, updated_at
+        FROM players
+        WHERE passphrase = ?1
+        "#,
+    )
+    .bind(passphrase)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn find_player_by_username(
+    pool: &SqlitePool,
+    username: &str,
+) -> Result<Option<Player>, sqlx::Error> {
+    sqlx::query_as::<_, Player>(
+        r#"
+        SELECT id, display_name, passphrase, username, password_hash,
+               show_on_leaderboard, totp_secret, totp_enabled, totp_last_step,
+               role, hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at, updated_at
+        FROM players
+        WHERE username = ?1
+        "#,
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn find_player_by_id(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<Option<Player>, sqlx::Error> {
+    sqlx::query_as::<_, Player>(
+        r#"
+        SELECT id, display_name, passphrase, username, password_hash,
+               show_on_leaderboard, totp_secret, totp_enabled, totp_last_step,
+               role, hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at, updated_at
+        FROM players
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn update_player(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    display_name: Option<&str>,
+    show_on_leaderboard: Option<bool>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET display_name = COALESCE(?2, display_name),
+            show_on_leaderboard = COALESCE(?3, show_on_leaderboard),
+            updated_at = ?4
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(display_name)
+    .bind(show_on_leaderboard)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_credentials(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    username: &str,
+    password_hash: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET username = ?2,
+            password_hash = ?3,
+            updated_at = ?4
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(username)
+    .bind(password_hash)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_pending_totp_secret(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    secret: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_secret = ?2,
+            totp_enabled = false,
+            totp_last_step = NULL,
+            updated_at = ?3
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(secret)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn enable_totp(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    verified_step: i64,
+    backup_code_hashes: &[String],
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_enabled = true,
+            totp_last_step = ?2,
+            updated_at = ?3
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(verified_step)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM backup_codes WHERE player_id = ?1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for hash in backup_code_hashes {
+        sqlx::query(
+            r#"
+            INSERT INTO backup_codes (player_id, code_hash, created_at)
+            VALUES (?1, ?2, ?3)
+            "#,
+        )
+        .bind(player_id)
+        .bind(hash)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn disable_totp(pool: &SqlitePool, player_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_secret = NULL,
+            totp_enabled = false,
+            totp_last_step = NULL,
+            updated_at = ?2
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM backup_codes WHERE player_id = ?1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn record_totp_step(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    step: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET totp_last_step = ?2
+        WHERE id = ?1
+          AND (totp_last_step IS NULL OR totp_last_step < ?2)
+        "#,
+    )
+    .bind(player_id)
+    .bind(step)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn consume_backup_code(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    code_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE backup_codes
+        SET used_at = ?3
+        WHERE player_id = ?1 AND code_hash = ?2 AND used_at IS NULL
+        "#,
+    )
+    .bind(player_id)
+    .bind(code_hash)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn create_login_challenge(
+    pool: &SqlitePool,
+    id: Uuid,
+    player_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM login_challenges WHERE player_id = ?1 AND expires_at <= ?2")
+        .bind(player_id)
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO login_challenges (id, player_id, expires_at) VALUES (?1, ?2, ?3)")
+        .bind(id)
+        .bind(player_id)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+pub async fn login_challenge_player(
+    pool: &SqlitePool,
+    id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar("SELECT player_id FROM login_challenges WHERE id = ?1 AND expires_at > ?2")
+        .bind(id)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn record_two_factor_attempt(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        UPDATE players
+        SET two_factor_attempts = two_factor_attempts + 1
+        WHERE id = ?1
+        RETURNING two_factor_attempts
+        "#,
+    )
+    .bind(player_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// SQLite has no DELETE in WITH, so this takes a transaction.
+pub async fn consume_login_challenge(pool: &SqlitePool, id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let player_id: Option<Uuid> =
+        sqlx::query_scalar("DELETE FROM login_challenges WHERE id = ?1 RETURNING player_id")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let Some(player_id) = player_id else {
+        return Ok(false);
+    };
+    sqlx::query("UPDATE players SET two_factor_attempts = 0 WHERE id = ?1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn revoke_login_challenges(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM login_challenges WHERE player_id = ?1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE players SET two_factor_attempts = 0 WHERE id = ?1")
+        .bind(player_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// SQLite has no `GREATEST`; its two-argument `MAX` is the same thing.
+pub async fn upsert_scores(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    scores: &ScoreSubmission,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO score_components (
+            player_id, total_money_earned, reputation, skill_levels_sum,
+            consultants_count, ai_tool_tiers_sum, manual_tasks_completed, updated_at
+        )
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        ON CONFLICT (player_id) DO UPDATE SET
+            total_money_earned = MAX(score_components.total_money_earned, excluded.total_money_earned),
+            reputation = MAX(score_components.reputation, excluded.reputation),
+            skill_levels_sum = MAX(score_components.skill_levels_sum, excluded.skill_levels_sum),
+            consultants_count = MAX(score_components.consultants_count, excluded.consultants_count),
+            ai_tool_tiers_sum = MAX(score_components.ai_tool_tiers_sum, excluded.ai_tool_tiers_sum),
+            manual_tasks_completed = MAX(score_components.manual_tasks_completed, excluded.manual_tasks_completed),
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(player_id)
+    .bind(scores.total_money_earned)
+    .bind(scores.reputation)
+    .bind(scores.skill_levels_sum)
+    .bind(scores.consultants_count)
+    .bind(scores.ai_tool_tiers_sum)
+    .bind(scores.manual_tasks_completed)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Same weights as `db::SCORE_FORMULA`.
+const SCORE_FORMULA: &str = r#"
+    (sc.total_money_earned * 1.0
+     + sc.reputation * 500.0
+     + sc.skill_levels_sum * 100
+     + sc.consultants_count * 250
+     + sc.ai_tool_tiers_sum * 150
+     + sc.manual_tasks_completed * 50)
+"#;
+
+/// Same filter as `db::RANKED_PLAYERS`, with the viewer bound as ?1 and the
+/// current time as ?2.
+const RANKED_PLAYERS: &str = r#"
+    p.show_on_leaderboard
+    AND NOT p.hidden_by_admin
+    AND (p.ban_kind IS NULL
+         OR p.ban_expires_at <= ?2
+         OR (p.ban_kind = 'shadow' AND p.id = ?1))
+"#;
+
+/// Ranked with `ROW_NUMBER()`, which the bundled SQLite (3.25+) supports.
+pub async fn get_leaderboard(
+    pool: &SqlitePool,
+    limit: i64,
+    viewer: Option<Uuid>,
+) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+    let query = format!(
+        r#"
+        SELECT
+            ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
+            p.display_name,
+            {score} AS score,
+            sc.total_money_earned,
+            sc.reputation,
+            sc.skill_levels_sum,
+            sc.consultants_count,
+            sc.ai_tool_tiers_sum,
+            sc.manual_tasks_completed
+        FROM score_components sc
+        JOIN players p ON p.id = sc.player_id
+        WHERE {ranked}
+        ORDER BY rank
+        LIMIT ?3
+        "#,
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
+    );
+
+    sqlx::query_as::<_, LeaderboardEntry>(&query)
+        .bind(viewer)
+        .bind(Utc::now())
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_player_rank(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<Option<(i64, f64)>, sqlx::Error> {
+    let query = format!(
+        r#"
+        WITH ranked AS (
+            SELECT
+                sc.player_id,
+                ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
+                {score} AS score
+            FROM score_components sc
+            JOIN players p ON p.id = sc.player_id
+            WHERE {ranked}
+        )
+        SELECT rank, score
+        FROM ranked
+        WHERE player_id = ?1
+        "#,
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
+    );
+
+    sqlx::query_as(&query)
+        .bind(player_id)
+        .bind(Utc::now())
+        .fetch_optional(pool)
+        .await
+}
+
+/// Viewers go in as one parameter each, so at most this many per query.
+const VIEWERS_PER_QUERY: usize = 500;
+
+pub async fn viewer_standings(
+    pool: &SqlitePool,
+    viewers: &[Uuid],
+) -> Result<Vec<ViewerStanding>, sqlx::Error> {
+    let mut standings = Vec::new();
+    for viewers in viewers.chunks(VIEWERS_PER_QUERY) {
+        let ids = (0..viewers.len())
+            .map(|i| format!("?{}", i + 3))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let query = format!(
+            r#"
+            WITH ranked AS (
+                SELECT
+                    sc.player_id,
+                    ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
+                    {score} AS score
+                FROM score_components sc
+                JOIN players p ON p.id = sc.player_id
+                WHERE {ranked}
+            ),
+            shadow_banned AS (
+                SELECT sc.player_id, {score} AS score
+                FROM score_components sc
+                JOIN players p ON p.id = sc.player_id
+                WHERE p.id IN ({ids})
+                  AND p.show_on_leaderboard
+                  AND NOT p.hidden_by_admin
+                  AND p.ban_kind = 'shadow'
+                  AND (p.ban_expires_at IS NULL OR p.ban_expires_at > ?2)
+            )
+            SELECT player_id, rank, score, false AS shadow_banned
+            FROM ranked
+            WHERE player_id IN ({ids})
+            UNION ALL
+            SELECT
+                s.player_id,
+                1 + (
+                    SELECT COUNT(*)
+                    FROM ranked r
+                    WHERE r.score > s.score OR (r.score = s.score AND r.player_id < s.player_id)
+                ) AS rank,
+                s.score,
+                true AS shadow_banned
+            FROM shadow_banned s
+            "#,
+            score = SCORE_FORMULA,
+            ranked = RANKED_PLAYERS
+        );
+
+        let mut query = sqlx::query_as(&query).bind(None::<Uuid>).bind(Utc::now());
+        for viewer in viewers {
+            query = query.bind(viewer);
+        }
+        standings.extend(query.fetch_all(pool).await?);
+    }
+    Ok(standings)
+}
+
+pub async fn overtaken_by(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    old_score: f64,
+    new_score: f64,
+    limit: i64,
+) -> Result<Vec<(Uuid, i64)>, sqlx::Error> {
+    let query = format!(
+        r#"
+        WITH ranked AS (
+            SELECT
+                sc.player_id,
+                ROW_NUMBER() OVER (ORDER BY {score} DESC, sc.player_id) AS rank,
+                {score} AS score
+            FROM score_components sc
+            JOIN players p ON p.id = sc.player_id
+            WHERE {ranked}
+        )
+        SELECT passed.player_id, passed.rank
+        FROM ranked passed
+        JOIN ranked passer ON passer.player_id = ?3
+        WHERE passed.score > ?4
+          AND passed.score < ?5
+          AND passed.player_id <> passer.player_id
+          AND passed.rank <= ?6
+        ORDER BY passed.rank
+        "#,
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
+    );
+
+    sqlx::query_as(&query)
+        .bind(None::<Uuid>)
+        .bind(Utc::now())
+        .bind(player_id)
+        .bind(old_score)
+        .bind(new_score)
+        .bind(limit)
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn upsert_save(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    save_data: &serde_json::Value,
+    version: i32,
+    compress_above: usize,
+) -> Result<(usize, usize), sqlx::Error> {
+    let json = serde_json::to_vec(save_data).map_err(|e| sqlx::Error::Encode(e.into()))?;
+    let size = json.len();
+
+    let (plain, compressed) = if size > compress_above {
+        let bytes = zstd::encode_all(json.as_slice(), super::SAVE_ZSTD_LEVEL)
+            .map_err(|e| sqlx::Error::Encode(e.into()))?;
+        (None, Some(bytes))
+    } else {
+        (Some(Json(save_data)), None)
+    };
+    let stored = compressed.as_ref().map_or(size, Vec::len);
+
+    sqlx::query(
+        r#"
+        INSERT INTO saves (player_id, save_data, save_data_zstd, size_bytes, stored_bytes, version, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ON CONFLICT (player_id) DO UPDATE SET
+            save_data = excluded.save_data,
+            save_data_zstd = excluded.save_data_zstd,
+            size_bytes = excluded.size_bytes,
+            stored_bytes = excluded.stored_bytes,
+            version = excluded.version,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(player_id)
+    .bind(plain)
+    .bind(compressed)
+    .bind(size as i64)
+    .bind(stored as i64)
+    .bind(version)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok((size, stored))
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredSave {
+    save_data: Option<Json<serde_json::Value>>,
+    save_data_zstd: Option<Vec<u8>>,
+    version: i32,
+    updated_at: DateTime<Utc>,
+}
+
+pub async fn get_save(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<Option<SaveDownload>, sqlx::Error> {
+    let row = sqlx::query_as::<_, StoredSave>(
+        r#"
+        SELECT save_data, save_data_zstd, version, updated_at
+        FROM saves
+        WHERE player_id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let save_data = match (row.save_data, row.save_data_zstd) {
+        (Some(Json(data)), _) => data,
+        (None, Some(bytes)) => {
+            let json =
+                zstd::decode_all(bytes.as_slice()).map_err(|e| sqlx::Error::Decode(e.into()))?;
+            serde_json::from_slice(&json).map_err(|e| sqlx::Error::Decode(e.into()))?
+        }
+        (None, None) => return Err(sqlx::Error::Decode("save row has no data".into())),
+    };
+
+    Ok(Some(SaveDownload {
+        save_data,
+        version: row.version,
+        updated_at: row.updated_at,
+    }))
+}
+
+// ── Admin ──
+
+pub async fn get_active_ban(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<Option<ActiveBan>, sqlx::Error> {
+    sqlx::query_as::<_, ActiveBan>(
+        r#"
+        SELECT ban_kind, ban_reason, ban_expires_at
+        FROM players
+        WHERE id = ?1
+          AND ban_kind IS NOT NULL
+          AND (ban_expires_at IS NULL OR ban_expires_at > ?2)
+        "#,
+    )
+    .bind(player_id)
+    .bind(Utc::now())
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get_player_role(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<Option<Role>, sqlx::Error> {
+    sqlx::query_scalar("SELECT role FROM players WHERE id = ?1")
+        .bind(player_id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// `LIKE` is already case-insensitive for ASCII in SQLite, but needs the
+/// escape character spelled out.
+pub async fn search_players(
+    pool: &SqlitePool,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<AdminPlayerSummary>, sqlx::Error> {
+    let exact_id = Uuid::parse_str(query.trim()).ok();
+    let pattern = format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+
+    sqlx::query_as::<_, AdminPlayerSummary>(
+        r#"
+        SELECT id, display_name, username, role, show_on_leaderboard,
+               hidden_by_admin, banned_at, ban_kind, ban_reason, ban_expires_at,
+               created_at
+        FROM players
+        WHERE id = ?1
+           OR display_name LIKE ?2 ESCAPE '\'
+           OR username LIKE ?2 ESCAPE '\'
+        ORDER BY created_at DESC
+        LIMIT ?3
+        "#,
+    )
+    .bind(exact_id)
+    .bind(pattern)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get_score_components(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<Option<ScoreComponents>, sqlx::Error> {
+    let query = format!(
+        r#"
+        SELECT
+            {score} AS score,
+            sc.total_money_earned,
+            sc.reputation,
+            sc.skill_levels_sum,
+            sc.consultants_count,
+            sc.ai_tool_tiers_sum,
+            sc.manual_tasks_completed,
+            sc.updated_at
+        FROM score_components sc
+        WHERE sc.player_id = ?1
+        "#,
+        score = SCORE_FORMULA
+    );
+
+    sqlx::query_as::<_, ScoreComponents>(&query)
+        .bind(player_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn get_save_metadata(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<Option<SaveMetadata>, sqlx::Error> {
+    sqlx::query_as::<_, SaveMetadata>(
+        r#"
+        SELECT version, size_bytes, stored_bytes,
+               save_data_zstd IS NOT NULL AS compressed, updated_at
+        FROM saves
+        WHERE player_id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await
+}
+
+async fn insert_audit(
+    conn: &mut sqlx::SqliteConnection,
+    admin_id: Option<Uuid>,
+    action: &str,
+    target_player_id: Option<Uuid>,
+    details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO admin_audit_log (admin_id, action, target_player_id, details, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        "#,
+    )
+    .bind(admin_id)
+    .bind(action)
+    .bind(target_player_id)
+    .bind(Json(details))
+    .bind(Utc::now())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_hidden_by_admin(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    hidden: bool,
+    details: serde_json::Value,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET hidden_by_admin = ?2,
+            updated_at = ?3
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(hidden)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let action = if hidden { "hide" } else { "unhide" };
+    insert_audit(&mut tx, admin_id, action, Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn reset_scores(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    details: serde_json::Value,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE score_components
+        SET total_money_earned = 0,
+            reputation = 0,
+            skill_levels_sum = 0,
+            consultants_count = 0,
+            ai_tool_tiers_sum = 0,
+            manual_tasks_completed = 0,
+            updated_at = ?2
+        WHERE player_id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_audit(&mut tx, admin_id, "reset_scores", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn ban_player(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    kind: BanKind,
+    reason: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET banned_at = ?5,
+            ban_kind = ?2,
+            ban_reason = ?3,
+            ban_expires_at = ?4,
+            updated_at = ?5
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(kind)
+    .bind(reason)
+    .bind(expires_at)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let details = serde_json::json!({
+        "kind": kind,
+        "reason": reason,
+        "expires_at": expires_at,
+    });
+    insert_audit(&mut tx, admin_id, "ban", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn unban_player(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    details: serde_json::Value,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET banned_at = NULL,
+            ban_kind = NULL,
+            ban_reason = NULL,
+            ban_expires_at = NULL,
+            updated_at = ?2
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    insert_audit(&mut tx, admin_id, "unban", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn set_role(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    player_id: Uuid,
+    role: Role,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        r#"
+        UPDATE players
+        SET role = ?2,
+            updated_at = ?3
+        WHERE id = ?1
+        "#,
+    )
+    .bind(player_id)
+    .bind(role)
+    .bind(Utc::now())
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let details = serde_json::json!({ "role": role });
+    insert_audit(&mut tx, admin_id, "set_role", Some(player_id), details).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn add_audit_entry(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    action: &str,
+    target_player_id: Option<Uuid>,
+    details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    insert_audit(&mut conn, admin_id, action, target_player_id, details).await
+}
+
+pub async fn get_audit_log(
+    pool: &SqlitePool,
+    target_player_id: Option<Uuid>,
+    limit: i64,
+) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AuditLogEntry>(
+        r#"
+        SELECT id, admin_id, action, target_player_id, details, created_at
+        FROM admin_audit_log
+        WHERE ?1 IS NULL OR target_player_id = ?1
+        ORDER BY id DESC
+        LIMIT ?2
+        "#,
+    )
+    .bind(target_player_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+// ── Idempotency ──
+
+pub async fn claim_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+    player_id: Uuid,
+    fingerprint: &str,
+    expired_before: DateTime<Utc>,
+    abandoned_before: DateTime<Utc>,
+) -> Result<Option<IdempotentRequest>, sqlx::Error> {
+    sqlx::query(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE key = ?1 AND player_id = ?2
+          AND (created_at < ?3 OR (status IS NULL AND created_at < ?4))
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(expired_before)
+    .bind(abandoned_before)
+    .execute(pool)
+    .await?;
+
+    let claimed = sqlx::query(
+        r#"
+        INSERT INTO idempotency_keys (key, player_id, fingerprint, created_at)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT (key, player_id) DO NOTHING
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(fingerprint)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+    if claimed.rows_affected() == 1 {
+        return Ok(None);
+    }
+
+    let holder = sqlx::query_as::<_, IdempotentRequest>(
+        r#"
+        SELECT fingerprint, status, content_type, body
+        FROM idempotency_keys
+        WHERE key = ?1 AND player_id = ?2
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(Some(holder.unwrap_or_else(|| IdempotentRequest {
+        fingerprint: fingerprint.to_string(),
+        status: None,
+        content_type: None,
+        body: None,
+    })))
+}
+
+pub async fn complete_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+    player_id: Uuid,
+    status: i16,
+    content_type: Option<&str>,
+    body: &[u8],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE idempotency_keys
+        SET status = ?3,
+            content_type = ?4,
+            body = ?5
+        WHERE key = ?1 AND player_id = ?2
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(status)
+    .bind(content_type)
+    .bind(body)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn release_idempotency_key(
+    pool: &SqlitePool,
+    key: &str,
+    player_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM idempotency_keys WHERE key = ?1 AND player_id = ?2")
+        .bind(key)
+        .bind(player_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn prune_idempotency_keys(
+    pool: &SqlitePool,
+    cutoff: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE created_at < ?1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ── Notifications ──
+
+pub async fn add_notification(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    kind: NotificationKind,
+    data: serde_json::Value,
+) -> Result<Notification, sqlx::Error> {
+    sqlx::query_as::<_, Notification>(
+        r#"
+        INSERT INTO notifications (player_id, kind, data, created_at)
+        VALUES (?1, ?2, ?3, ?4)
+        RETURNING id, player_id, kind, data, created_at, read_at
+        "#,
+    )
+    .bind(player_id)
+    .bind(kind)
+    .bind(Json(data))
+    .bind(Utc::now())
+    .fetch_one(pool)
+    .await
+}
+
+/// Uuids are stored as blobs, so a list of them goes in as a JSON array of
+/// `hex(id)` values for `json_each`.
+fn hex_ids(ids: &[Uuid]) -> String {
+    let hex: Vec<String> = ids
+        .iter()
+        .map(|id| id.simple().to_string().to_uppercase())
+        .collect();
+    serde_json::to_string(&hex).expect("strings serialize")
+}
+
+pub async fn add_notifications(
+    pool: &SqlitePool,
+    kind: NotificationKind,
+    notifications: &[(Uuid, serde_json::Value)],
+) -> Result<Vec<Notification>, sqlx::Error> {
+    let rows: Vec<serde_json::Value> = notifications
+        .iter()
+        .map(|(id, data)| {
+            let id = id.simple().to_string().to_uppercase();
+            serde_json::json!({ "player": id, "data": data })
+        })
+        .collect();
+    sqlx::query_as::<_, Notification>(
+        r#"
+        INSERT INTO notifications (player_id, kind, data, created_at)
+        SELECT p.id, ?2, json_extract(n.value, '$.data'), ?3
+        FROM json_each(?1) n
+        JOIN players p ON hex(p.id) = json_extract(n.value, '$.player')
+        RETURNING id, player_id, kind, data, created_at, read_at
+        "#,
+    )
+    .bind(Json(rows))
+    .bind(kind)
+    .bind(Utc::now())
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn add_notification_batch(
+    pool: &SqlitePool,
+    kind: NotificationKind,
+    data: serde_json::Value,
+    after: Option<Uuid>,
+    skip: &[Uuid],
+    limit: i64,
+) -> Result<(u64, Option<Uuid>), sqlx::Error> {
+    let last: Option<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT id FROM (
+            SELECT id FROM players
+            WHERE ?1 IS NULL OR id > ?1
+            ORDER BY id
+            LIMIT ?2
+        )
+        ORDER BY id DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_optional(pool)
+    .await?;
+    let Some(last) = last else {
+        return Ok((0, None));
+    };
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO notifications (player_id, kind, data, created_at)
+        SELECT id, ?4, ?5, ?6 FROM players
+        WHERE (?1 IS NULL OR id > ?1)
+          AND id <= ?2
+          AND hex(id) NOT IN (SELECT value FROM json_each(?3))
+        "#,
+    )
+    .bind(after)
+    .bind(last)
+    .bind(hex_ids(skip))
+    .bind(kind)
+    .bind(Json(data))
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok((result.rows_affected(), Some(last)))
+}
+
+pub async fn list_notifications(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    unread_only: bool,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<Notification>, sqlx::Error> {
+    sqlx::query_as::<_, Notification>(
+        r#"
+        SELECT id, player_id, kind, data, created_at, read_at
+        FROM notifications
+        WHERE player_id = ?1
+          AND (NOT ?2 OR read_at IS NULL)
+          AND (?3 IS NULL OR id < ?3)
+        ORDER BY id DESC
+        LIMIT ?4
+        "#,
+    )
+    .bind(player_id)
+    .bind(unread_only)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn count_unread_notifications(
+    pool: &SqlitePool,
+    player_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM notifications WHERE player_id = ?1 AND read_at IS NULL",
+    )
+    .bind(player_id)
+    .fetch_one(pool)
+    .await
+}
+
+/// The ids go in as a JSON array, since SQLite has no array parameters.
+pub async fn mark_notifications_read(
+    pool: &SqlitePool,
+    player_id: Uuid,
+    ids: Option<&[i64]>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE notifications
+        SET read_at = ?3
+        WHERE player_id = ?1
+          AND read_at IS NULL
+          AND (?2 IS NULL OR id IN (SELECT value FROM json_each(?2)))
+        "#,
+    )
+    .bind(player_id)
+    .bind(ids.map(Json))
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn prune_notifications(
+    pool: &SqlitePool,
+    cutoff: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM notifications WHERE created_at < ?1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ── Remote config ──
+
+pub async fn publish_config(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    document: &ConfigDocument,
+    note: &str,
+    rollback_of: Option<i64>,
+) -> Result<ConfigVersion, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let published = sqlx::query_as::<_, ConfigVersion>(
+        r#"
+        INSERT INTO config_versions (document, note, published_by, published_at)
+        VALUES (?1, ?2, ?3, ?4)
+        RETURNING version, document, note, published_by, published_at
+        "#,
+    )
+    .bind(Json(document))
+    .bind(note)
+    .bind(admin_id)
+    .bind(Utc::now())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let (action, details) = super::config_audit(&published, rollback_of);
+    insert_audit(&mut tx, admin_id, action, None, details).await?;
+
+    tx.commit().await?;
+    Ok(published)
+}
+
+pub async fn get_config_version(
+    pool: &SqlitePool,
+    version: Option<i64>,
+) -> Result<Option<ConfigVersion>, sqlx::Error> {
+    sqlx::query_as::<_, ConfigVersion>(
+        r#"
+        SELECT version, document, note, published_by, published_at
+        FROM config_versions
+        WHERE ?1 IS NULL OR version = ?1
+        ORDER BY version DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(version)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn list_config_versions(
+    pool: &SqlitePool,
+    limit: i64,
+) -> Result<Vec<ConfigVersion>, sqlx::Error> {
+    sqlx::query_as::<_, ConfigVersion>(
+        r#"
+        SELECT version, document, note, published_by, published_at
+        FROM config_versions
+        ORDER BY version DESC
+        LIMIT ?1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+// ── Experiments ──
+
+pub async fn create_experiment(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    key: &str,
+    description: &str,
+    variants: &[ExperimentVariant],
+) -> Result<Experiment, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        r#"
+        INSERT INTO experiments (key, description, variants, created_by, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        RETURNING key, description, variants, created_by, created_at, ended_at
+        "#,
+    )
+    .bind(key)
+    .bind(description)
+    .bind(Json(variants))
+    .bind(admin_id)
+    .bind(Utc::now())
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn get_experiment(
+    pool: &SqlitePool,
+    key: &str,
+) -> Result<Option<Experiment>, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        r#"
+        SELECT key, description, variants, created_by, created_at, ended_at
+        FROM experiments
+        WHERE key = ?1
+        "#,
+    )
+    .bind(key)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn list_experiments(
+    pool: &SqlitePool,
+    running_only: bool,
+) -> Result<Vec<Experiment>, sqlx::Error> {
+    sqlx::query_as::<_, Experiment>(
+        r#"
+        SELECT key, description, variants, created_by, created_at, ended_at
+        FROM experiments
+        WHERE NOT ?1 OR ended_at IS NULL
+        ORDER BY created_at DESC, key
+        "#,
+    )
+    .bind(running_only)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn end_experiment(
+    pool: &SqlitePool,
+    admin_id: Option<Uuid>,
+    key: &str,
+) -> Result<Option<Experiment>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let ended = sqlx::query_as::<_, Experiment>(
+        r#"
+        UPDATE experiments
+        SET ended_at = COALESCE(ended_at, ?2)
+        WHERE key = ?1
+        RETURNING key, description, variants, created_by, created_at, ended_at
+        "#,
+    )
+    .bind(key)
+    .bind(Utc::now())
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(ended) = ended else {
+        return Ok(None);
+    };
+
+    let details = serde_json::json!({ "key": key, "ended_at": ended.ended_at });
+    insert_audit(&mut tx, admin_id, "end_experiment", None, details).await?;
+
+    tx.commit().await?;
+    Ok(Some(ended))
+}
+
+pub async fn record_exposure(
+    pool: &SqlitePool,
+    key: &str,
+    player_id: Uuid,
+    variant: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO experiment_exposures (experiment_key, player_id, variant, exposed_at)
+        VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT (experiment_key, player_id) DO NOTHING
+        "#,
+    )
+    .bind(key)
+    .bind(player_id)
+    .bind(variant)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn experiment_metrics(
+    pool: &SqlitePool,
+    key: &str,
+) -> Result<Vec<VariantMetrics>, sqlx::Error> {
+    let query = format!(
+        r#"
+        SELECT
+            e.variant,
+            COUNT(*) AS players,
+            AVG({score}) AS avg_score,
+            AVG(sc.total_money_earned) AS avg_total_money_earned,
+            AVG(sc.reputation) AS avg_reputation,
+            AVG(sc.skill_levels_sum) AS avg_skill_levels_sum,
+            AVG(sc.consultants_count) AS avg_consultants_count,
+            AVG(sc.ai_tool_tiers_sum) AS avg_ai_tool_tiers_sum,
+            AVG(sc.manual_tasks_completed) AS avg_manual_tasks_completed
+        FROM experiment_exposures e
+        JOIN score_components sc ON sc.player_id = e.player_id
+        JOIN players p ON p.id = e.player_id
+        WHERE e.experiment_key = ?3 AND {ranked}
+        GROUP BY e.variant
+        "#,
+        score = SCORE_FORMULA,
+        ranked = RANKED_PLAYERS
+    );
+
+    sqlx::query_as::<_, VariantMetrics>(&query)
+        .bind(None::<Uuid>)
+        .bind(Utc::now())
+        .bind(key)
+        .fetch_all(pool)
+        .await
+}
+
+// ── Maintenance ──
+
+pub async fn players_with_saves(pool: &SqlitePool) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar("SELECT player_id FROM saves ORDER BY updated_at")
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn count_saves_before(
+    pool: &SqlitePool,
+    cutoff: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM saves WHERE updated_at < ?1")
+        .bind(cutoff)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn delete_saves_before(
+    pool: &SqlitePool,
+    cutoff: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM saves WHERE updated_at < ?1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+// ── Health ──
+
+/// The SQLite mirror of `migrations/`, kept at the same version numbers.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations_sqlite");
+
+pub async fn ping(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+pub async fn pending_migrations(pool: &SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    let applied: Vec<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .map(|m| m.version)
+        .filter(|v| !applied.contains(v))
+        .collect())
+}
@@ -0,0 +1,48 @@
+//! Conditional GETs for JSON documents the game polls, so an unchanged
+//! document costs a 304 instead of the whole body.
+
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+/// `body` with an ETag of its contents, or 304 if the request's
+/// `If-None-Match` has that tag. `cache_headers` go on either response.
+pub fn json_or_not_modified<const N: usize>(
+    request: &HeaderMap,
+    body: Bytes,
+    cache_headers: [(HeaderName, HeaderValue); N],
+) -> Response {
+    let etag = format!("W/\"{:.32x}\"", Sha256::digest(&body));
+    let etag = HeaderValue::from_str(&etag).expect("hex is a valid header");
+    let unchanged = request
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|tags| etag_matches(tags, &etag));
+    let headers = ([(header::ETAG, etag)], cache_headers);
+    if unchanged {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    (
+        headers,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )],
+        body,
+    )
+        .into_response()
+}
+
+/// Weak comparison against an `If-None-Match` list, as for GET.
+fn etag_matches(tags: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(tags) = tags.to_str() else {
+        return false;
+    };
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_owned();
+    let ours = opaque(etag.to_str().unwrap_or_default());
+    tags.split(',')
+        .any(|tag| tag.trim() == "*" || opaque(tag) == ours)
+}
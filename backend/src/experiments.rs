@@ -0,0 +1,246 @@
+//! A/B experiments. A player's variant is picked by a hash of the
+//! experiment key and their id, so it never changes and needs no storage.
+//! The game reports when it first shows a player their variant; reports
+//! compare the exposed players' score components per variant.
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthPlayer,
+    error::{ApiError, ErrorBody, FieldErrors, Json, Path},
+    models::*,
+    remote_config,
+    repo::Repository,
+    AppState,
+};
+
+const MAX_VARIANTS: usize = 10;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/experiments", get(get_assignments))
+        .route("/experiments/{key}/exposures", post(record_exposure))
+}
+
+/// The variant `player_id` is in.
+pub fn assign(experiment: &Experiment, player_id: Uuid) -> &str {
+    let total: u64 = experiment
+        .variants
+        .iter()
+        .map(|v| u64::from(v.weight))
+        .sum();
+    // Prefixed so an experiment and a config rollout with the same key
+    // don't pick the same players
+    let salt = format!("experiment:{}", experiment.key);
+    let mut position = remote_config::bucket(&salt, player_id, total.max(1));
+    for variant in &experiment.variants {
+        match position.checked_sub(variant.weight.into()) {
+            Some(rest) => position = rest,
+            None => return &variant.name,
+        }
+    }
+    &experiment.variants[0].name
+}
+
+/// Validate a new experiment.
+pub fn check_experiment(errors: &mut FieldErrors, req: &CreateExperimentRequest) {
+    errors.check(
+        "key",
+        (1..=64).contains(&req.key.len())
+            && req
+                .key
+                .bytes()
+                .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' | b'.')),
+        "must be 1 to 64 lowercase letters, digits, '_', '-' or '.'",
+    );
+    errors.check(
+        "description",
+        req.description.chars().count() <= 500,
+        "must be at most 500 characters",
+    );
+    errors.check(
+        "variants",
+        (2..=MAX_VARIANTS).contains(&req.variants.len()),
+        format!("must have 2 to {MAX_VARIANTS} variants"),
+    );
+    for (i, variant) in req.variants.iter().enumerate() {
+        errors.check(
+            "variants",
+            !variant.name.trim().is_empty() && variant.name.chars().count() <= 32,
+            format!("#{i}: name must be 1 to 32 characters"),
+        );
+        errors.check(
+            "variants",
+            req.variants[..i].iter().all(|v| v.name != variant.name),
+            format!("#{i}: {:?} is listed twice", variant.name),
+        );
+        errors.check(
+            "variants",
+            (1..=1000).contains(&variant.weight),
+            format!("#{i}: weight must be 1 to 1000"),
+        );
+    }
+}
+
+/// Each variant's metrics, in the experiment's order, with the change in
+/// average score against the first (control) variant.
+pub async fn report(db: &dyn Repository, key: &str) -> Result<Option<ExperimentReport>, ApiError> {
+    let Some(experiment) = db.get_experiment(key).await? else {
+        return Ok(None);
+    };
+    let mut metrics = db.experiment_metrics(key).await?;
+
+    let mut variants: Vec<VariantReport> = experiment
+        .variants
+        .iter()
+        .map(|variant| {
+            let metrics = match metrics.iter().position(|m| m.variant == variant.name) {
+                Some(i) => metrics.swap_remove(i),
+                None => VariantMetrics {
+                    variant: variant.name.clone(),
+                    ..Default::default()
+                },
+            };
+            VariantReport {
+                metrics,
+                score_change_vs_control: None,
+            }
+        })
+        .collect();
+    let control = variants[0].metrics.avg_score;
+    if control > 0.0 {
+        for variant in &mut variants[1..] {
+            variant.score_change_vs_control = Some(variant.metrics.avg_score / control - 1.0);
+        }
+    }
+
+    Ok(Some(ExperimentReport {
+        experiment,
+        variants,
+    }))
+}
+
+/// GET /api/experiments — The caller's variant in every running experiment.
+#[utoipa::path(
+    get,
+    path = "/api/experiments",
+    tag = "experiments",
+    responses(
+        (status = 200, body = ExperimentAssignments),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+pub async fn get_assignments(
+    AuthPlayer(player_id): AuthPlayer,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let experiments = state.db.list_experiments(true).await?;
+    let assignments = experiments
+        .iter()
+        .map(|e| (e.key.clone(), assign(e, player_id).to_owned()))
+        .collect();
+
+    Ok(Json(ExperimentAssignments { assignments }))
+}
+
+/// POST /api/experiments/{key}/exposures — Note that the caller was shown their variant.
+#[utoipa::path(
+    post,
+    path = "/api/experiments/{key}/exposures",
+    tag = "experiments",
+    params(("key" = String, Path, description = "Experiment key")),
+    responses(
+        (status = 200, description = "Recorded, or already had been", body = ExposureRecorded),
+        (status = 404, description = "No such experiment", body = ErrorBody),
+        (status = 409, description = "The experiment has ended", body = ErrorBody),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+pub async fn record_exposure(
+    AuthPlayer(player_id): AuthPlayer,
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let experiment = state
+        .db
+        .get_experiment(&key)
+        .await?
+        .ok_or_else(experiment_not_found)?;
+    if experiment.ended_at.is_some() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "experiment_ended",
+            "The experiment has ended",
+        ));
+    }
+
+    // The variant is worked out here rather than taken from the client
+    let variant = assign(&experiment, player_id);
+    state.db.record_exposure(&key, player_id, variant).await?;
+    metrics::counter!("experiment_exposures_total", "experiment" => key.clone()).increment(1);
+
+    Ok(Json(ExposureRecorded {
+        variant: variant.to_owned(),
+        experiment: key,
+    }))
+}
+
+pub fn experiment_not_found() -> ApiError {
+    ApiError::not_found("experiment_not_found", "No experiment with that key")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn experiment(weights: &[u32]) -> Experiment {
+        Experiment {
+            key: "payouts".into(),
+            description: String::new(),
+            variants: weights
+                .iter()
+                .enumerate()
+                .map(|(i, &weight)| ExperimentVariant {
+                    name: format!("v{i}"),
+                    weight,
+                })
+                .collect(),
+            created_by: None,
+            created_at: Utc::now(),
+            ended_at: None,
+        }
+    }
+
+    #[test]
+    fn assignment_is_stable_and_follows_the_weights() {
+        let experiment = experiment(&[1, 3]);
+        let players: Vec<_> = (0..2000).map(|_| Uuid::new_v4()).collect();
+        let in_control = players
+            .iter()
+            .filter(|&&id| assign(&experiment, id) == "v0")
+            .count();
+        assert!((400..=600).contains(&in_control), "{in_control}");
+        for &id in &players[..20] {
+            assert_eq!(assign(&experiment, id), assign(&experiment, id));
+        }
+
+        let mut renamed = experiment.clone();
+        renamed.key = "other".into();
+        let moved = players
+            .iter()
+            .filter(|&&id| assign(&experiment, id) != assign(&renamed, id))
+            .count();
+        assert!(moved > 0);
+    }
+}
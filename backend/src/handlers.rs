@@ -4,20 +4,45 @@ use argon2::{
 };
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{header, StatusCode},
     response::IntoResponse,
-    Json,
 };
 use rand::Rng;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
 use uuid::Uuid;
 
 use crate::{
-    auth::{create_token, AuthPlayer, OptionalAuthPlayer},
-    db,
+    auth::{
+        create_challenge_token, create_token, reject_banned, verify_challenge_token, AuthPlayer,
+        OptionalAuthPlayer,
+    },
+    error::{ApiError, ErrorBody, FieldErrors, Json},
     models::*,
-    AppState,
+    notifications, totp, AppState,
 };
 
+const TOTP_ISSUER: &str = "Consultancy Tycoon";
+
+/// How many players the leaderboard shows.
+pub const LEADERBOARD_SIZE: i64 = 50;
+
+/// How long readiness waits for the database before calling it unavailable.
+const READY_DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Second-factor codes a player can try before their challenges are
+/// revoked and they have to start over from the password. Counted per
+/// player rather than per IP, so spreading guesses over many addresses
+/// doesn't help.
+const MAX_TWO_FACTOR_ATTEMPTS: i64 = 5;
+
+const MAX_DISPLAY_NAME_CHARS: usize = 32;
+const MIN_USERNAME_CHARS: usize = 3;
+const MAX_USERNAME_CHARS: usize = 32;
+const MIN_PASSWORD_CHARS: usize = 8;
+
 const ADJECTIVES: &[&str] = &[
     "BRAVE", "CALM", "DARK", "FAST", "GOLD", "HAPPY", "ICY", "KEEN", "LOUD", "MILD",
     "NEAT", "ODD", "PINK", "QUICK", "RED", "SAFE", "TALL", "VAST", "WARM", "ZESTY",
@@ -28,7 +53,7 @@ const NOUNS: &[&str] = &[
     "LION", "MOON", "NEWT", "OWL", "PIKE", "QUAIL", "ROSE", "STAR", "TOAD", "WOLF",
 ];
 
-fn generate_passphrase() -> String {
+pub(crate) fn generate_passphrase() -> String {
     let mut rng = rand::rng();
     let adj = ADJECTIVES[rng.random_range(0..ADJECTIVES.len())];
     let noun = NOUNS[rng.random_range(0..NOUNS.len())];
@@ -36,20 +61,96 @@ fn generate_passphrase() -> String {
     format!("{adj}-{noun}-{num}")
 }
 
+fn check_display_name(errors: &mut FieldErrors, display_name: &str) {
+    let len = display_name.trim().chars().count();
+    errors.check(
+        "display_name",
+        (1..=MAX_DISPLAY_NAME_CHARS).contains(&len),
+        format!("must be 1-{MAX_DISPLAY_NAME_CHARS} characters"),
+    );
+}
+
+/// Rules for the username and password of a registered account.
+pub(crate) fn check_credentials(errors: &mut FieldErrors, username: &str, password: &str) {
+    let username_len = username.chars().count();
+    errors.check(
+        "username",
+        (MIN_USERNAME_CHARS..=MAX_USERNAME_CHARS).contains(&username_len),
+        format!("must be {MIN_USERNAME_CHARS}-{MAX_USERNAME_CHARS} characters"),
+    );
+    errors.check(
+        "username",
+        username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')),
+        "may only contain letters, digits, '_', '-' and '.'",
+    );
+    errors.check(
+        "password",
+        password.chars().count() >= MIN_PASSWORD_CHARS,
+        format!("must be at least {MIN_PASSWORD_CHARS} characters"),
+    );
+}
+
+pub(crate) fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// Count a failed login by `reason`; the client only ever sees `invalid_credentials`.
+fn login_failed(reason: &'static str) -> ApiError {
+    metrics::counter!("login_failures_total", "reason" => reason).increment(1);
+    ApiError::unauthorized("invalid_credentials", "Wrong username or password")
+}
+
+fn invalid_code() -> ApiError {
+    ApiError::unauthorized("invalid_code", "The code is wrong, expired or already used")
+}
+
+/// The authenticated player's row. Only missing if the player was deleted
+/// after their token was issued.
+async fn current_player(state: &AppState, player_id: Uuid) -> Result<Player, ApiError> {
+    state
+        .db
+        .find_player_by_id(player_id)
+        .await?
+        .ok_or_else(ApiError::missing_auth)
+}
+
 /// POST /api/players — Create a new anonymous player.
+#[utoipa::path(
+    post,
+    path = "/api/players",
+    tag = "players",
+    request_body = CreatePlayerRequest,
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries replay the first response. At least 32 characters, e.g. a random UUID, since it is the only thing that lets a client replay its response")),
+    responses(
+        (status = 200, body = CreatePlayerResponse),
+        (status = 409, description = "Same Idempotency-Key still in flight", body = ErrorBody),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+        (status = 429, description = "Rate limited", body = ErrorBody),
+    ),
+)]
 pub async fn create_player(
     State(state): State<AppState>,
     Json(req): Json<CreatePlayerRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, ApiError> {
+    let mut errors = FieldErrors::default();
+    check_display_name(&mut errors, &req.display_name);
+    errors.into_result()?;
+
     let id = Uuid::new_v4();
     let passphrase = generate_passphrase();
 
-    db::create_player(&state.db, id, &req.display_name, &passphrase)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state
+        .db
+        .create_player(id, req.display_name.trim(), &passphrase)
+        .await?;
+    metrics::counter!("players_created_total").increment(1);
 
-    let token =
-        create_token(id, &state.jwt_secret).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let token = create_token(id, &state.jwt, state.config.auth.session_lifetime())?;
 
     Ok(Json(CreatePlayerResponse {
         id,
@@ -59,73 +160,202 @@ pub async fn create_player(
 }
 
 /// POST /api/players/recover — Recover account by passphrase.
+/// Accounts with 2FA enabled get a challenge token to redeem at /api/players/login/2fa.
+#[utoipa::path(
+    post,
+    path = "/api/players/recover",
+    tag = "players",
+    request_body = RecoverRequest,
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 403, description = "Account banned", body = ErrorBody),
+        (status = 404, description = "Unknown passphrase", body = ErrorBody),
+    ),
+)]
 pub async fn recover_player(
     State(state): State<AppState>,
     Json(req): Json<RecoverRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let player = db::find_player_by_passphrase(&state.db, &req.passphrase)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
-
-    let token = create_token(player.id, &state.jwt_secret)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<impl IntoResponse, ApiError> {
+    let player = state
+        .db
+        .find_player_by_passphrase(&req.passphrase)
+        .await?
+        .ok_or_else(|| {
+            ApiError::not_found("unknown_passphrase", "No player has that passphrase")
+        })?;
 
-    Ok(Json(AuthResponse {
-        id: player.id,
-        display_name: player.display_name,
-        token,
-    }))
+    Ok(Json(first_factor_passed(&state, player).await?))
 }
 
 /// POST /api/players/register — Upgrade anonymous account with username/password. Requires auth.
+#[utoipa::path(
+    post,
+    path = "/api/players/register",
+    tag = "players",
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "Registered"),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 409, description = "Username taken", body = ErrorBody),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
 pub async fn register(
     AuthPlayer(player_id): AuthPlayer,
     State(state): State<AppState>,
     Json(req): Json<RegisterRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, ApiError> {
+    let mut errors = FieldErrors::default();
+    check_credentials(&mut errors, &req.username, &req.password);
+    errors.into_result()?;
+
+    let username_taken = || ApiError::conflict("username_taken", "That username is already taken");
+
     // Check if username is already taken
-    let existing = db::find_player_by_username(&state.db, &req.username)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let existing = state.db.find_player_by_username(&req.username).await?;
     if existing.is_some() {
-        return Err(StatusCode::CONFLICT);
+        return Err(username_taken());
     }
 
-    // Hash password
-    let salt = SaltString::generate(&mut OsRng);
-    let password_hash = Argon2::default()
-        .hash_password(req.password.as_bytes(), &salt)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .to_string();
+    let password_hash = hash_password(&req.password)?;
 
-    db::set_credentials(&state.db, player_id, &req.username, &password_hash)
+    // A concurrent registration can still win the race for the username
+    state
+        .db
+        .set_credentials(player_id, &req.username, &password_hash)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| match ApiError::from(e) {
+            e if e.code() == "conflict" => username_taken(),
+            e => e,
+        })?;
 
     Ok(StatusCode::OK)
 }
 
 /// POST /api/players/login — Login with username/password.
+/// Accounts with 2FA enabled get a challenge token to redeem at /api/players/login/2fa.
+#[utoipa::path(
+    post,
+    path = "/api/players/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, body = LoginResponse),
+        (status = 401, description = "Wrong username or password", body = ErrorBody),
+        (status = 403, description = "Account banned", body = ErrorBody),
+    ),
+)]
 pub async fn login(
     State(state): State<AppState>,
     Json(req): Json<LoginRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let player = db::find_player_by_username(&state.db, &req.username)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+) -> Result<impl IntoResponse, ApiError> {
+    let player = state
+        .db
+        .find_player_by_username(&req.username)
+        .await?
+        .ok_or_else(|| login_failed("unknown_user"))?;
 
-    let stored_hash = player.password_hash.ok_or(StatusCode::UNAUTHORIZED)?;
-    let parsed_hash =
-        PasswordHash::new(&stored_hash).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stored_hash = player
+        .password_hash
+        .as_deref()
+        .ok_or_else(|| login_failed("unknown_user"))?;
+    let parsed_hash = PasswordHash::new(stored_hash)?;
 
     Argon2::default()
         .verify_password(req.password.as_bytes(), &parsed_hash)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+        .map_err(|_| login_failed("wrong_password"))?;
+
+    Ok(Json(first_factor_passed(&state, player).await?))
+}
 
-    let token = create_token(player.id, &state.jwt_secret)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+/// A session token, or a challenge if the player also needs a second factor.
+/// Both the password and the recovery passphrase only count as the first.
+/// Hard-banned players get neither.
+async fn first_factor_passed(state: &AppState, player: Player) -> Result<LoginResponse, ApiError> {
+    reject_banned(state, player.id).await?;
+    if player.totp_enabled {
+        let challenge_id = Uuid::new_v4();
+        let lifetime = state.config.auth.challenge_lifetime();
+        state
+            .db
+            .create_login_challenge(challenge_id, player.id, chrono::Utc::now() + lifetime)
+            .await?;
+        let challenge_token =
+            create_challenge_token(player.id, challenge_id, &state.jwt, lifetime)?;
+        return Ok(LoginResponse::TwoFactorRequired(TwoFactorChallenge {
+            two_factor_required: true,
+            challenge_token,
+        }));
+    }
+
+    let token = create_token(player.id, &state.jwt, state.config.auth.session_lifetime())?;
+
+    Ok(LoginResponse::Authenticated(AuthResponse {
+        id: player.id,
+        display_name: player.display_name,
+        token,
+    }))
+}
+
+/// POST /api/players/login/2fa — Complete a two-factor login with a TOTP or backup code.
+/// Each challenge logs in once, and too many wrong codes revoke it.
+#[utoipa::path(
+    post,
+    path = "/api/players/login/2fa",
+    tag = "auth",
+    request_body = TwoFactorLoginRequest,
+    responses(
+        (status = 200, body = AuthResponse),
+        (status = 401, description = "Invalid challenge or code", body = ErrorBody),
+        (status = 403, description = "Account banned", body = ErrorBody),
+    ),
+)]
+pub async fn login_two_factor(
+    State(state): State<AppState>,
+    Json(req): Json<TwoFactorLoginRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let invalid_challenge = || {
+        ApiError::unauthorized(
+            "invalid_challenge",
+            "The login challenge is invalid or has expired",
+        )
+    };
+
+    let claims = verify_challenge_token(&req.challenge_token, &state.jwt)
+        .map_err(|_| invalid_challenge())?;
+    let challenge_id = claims.jti.ok_or_else(invalid_challenge)?;
+    if state.db.login_challenge_player(challenge_id).await? != Some(claims.sub) {
+        return Err(invalid_challenge());
+    }
+
+    let player = state
+        .db
+        .find_player_by_id(claims.sub)
+        .await?
+        .ok_or_else(invalid_challenge)?;
+
+    // Counted before checking so concurrent guesses can't slip past the limit
+    let attempts = state.db.record_two_factor_attempt(player.id).await?;
+    if attempts > MAX_TWO_FACTOR_ATTEMPTS {
+        state.db.revoke_login_challenges(player.id).await?;
+        return Err(invalid_challenge());
+    }
+
+    if !check_second_factor(&state, &player, &req.code).await? {
+        metrics::counter!("login_failures_total", "reason" => "second_factor").increment(1);
+        if attempts == MAX_TWO_FACTOR_ATTEMPTS {
+            state.db.revoke_login_challenges(player.id).await?;
+        }
+        return Err(invalid_code());
+    }
+    if !state.db.consume_login_challenge(challenge_id).await? {
+        return Err(invalid_challenge());
+    }
+    // The ban may have come after the challenge
+    reject_banned(&state, player.id).await?;
+
+    let token = create_token(player.id, &state.jwt, state.config.auth.session_lifetime())?;
 
     Ok(Json(AuthResponse {
         id: player.id,
@@ -134,85 +364,462 @@ pub async fn login(
     }))
 }
 
+/// POST /api/players/2fa/enroll — Start 2FA enrollment. Requires a registered account.
+#[utoipa::path(
+    post,
+    path = "/api/players/2fa/enroll",
+    tag = "auth",
+    responses(
+        (status = 200, body = TotpEnrollResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 409, description = "2FA already enabled", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+pub async fn enroll_totp(
+    AuthPlayer(player_id): AuthPlayer,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let player = current_player(&state, player_id).await?;
+
+    // 2FA protects the password login, so anonymous accounts have nothing to protect
+    let username = player.username.ok_or_else(|| {
+        ApiError::bad_request(
+            "registration_required",
+            "Register a username and password before enabling 2FA",
+        )
+    })?;
+    if player.totp_enabled {
+        return Err(ApiError::conflict(
+            "two_factor_already_enabled",
+            "2FA is already enabled",
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    state.db.set_pending_totp_secret(player_id, &secret).await?;
+
+    Ok(Json(TotpEnrollResponse {
+        otpauth_uri: totp::otpauth_uri(&secret, &username, TOTP_ISSUER),
+        secret,
+    }))
+}
+
+/// POST /api/players/2fa/verify — Confirm enrollment with a first code; returns backup codes once.
+#[utoipa::path(
+    post,
+    path = "/api/players/2fa/verify",
+    tag = "auth",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, body = BackupCodesResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+pub async fn verify_totp(
+    AuthPlayer(player_id): AuthPlayer,
+    State(state): State<AppState>,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let player = current_player(&state, player_id).await?;
+
+    if player.totp_enabled {
+        return Err(ApiError::conflict(
+            "two_factor_already_enabled",
+            "2FA is already enabled",
+        ));
+    }
+    let secret = player.totp_secret.ok_or_else(|| {
+        ApiError::bad_request(
+            "two_factor_not_enrolled",
+            "Start enrollment at /api/players/2fa/enroll first",
+        )
+    })?;
+
+    let step = totp::verify(&secret, &req.code, unix_now(), None).ok_or_else(invalid_code)?;
+
+    let backup_codes = totp::generate_backup_codes();
+    let hashes: Vec<String> = backup_codes
+        .iter()
+        .map(|c| totp::hash_backup_code(c))
+        .collect();
+
+    state
+        .db
+        .enable_totp(player_id, step as i64, &hashes)
+        .await?;
+
+    Ok(Json(BackupCodesResponse { backup_codes }))
+}
+
+/// POST /api/players/2fa/disable — Turn off 2FA. Requires a current TOTP or backup code.
+#[utoipa::path(
+    post,
+    path = "/api/players/2fa/disable",
+    tag = "auth",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "2FA disabled"),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+pub async fn disable_totp(
+    AuthPlayer(player_id): AuthPlayer,
+    State(state): State<AppState>,
+    Json(req): Json<TotpCodeRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let player = current_player(&state, player_id).await?;
+
+    if !player.totp_enabled {
+        return Err(ApiError::bad_request(
+            "two_factor_not_enabled",
+            "2FA is not enabled",
+        ));
+    }
+    if !check_second_factor(&state, &player, &req.code).await? {
+        return Err(invalid_code());
+    }
+
+    state.db.disable_totp(player_id).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Accept either a fresh TOTP code or an unused backup code for a player with 2FA enabled.
+async fn check_second_factor(
+    state: &AppState,
+    player: &Player,
+    code: &str,
+) -> Result<bool, ApiError> {
+    let Some(secret) = player
+        .totp_secret
+        .as_deref()
+        .filter(|_| player.totp_enabled)
+    else {
+        return Ok(false);
+    };
+
+    let last_step = player.totp_last_step.map(|s| s as u64);
+    if let Some(step) = totp::verify(secret, code, unix_now(), last_step) {
+        // The conditional update closes the race between two requests using the same code
+        return Ok(state.db.record_totp_step(player.id, step as i64).await?);
+    }
+
+    Ok(state
+        .db
+        .consume_backup_code(player.id, &totp::hash_backup_code(code))
+        .await?)
+}
+
+fn unix_now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
 /// PATCH /api/players/me — Update display_name and/or show_on_leaderboard.
+#[utoipa::path(
+    patch,
+    path = "/api/players/me",
+    tag = "players",
+    request_body = UpdatePlayerRequest,
+    responses(
+        (status = 200, description = "Updated"),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
 pub async fn update_player(
     AuthPlayer(player_id): AuthPlayer,
     State(state): State<AppState>,
     Json(req): Json<UpdatePlayerRequest>,
-) -> Result<impl IntoResponse, StatusCode> {
-    db::update_player(
-        &state.db,
-        player_id,
-        req.display_name.as_deref(),
-        req.show_on_leaderboard,
-    )
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(display_name) = &req.display_name {
+        let mut errors = FieldErrors::default();
+        check_display_name(&mut errors, display_name);
+        errors.into_result()?;
+    }
+
+    state
+        .db
+        .update_player(
+            player_id,
+            req.display_name.as_deref().map(str::trim),
+            req.show_on_leaderboard,
+        )
+        .await?;
+    state.leaderboard.changed();
 
     Ok(StatusCode::OK)
 }
 
 /// PUT /api/scores — Submit score components.
+#[utoipa::path(
+    put,
+    path = "/api/scores",
+    tag = "scores",
+    request_body = ScoreSubmission,
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries replay the first response")),
+    responses(
+        (status = 200, description = "Stored"),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 409, description = "Same Idempotency-Key still in flight", body = ErrorBody),
+        (status = 422, description = "Validation failed", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
 pub async fn submit_scores(
     AuthPlayer(player_id): AuthPlayer,
     State(state): State<AppState>,
     Json(scores): Json<ScoreSubmission>,
-) -> Result<impl IntoResponse, StatusCode> {
-    db::upsert_scores(&state.db, player_id, &scores)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<impl IntoResponse, ApiError> {
+    let mut errors = FieldErrors::default();
+    for (field, value) in [
+        ("total_money_earned", scores.total_money_earned),
+        ("reputation", scores.reputation),
+    ] {
+        errors.check(
+            field,
+            value.is_finite() && value >= 0.0,
+            "must be a non-negative number",
+        );
+    }
+    for (field, value) in [
+        ("skill_levels_sum", scores.skill_levels_sum),
+        ("consultants_count", scores.consultants_count),
+        ("ai_tool_tiers_sum", scores.ai_tool_tiers_sum),
+        ("manual_tasks_completed", scores.manual_tasks_completed),
+    ] {
+        errors.check(field, value >= 0, "must not be negative");
+    }
+    errors.into_result().inspect_err(|_| {
+        metrics::counter!("score_submissions_rejected_total", "reason" => "validation")
+            .increment(1);
+    })?;
+
+    let old_score = state
+        .db
+        .get_score_components(player_id)
+        .await?
+        .map(|c| c.score);
+    state.db.upsert_scores(player_id, &scores).await?;
+    state.leaderboard.changed();
+    let new_score = state
+        .db
+        .get_score_components(player_id)
+        .await?
+        .map(|c| c.score);
+    if let (Some(old_score), Some(new_score)) = (old_score, new_score) {
+        tokio::spawn(notifications::overtaken(
+            state, player_id, old_score, new_score,
+        ));
+    }
 
     Ok(StatusCode::OK)
 }
 
 /// GET /api/leaderboard — Get top 50 + optional player rank.
+#[utoipa::path(
+    get,
+    path = "/api/leaderboard",
+    tag = "scores",
+    responses(
+        (status = 200, body = LeaderboardResponse),
+    ),
+    security((), ("bearer" = [])),
+)]
 pub async fn get_leaderboard(
     auth: OptionalAuthPlayer,
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let entries = db::get_leaderboard(&state.db, 50)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let (player_rank, player_score) = if let Some(player_id) = auth.0 {
-        match db::get_player_rank(&state.db, player_id).await {
-            Ok(Some((rank, score))) => (Some(rank), Some(score)),
-            Ok(None) => (None, None),
-            Err(_) => (None, None),
-        }
-    } else {
-        (None, None)
-    };
-
+) -> Result<impl IntoResponse, ApiError> {
+    let (entries, standing) = leaderboard(&state, auth.0).await?;
     Ok(Json(LeaderboardResponse {
         entries,
-        player_rank,
-        player_score,
+        player_rank: standing.as_ref().map(|s| s.rank),
+        player_score: standing.as_ref().map(|s| s.score),
     }))
 }
 
+/// The top 50 as `viewer` sees them, and where the viewer stands, for every
+/// version of the leaderboard response.
+pub async fn leaderboard(
+    state: &AppState,
+    viewer: Option<Uuid>,
+) -> Result<(Vec<LeaderboardEntry>, Option<PlayerStanding>), ApiError> {
+    let started = Instant::now();
+    let entries = state.db.get_leaderboard(LEADERBOARD_SIZE, viewer).await?;
+    metrics::histogram!("leaderboard_query_duration_seconds")
+        .record(started.elapsed().as_secs_f64());
+
+    let standing = match viewer {
+        Some(player_id) => standing(state, player_id).await,
+        None => None,
+    };
+
+    Ok((entries, standing))
+}
+
+/// Where `player_id` stands, as they see it. The board itself is the
+/// important part, so a failed lookup is logged and left out rather than
+/// failing the whole response.
+pub async fn standing(state: &AppState, player_id: Uuid) -> Option<PlayerStanding> {
+    match state.db.get_player_rank(player_id).await {
+        Ok(standing) => standing.map(|(rank, score)| PlayerStanding { rank, score }),
+        Err(e) => {
+            tracing::warn!(error = %e, "looking up player rank failed");
+            None
+        }
+    }
+}
+
 /// PUT /api/saves — Upload cloud save.
+#[utoipa::path(
+    put,
+    path = "/api/saves",
+    tag = "saves",
+    request_body = SaveUpload,
+    params(("Idempotency-Key" = Option<String>, Header, description = "Retries replay the first response")),
+    responses(
+        (status = 200, description = "Stored"),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 409, description = "Same Idempotency-Key still in flight", body = ErrorBody),
+        (status = 413, description = "Save too large", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
 pub async fn upload_save(
     AuthPlayer(player_id): AuthPlayer,
     State(state): State<AppState>,
     Json(save): Json<SaveUpload>,
-) -> Result<impl IntoResponse, StatusCode> {
-    db::upsert_save(&state.db, player_id, &save.save_data, save.version)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<impl IntoResponse, ApiError> {
+    let (size, stored) = state
+        .db
+        .upsert_save(
+            player_id,
+            &save.save_data,
+            save.version,
+            state.config.saves.compress_above_bytes,
+        )
+        .await?;
+    metrics::counter!("saves_uploaded_total").increment(1);
+    metrics::histogram!("save_size_bytes").record(size as f64);
+    metrics::histogram!("save_stored_bytes").record(stored as f64);
 
     Ok(StatusCode::OK)
 }
 
 /// GET /api/saves/me — Download cloud save.
+#[utoipa::path(
+    get,
+    path = "/api/saves/me",
+    tag = "saves",
+    responses(
+        (status = 200, body = SaveDownload),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+        (status = 404, description = "No save uploaded yet", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
 pub async fn download_save(
     AuthPlayer(player_id): AuthPlayer,
     State(state): State<AppState>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let save = db::get_save(&state.db, player_id)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+) -> Result<impl IntoResponse, ApiError> {
+    let save = state
+        .db
+        .get_save(player_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("save_not_found", "No cloud save uploaded yet"))?;
 
     Ok(Json(save))
 }
+
+/// GET /.well-known/jwks.json — Public keys for verifying player tokens.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    tag = "auth",
+    responses(
+        (status = 200, description = "JSON Web Key Set", body = Object),
+    ),
+)]
+pub async fn jwks(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(state.jwt.jwks().clone()),
+    )
+}
+
+fn health(state: &AppState, status: &'static str) -> HealthResponse {
+    HealthResponse {
+        status,
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("GIT_HASH"),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+        checks: BTreeMap::new(),
+    }
+}
+
+/// GET /api/health/live — The process is up and serving requests. Never touches the database.
+#[utoipa::path(
+    get,
+    path = "/api/health/live",
+    tag = "health",
+    responses(
+        (status = 200, body = HealthResponse),
+    ),
+)]
+pub async fn health_live(State(state): State<AppState>) -> impl IntoResponse {
+    Json(health(&state, "live"))
+}
+
+/// GET /api/health/ready — 200 if the database answers and all migrations are applied, else 503.
+#[utoipa::path(
+    get,
+    path = "/api/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, body = HealthResponse),
+        (status = 503, description = "Not ready", body = HealthResponse),
+    ),
+)]
+pub async fn health_ready(State(state): State<AppState>) -> impl IntoResponse {
+    let mut checks = BTreeMap::new();
+
+    // Errors are logged rather than returned, since this endpoint is public
+    let database = match tokio::time::timeout(READY_DB_TIMEOUT, state.db.ping()).await {
+        Ok(Ok(())) => "ok".to_string(),
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "readiness: database ping failed");
+            "unreachable".to_string()
+        }
+        Err(_) => "timed out".to_string(),
+    };
+    let database_ok = database == "ok";
+    checks.insert("database", database);
+
+    let migrations = if !database_ok {
+        "unknown".to_string()
+    } else {
+        match state.db.pending_migrations().await {
+            Ok(pending) if pending.is_empty() => "ok".to_string(),
+            Ok(pending) => format!("pending: {pending:?}"),
+            Err(e) => {
+                tracing::warn!(error = %e, "readiness: migration check failed");
+                "unknown".to_string()
+            }
+        }
+    };
+    checks.insert("migrations", migrations);
+
+    let ready = checks.values().all(|c| c == "ok");
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let mut body = health(&state, if ready { "ready" } else { "unavailable" });
+    body.checks = checks;
+    (status, Json(body))
+}
@@ -0,0 +1,336 @@
+//! `Idempotency-Key` support for endpoints with side effects. The game
+//! retries requests on flaky connections; sending the same key with a retry
+//! returns the response to the first attempt instead of, say, creating a
+//! second anonymous player.
+//!
+//! Keys are scoped to the authenticated player, so they only need to be
+//! unique per player. Anonymous requests have nobody to scope to, so there
+//! the key is the client's secret; see `Scope`.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, FromRequestParts, Request, State},
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{TimeDelta, Utc};
+use rand::Rng;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    auth::OptionalAuthPlayer, error::ApiError, models::IdempotentRequest, repo::Repository,
+    AppState,
+};
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+/// Set on responses replayed from an earlier request.
+pub const REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+const MAX_KEY_LEN: usize = 255;
+
+/// Anonymous keys must be at least this long, e.g. a random UUID, so they
+/// can't be guessed.
+const MIN_ANONYMOUS_KEY_LEN: usize = 32;
+
+/// A claimed key still without a response after this long belongs to a
+/// request that never finished, e.g. because the server restarted.
+const ABANDONED_AFTER: TimeDelta = TimeDelta::minutes(1);
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Middleware replaying the stored response when a request repeats an
+/// `Idempotency-Key`. Only successful responses are stored; after an error
+/// the key is released so the retry runs again.
+pub async fn idempotent(State(state): State<AppState>, req: Request, next: Next) -> Response {
+    let ttl = state.config.idempotency.ttl();
+    let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(req).await;
+    };
+    if ttl.is_zero() {
+        return next.run(req).await;
+    }
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key.to_string(),
+        _ => {
+            return ApiError::bad_request(
+                "invalid_idempotency_key",
+                format!("Idempotency-Key must be 1 to {MAX_KEY_LEN} visible ASCII characters"),
+            )
+            .into_response()
+        }
+    };
+
+    let (mut parts, body) = req.into_parts();
+    let OptionalAuthPlayer(player_id) =
+        match OptionalAuthPlayer::from_request_parts(&mut parts, &state).await {
+            Ok(player) => player,
+            Err(e) => return e.into_response(),
+        };
+    if player_id.is_none() && key.len() < MIN_ANONYMOUS_KEY_LEN {
+        return ApiError::bad_request(
+            "invalid_idempotency_key",
+            format!(
+                "Without a session token, Idempotency-Key must be at least \
+                 {MIN_ANONYMOUS_KEY_LEN} characters, e.g. a random UUID"
+            ),
+        )
+        .into_response();
+    }
+    let scope = Scope::new(key, player_id);
+
+    // Read the body through `Bytes` so the route's body limit still applies
+    let mut limited = Request::new(body);
+    *limited.extensions_mut() = parts.extensions.clone();
+    let body = match Bytes::from_request(limited, &()).await {
+        Ok(body) => body,
+        Err(rejection) => {
+            let code = if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+                "payload_too_large"
+            } else {
+                "invalid_body"
+            };
+            return ApiError::new(rejection.status(), code, rejection.body_text()).into_response();
+        }
+    };
+
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str());
+    hasher.update(b"\n");
+    // Layered inside the versioned routers, which strip their prefix, so a
+    // retry of an `/api` request through `/api/v1` is the same request
+    hasher.update(parts.uri.to_string());
+    hasher.update(b"\n");
+    hasher.update(&body);
+    let fingerprint = format!("{:x}", hasher.finalize());
+
+    let now = Utc::now();
+    let claim = state
+        .db
+        .claim_idempotency_key(
+            &scope.key,
+            scope.player_id,
+            &fingerprint,
+            now - ttl,
+            now - ABANDONED_AFTER,
+        )
+        .await;
+    match claim {
+        Err(e) => return ApiError::from(e).into_response(),
+        Ok(None) => {}
+        Ok(Some(previous)) if previous.fingerprint != fingerprint => {
+            return ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "idempotency_key_reused",
+                "This Idempotency-Key was used for a different request",
+            )
+            .into_response();
+        }
+        Ok(Some(previous)) if previous.status.is_none() => {
+            let mut response = ApiError::conflict(
+                "idempotency_key_in_use",
+                "A request with this Idempotency-Key is still being handled",
+            )
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from_static("1"));
+            return response;
+        }
+        Ok(Some(previous)) => {
+            metrics::counter!("idempotent_replays_total").increment(1);
+            return replay(previous, &scope);
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if !response.status().is_success() {
+        release(&state, &scope).await;
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            release(&state, &scope).await;
+            return ApiError::internal("idempotent response", e).into_response();
+        }
+    };
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if let Err(e) = state
+        .db
+        .complete_idempotency_key(
+            &scope.key,
+            scope.player_id,
+            parts.status.as_u16() as i16,
+            content_type,
+            &scope.seal(&body),
+        )
+        .await
+    {
+        // The request itself succeeded, so don't fail it; a retry will
+        // find the key still claimed until it's abandoned
+        tracing::warn!(error = %e, "storing idempotent response failed");
+    }
+
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Where a key's response is kept. Authenticated keys belong to the
+/// player. Anonymous keys share the nil player, so the key itself is what
+/// keeps one client from replaying another's response, which for
+/// `POST /players` holds a passphrase and session token. Only a hash of an
+/// anonymous key is stored, and the response is stored encrypted with a
+/// key derived from it, so the table alone can't be used to replay or read
+/// it.
+struct Scope {
+    key: String,
+    player_id: Uuid,
+    sealing: Option<LessSafeKey>,
+}
+
+impl Scope {
+    fn new(key: String, player_id: Option<Uuid>) -> Self {
+        let Some(player_id) = player_id else {
+            let derive = |purpose: &str| {
+                Sha256::new()
+                    .chain_update(purpose)
+                    .chain_update(b"\n")
+                    .chain_update(&key)
+                    .finalize()
+            };
+            let sealing = UnboundKey::new(&CHACHA20_POLY1305, &derive("idempotent response"))
+                .expect("SHA-256 gives a ChaCha20 key");
+            return Scope {
+                key: format!("{:x}", derive("idempotency key")),
+                player_id: Uuid::nil(),
+                sealing: Some(LessSafeKey::new(sealing)),
+            };
+        };
+        Scope {
+            key,
+            player_id,
+            sealing: None,
+        }
+    }
+
+    /// The response body as stored: for anonymous keys, a random nonce
+    /// followed by the encrypted body.
+    fn seal(&self, body: &[u8]) -> Vec<u8> {
+        let Some(sealing) = &self.sealing else {
+            return body.to_vec();
+        };
+        let mut nonce = [0; NONCE_LEN];
+        rand::rng().fill(&mut nonce);
+        let mut sealed = body.to_vec();
+        sealing
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .expect("response fits in a ChaCha20 message");
+        [&nonce[..], &sealed].concat()
+    }
+
+    /// The body `seal` stored, or None if it doesn't decrypt.
+    fn open(&self, stored: Vec<u8>) -> Option<Vec<u8>> {
+        let Some(sealing) = &self.sealing else {
+            return Some(stored);
+        };
+        let (nonce, sealed) = stored.split_at_checked(NONCE_LEN)?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut sealed = sealed.to_vec();
+        let body = sealing
+            .open_in_place(nonce, Aad::empty(), &mut sealed)
+            .ok()?;
+        Some(body.to_vec())
+    }
+}
+
+async fn release(state: &AppState, scope: &Scope) {
+    if let Err(e) = state
+        .db
+        .release_idempotency_key(&scope.key, scope.player_id)
+        .await
+    {
+        tracing::warn!(error = %e, "releasing idempotency key failed");
+    }
+}
+
+fn replay(previous: IdempotentRequest, scope: &Scope) -> Response {
+    let status = previous
+        .status
+        .and_then(|s| StatusCode::from_u16(s as u16).ok())
+        .unwrap_or(StatusCode::OK);
+    let Some(body) = scope.open(previous.body.unwrap_or_default()) else {
+        return ApiError::internal("idempotent replay", "stored response doesn't decrypt")
+            .into_response();
+    };
+    let mut response = (status, body).into_response();
+    let headers = response.headers_mut();
+    headers.remove(header::CONTENT_TYPE);
+    if let Some(content_type) = previous
+        .content_type
+        .and_then(|v| HeaderValue::from_str(&v).ok())
+    {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+/// Delete expired keys every hour. Claims past the TTL are ignored anyway;
+/// this only keeps the table from growing.
+pub fn spawn_pruner(db: Arc<dyn Repository>, ttl: TimeDelta) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match db.prune_idempotency_keys(Utc::now() - ttl).await {
+                Ok(0) => {}
+                Ok(pruned) => tracing::debug!(pruned, "pruned expired idempotency keys"),
+                Err(e) => tracing::warn!(error = %e, "pruning idempotency keys failed"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anonymous_scopes_hide_the_key_and_response() {
+        let key = Uuid::new_v4().to_string();
+        let body = br#"{"passphrase":"correct horse","token":"secret"}"#;
+        let scope = Scope::new(key.clone(), None);
+        assert_eq!(scope.player_id, Uuid::nil());
+        assert!(!scope.key.contains(&key));
+
+        let stored = scope.seal(body);
+        assert!(!stored.windows(6).any(|w| w == b"secret"));
+        assert_eq!(
+            Scope::new(key, None).open(stored.clone()).as_deref(),
+            Some(&body[..])
+        );
+        let guess = Scope::new(Uuid::new_v4().to_string(), None);
+        assert_eq!(guess.open(stored), None);
+    }
+
+    #[test]
+    fn player_scopes_keep_the_key_and_response() {
+        let player = Uuid::new_v4();
+        let scope = Scope::new("save-1".into(), Some(player));
+        assert_eq!((scope.key.as_str(), scope.player_id), ("save-1", player));
+        assert_eq!(scope.seal(b"{}"), b"{}");
+    }
+}
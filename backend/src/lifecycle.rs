@@ -0,0 +1,191 @@
+use std::{
+    fs,
+    future::Future,
+    io,
+    net::SocketAddr,
+    os::unix::fs::{FileTypeExt, PermissionsExt},
+    path::Path,
+    time::Duration,
+};
+
+use axum::{extract::ConnectInfo, serve::ListenerExt, Extension, Router};
+use listenfd::ListenFd;
+use sd_notify::NotifyState;
+use tokio::{
+    net::{TcpListener, UnixListener},
+    signal::unix::SignalKind,
+    sync::watch,
+};
+
+use crate::{config::Config, tls::TlsListener};
+
+/// Resolves once SIGTERM (systemd stop/restart) or SIGINT (Ctrl-C) arrives.
+/// Clones all fire together, so the server and the drain timer can both wait.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    pub fn on_signal() -> Self {
+        let (tx, rx) = watch::channel(false);
+        tokio::spawn(async move {
+            let mut term = tokio::signal::unix::signal(SignalKind::terminate())
+                .expect("Failed to install SIGTERM handler");
+            tokio::select! {
+                _ = term.recv() => tracing::info!("SIGTERM received, shutting down"),
+                _ = tokio::signal::ctrl_c() => tracing::info!("SIGINT received, shutting down"),
+            }
+            notify(NotifyState::Stopping);
+            let _ = tx.send(true);
+        });
+        Shutdown(rx)
+    }
+
+    pub async fn wait(mut self) {
+        // An error means the sender is gone, which only happens after it fired
+        let _ = self.0.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Run `server` until it finishes draining after `shutdown`, giving up on
+/// in-flight requests once `drain_timeout` has passed since the signal.
+pub async fn serve_until_drained(
+    server: impl Future<Output = io::Result<()>>,
+    shutdown: Shutdown,
+    drain_timeout: Duration,
+) -> io::Result<()> {
+    let deadline = async {
+        shutdown.wait().await;
+        tokio::time::sleep(drain_timeout).await;
+    };
+    tokio::select! {
+        result = server => result,
+        _ = deadline => {
+            tracing::warn!(
+                "Requests still in flight after {}s, exiting anyway",
+                drain_timeout.as_secs()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Where connections come from: plain TCP, TLS over TCP or a Unix socket.
+pub enum Listener {
+    Tcp(TcpListener),
+    Tls(TlsListener),
+    Unix(UnixListener),
+}
+
+/// Bind the listener the config asks for.
+pub async fn bind(config: &Config) -> io::Result<Listener> {
+    if let Some(path) = &config.listen_socket {
+        return unix_listener(path).map(Listener::Unix);
+    }
+    let listener = tcp_listener(config.listen_addr).await?;
+    match config.tls.paths() {
+        Some((cert_path, key_path)) => {
+            let listener = TlsListener::new(listener, cert_path, key_path)?;
+            tracing::info!("TLS enabled with {}", cert_path.display());
+            Ok(Listener::Tls(listener))
+        }
+        None => Ok(Listener::Tcp(listener)),
+    }
+}
+
+/// Serve `app` on `listener` until `shutdown` fires and the open
+/// connections have finished.
+pub async fn serve(listener: Listener, app: Router, shutdown: Shutdown) -> io::Result<()> {
+    match listener {
+        Listener::Tcp(listener) => {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.wait())
+            .await
+        }
+        Listener::Tls(listener) => {
+            // Going through `tap_io` is what provides `ConnectInfo<SocketAddr>`
+            // for a listener axum doesn't know
+            axum::serve(
+                listener.tap_io(|_| {}),
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(shutdown.wait())
+            .await
+        }
+        Listener::Unix(listener) => {
+            // Only a local proxy can reach the socket, so peers count as
+            // loopback and the rate limiter goes by X-Forwarded-For
+            let peer = ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 0)));
+            axum::serve(listener, app.layer(Extension(peer)).into_make_service())
+                .with_graceful_shutdown(shutdown.wait())
+                .await
+        }
+    }
+}
+
+/// The socket systemd passed us (socket activation), or a fresh one bound
+/// to `addr`. With an activated socket, connections arriving while the
+/// service restarts wait in the kernel's backlog instead of being refused.
+pub async fn tcp_listener(addr: SocketAddr) -> io::Result<TcpListener> {
+    if let Some(listener) = ListenFd::from_env().take_tcp_listener(0)? {
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        tracing::info!(
+            "Listening on {} (socket activation)",
+            listener.local_addr()?
+        );
+        return Ok(listener);
+    }
+    let listener = TcpListener::bind(addr).await?;
+    tracing::info!("Listening on {addr}");
+    Ok(listener)
+}
+
+/// Like [`tcp_listener`], for a Unix socket at `path`. A socket left behind
+/// by an earlier run is replaced.
+fn unix_listener(path: &Path) -> io::Result<UnixListener> {
+    if let Some(listener) = ListenFd::from_env().take_unix_listener(0)? {
+        listener.set_nonblocking(true)?;
+        tracing::info!("Listening on {} (socket activation)", path.display());
+        return UnixListener::from_std(listener);
+    }
+
+    if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    // Owner and group, so a proxy running as another user in our group can connect
+    fs::set_permissions(path, fs::Permissions::from_mode(0o660))?;
+    tracing::info!("Listening on {}", path.display());
+    Ok(listener)
+}
+
+/// Tell systemd we're serving (`Type=notify`) and start the watchdog
+/// heartbeat if the unit sets `WatchdogSec`. The heartbeat runs on the
+/// async runtime, so a wedged runtime stops it and systemd restarts us.
+pub fn notify_ready() {
+    notify(NotifyState::Ready);
+
+    let mut usec = 0;
+    if sd_notify::watchdog_enabled(false, &mut usec) {
+        // Ping at half the deadline so one late tick doesn't trip it
+        let interval = Duration::from_micros(usec) / 2;
+        tracing::info!("systemd watchdog enabled, pinging every {interval:?}");
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                notify(NotifyState::Watchdog);
+            }
+        });
+    }
+}
+
+/// No-op when not started by systemd (`NOTIFY_SOCKET` unset).
+fn notify(state: NotifyState) {
+    if let Err(e) = sd_notify::notify(false, &[state]) {
+        tracing::warn!(error = %e, "sd_notify failed");
+    }
+}
@@ -0,0 +1,376 @@
+//! Live leaderboard updates over Server-Sent Events. Writes that can move
+//! players only mark the standings as changed; one task then reads the top
+//! of the board and where every signed-in subscriber stands, at most every
+//! `leaderboard.stream_interval_ms`, and broadcasts both to every
+//! subscriber. That is two queries per change however many players are
+//! watching, plus a board of their own for each shadow-banned one on it.
+//!
+//! Signed-in subscribers also get their new notifications on the same
+//! stream, so the game only keeps one connection open.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::State,
+    http::{HeaderName, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Sse,
+    },
+};
+use futures_util::{stream, Stream};
+use serde::Serialize;
+use tokio::sync::{broadcast, watch, Notify};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthPlayer,
+    error::{ApiError, ErrorBody},
+    handlers,
+    models::{LeaderboardEntry, LeaderboardResponseV2, Notification, PlayerStanding},
+    notifications::Subscription,
+    repo::Repository,
+    AppState,
+};
+
+/// Boards a subscriber can fall behind by before it skips ahead.
+const BACKLOG: usize = 16;
+
+type Board = Arc<Vec<LeaderboardEntry>>;
+
+/// What every subscriber sees after a change.
+struct Snapshot {
+    board: Board,
+    /// Each signed-in subscriber's view, missing for those who subscribed
+    /// after it was read.
+    viewers: HashMap<Uuid, View>,
+}
+
+struct View {
+    board: Board,
+    me: Option<PlayerStanding>,
+}
+
+pub struct LeaderboardFeed {
+    changed: Notify,
+    boards: broadcast::Sender<Arc<Snapshot>>,
+    closed: watch::Sender<bool>,
+    /// Signed-in subscribers, with how many streams each has open.
+    viewers: Mutex<HashMap<Uuid, usize>>,
+}
+
+impl LeaderboardFeed {
+    /// Create the feed and start the task publishing to it.
+    pub fn spawn(db: Arc<dyn Repository>, interval: Duration) -> Arc<Self> {
+        let feed = Arc::new(LeaderboardFeed {
+            changed: Notify::new(),
+            boards: broadcast::channel(BACKLOG).0,
+            closed: watch::Sender::new(false),
+            viewers: Mutex::default(),
+        });
+        tokio::spawn(publish(feed.clone(), db, interval));
+        feed
+    }
+
+    /// Note that the standings may have changed. Cheap enough to call after
+    /// every write that could move a player.
+    pub fn changed(&self) {
+        self.changed.notify_one();
+    }
+
+    /// End every stream, so shutdown doesn't wait for them to time out.
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    fn watch(&self, viewer: Uuid) {
+        *self.viewers.lock().unwrap().entry(viewer).or_default() += 1;
+    }
+
+    fn unwatch(&self, viewer: Uuid) {
+        let mut viewers = self.viewers.lock().unwrap();
+        if let Some(streams) = viewers.get_mut(&viewer) {
+            *streams -= 1;
+            if *streams == 0 {
+                viewers.remove(&viewer);
+            }
+        }
+    }
+}
+
+async fn publish(feed: Arc<LeaderboardFeed>, db: Arc<dyn Repository>, interval: Duration) {
+    let mut closed = feed.closed.subscribe();
+    let mut last: Option<Board> = None;
+    loop {
+        tokio::select! {
+            _ = feed.changed.notified() => {}
+            _ = closed.changed() => return,
+        }
+
+        match db.get_leaderboard(handlers::LEADERBOARD_SIZE, None).await {
+            Ok(board) => {
+                // Resend the same allocation when nothing moved, so
+                // subscribers can skip it without comparing entries
+                let board = match last {
+                    Some(last) if *last == board => last,
+                    _ => Arc::new(board),
+                };
+                last = Some(board.clone());
+                let viewers = views(&feed, db.as_ref(), &board).await;
+                // Nobody listening isn't an error
+                let _ = feed.boards.send(Arc::new(Snapshot { board, viewers }));
+            }
+            Err(e) => tracing::warn!(error = %e, "reading the leaderboard for subscribers failed"),
+        }
+
+        // Changes during the pause leave a permit, so they go out right after
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// What each signed-in subscriber sees alongside `board`. Viewers left out
+/// on an error keep what they had until the next change.
+async fn views(feed: &LeaderboardFeed, db: &dyn Repository, board: &Board) -> HashMap<Uuid, View> {
+    let viewers: Vec<Uuid> = feed.viewers.lock().unwrap().keys().copied().collect();
+    if viewers.is_empty() {
+        return HashMap::new();
+    }
+    let standings = match db.viewer_standings(&viewers).await {
+        Ok(standings) => standings,
+        Err(e) => {
+            tracing::warn!(error = %e, "reading subscribers' standings failed");
+            return HashMap::new();
+        }
+    };
+
+    // Unranked viewers see the board without themselves
+    let mut views: HashMap<Uuid, View> = viewers
+        .into_iter()
+        .map(|viewer| {
+            let view = View {
+                board: board.clone(),
+                me: None,
+            };
+            (viewer, view)
+        })
+        .collect();
+    for standing in standings {
+        let me = Some(PlayerStanding {
+            rank: standing.rank,
+            score: standing.score,
+        });
+        // Shadow-banned players see themselves on the board, so one ranked
+        // high enough to be on it needs a board of its own
+        let board = if standing.shadow_banned && standing.rank <= handlers::LEADERBOARD_SIZE {
+            match db
+                .get_leaderboard(handlers::LEADERBOARD_SIZE, Some(standing.player_id))
+                .await
+            {
+                Ok(entries) => Arc::new(entries),
+                Err(e) => {
+                    tracing::warn!(error = %e, "reading a shadow-banned subscriber's board failed");
+                    views.remove(&standing.player_id);
+                    continue;
+                }
+            }
+        } else {
+            board.clone()
+        };
+        views.insert(standing.player_id, View { board, me });
+    }
+    views
+}
+
+/// GET /api/leaderboard/stream — Top 50 and the caller's standing, now and after every change,
+/// plus the caller's new notifications.
+#[utoipa::path(
+    get,
+    path = "/api/leaderboard/stream",
+    tag = "scores",
+    responses(
+        (
+            status = 200,
+            description = "Server-Sent Events named `leaderboard`, each shaped like the v2 \
+                leaderboard, and `notification`, each one of the caller's new notifications",
+            content_type = "text/event-stream",
+            body = LeaderboardResponseV2,
+        ),
+        (status = 401, description = "Invalid token", body = ErrorBody),
+        (status = 403, description = "Account banned", body = ErrorBody),
+    ),
+    security((), ("bearer" = [])),
+)]
+pub async fn stream_leaderboard(
+    auth: Option<AuthPlayer>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let events = subscribe(state, auth.map(|AuthPlayer(player_id)| player_id)).await?;
+    Ok((
+        // Keep nginx-style proxies from holding events back
+        [(
+            HeaderName::from_static("x-accel-buffering"),
+            HeaderValue::from_static("no"),
+        )],
+        Sse::new(events).keep_alive(KeepAlive::default()),
+    ))
+}
+
+/// The same shape as `LeaderboardResponseV2`, without copying the board
+/// for every subscriber.
+#[derive(Serialize)]
+struct Update<'a> {
+    entries: &'a [LeaderboardEntry],
+    me: Option<PlayerStanding>,
+}
+
+struct Subscriber {
+    feed: Arc<LeaderboardFeed>,
+    viewer: Option<Uuid>,
+    boards: broadcast::Receiver<Arc<Snapshot>>,
+    closed: watch::Receiver<bool>,
+    entries: Board,
+    me: Option<PlayerStanding>,
+    notifications: Option<Subscription>,
+    first: bool,
+}
+
+enum Wake {
+    Board(Arc<Snapshot>),
+    Notification(Arc<Notification>),
+}
+
+async fn subscribe(
+    state: AppState,
+    viewer: Option<Uuid>,
+) -> Result<impl Stream<Item = Result<Event, Infallible>>, ApiError> {
+    // Subscribe before the first read, so no change in between is missed
+    let boards = state.leaderboard.boards.subscribe();
+    let closed = state.leaderboard.closed.subscribe();
+    let notifications = viewer.map(|player_id| state.notifier.subscribe(player_id));
+    if *closed.borrow() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "shutting_down",
+            "The server is shutting down",
+        ));
+    }
+
+    let (entries, me) = handlers::leaderboard(&state, viewer).await?;
+
+    // Snapshots read before this leave the viewer out, and are skipped
+    if let Some(viewer) = viewer {
+        state.leaderboard.watch(viewer);
+    }
+    metrics::gauge!("leaderboard_subscribers").increment(1.0);
+    let subscriber = Subscriber {
+        feed: state.leaderboard.clone(),
+        viewer,
+        boards,
+        closed,
+        entries: Arc::new(entries),
+        me,
+        notifications,
+        first: true,
+    };
+    Ok(stream::unfold(subscriber, |mut subscriber| async move {
+        let event = subscriber.next_event().await?;
+        Some((Ok(event), subscriber))
+    }))
+}
+
+impl Subscriber {
+    /// The next notification or update that changes what this subscriber
+    /// sees, or `None` once the feed closes.
+    async fn next_event(&mut self) -> Option<Event> {
+        if !std::mem::take(&mut self.first) {
+            loop {
+                match self.wait().await? {
+                    Wake::Board(snapshot) => {
+                        if self.refresh(&snapshot) {
+                            break;
+                        }
+                    }
+                    Wake::Notification(notification) => {
+                        return Some(
+                            Event::default()
+                                .event("notification")
+                                .json_data(&*notification)
+                                .expect("notification serializes"),
+                        );
+                    }
+                }
+            }
+        }
+        let update = Update {
+            entries: &self.entries,
+            me: self.me,
+        };
+        Some(
+            Event::default()
+                .event("leaderboard")
+                .json_data(update)
+                .expect("leaderboard serializes"),
+        )
+    }
+
+    /// Wait for the next board or notification, or `None` once the feed
+    /// closes.
+    async fn wait(&mut self) -> Option<Wake> {
+        let Subscriber {
+            boards,
+            closed,
+            notifications,
+            ..
+        } = self;
+        let notification = async {
+            match notifications {
+                Some(notifications) => notifications.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(notification);
+        loop {
+            tokio::select! {
+                _ = closed.changed() => return None,
+                notification = &mut notification => return Some(Wake::Notification(notification)),
+                board = boards.recv() => match board {
+                    Ok(board) => return Some(Wake::Board(board)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+            }
+        }
+    }
+
+    /// Take in a new snapshot. True if the board or the viewer's standing
+    /// moved.
+    fn refresh(&mut self, snapshot: &Snapshot) -> bool {
+        let (entries, me) = match self.viewer {
+            Some(viewer) => match snapshot.viewers.get(&viewer) {
+                Some(view) => (view.board.clone(), view.me),
+                None => return false,
+            },
+            None => (snapshot.board.clone(), None),
+        };
+
+        let moved =
+            !(Arc::ptr_eq(&entries, &self.entries) || entries == self.entries) || me != self.me;
+        self.entries = entries;
+        self.me = me;
+        moved
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        metrics::gauge!("leaderboard_subscribers").decrement(1.0);
+        if let Some(viewer) = self.viewer {
+            self.feed.unwatch(viewer);
+        }
+    }
+}
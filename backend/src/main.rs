@@ -1,64 +1,135 @@
-use axum::{
-    routing::{get, patch, post, put},
-    Router,
-};
-use sqlx::postgres::PgPoolOptions;
-use std::net::SocketAddr;
-use tower_http::cors::CorsLayer;
+use clap::Parser;
+use std::{sync::Arc, time::Instant};
 
+mod admin;
 mod auth;
+mod cli;
+mod client_version;
+mod config;
+mod content;
+mod cors;
 mod db;
+mod error;
+mod etag;
+mod experiments;
 mod handlers;
+mod idempotency;
+mod keys;
+mod lifecycle;
+mod live;
 mod models;
+mod notifications;
+mod openapi;
+mod ratelimit;
+mod remote_config;
+mod repo;
+mod routes;
+mod telemetry;
+mod tls;
+mod totp;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub db: sqlx::PgPool,
-    pub jwt_secret: String,
+    pub db: Arc<dyn repo::Repository>,
+    pub jwt: Arc<keys::JwtKeys>,
+    pub config: Arc<config::Config>,
+    pub leaderboard: Arc<live::LeaderboardFeed>,
+    pub notifier: Arc<notifications::Notifier>,
+    pub content: Arc<content::Content>,
+    pub started_at: Instant,
 }
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
+    let cli = cli::Cli::parse();
+    telemetry::init();
 
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let listen_addr: SocketAddr = std::env::var("LISTEN_ADDR")
-        .unwrap_or_else(|_| "127.0.0.1:3080".to_string())
-        .parse()
-        .expect("Invalid LISTEN_ADDR");
+    let config = config::Config::load().unwrap_or_else(|e| {
+        eprint!("{e}");
+        std::process::exit(1);
+    });
 
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
+    match cli.command.unwrap_or(cli::Command::Serve) {
+        cli::Command::Serve => serve(config).await,
+        command => {
+            if let Err(e) = cli::run(command, &config).await {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+async fn serve(config: config::Config) {
+    let metrics = telemetry::install_metrics();
+    let jwt = keys::JwtKeys::from_config(&config.auth)
+        .unwrap_or_else(|e| panic!("Invalid JWT key configuration: {e}"));
+
+    let db = repo::connect(&config)
         .await
         .expect("Failed to connect to database");
 
-    sqlx::migrate!()
-        .run(&pool)
-        .await
-        .expect("Failed to run migrations");
+    db.run_migrations().await.expect("Failed to run migrations");
+
+    if config.idempotency.ttl_secs > 0 {
+        idempotency::spawn_pruner(db.clone(), config.idempotency.ttl());
+    }
+    if config.notifications.retention_days > 0 {
+        notifications::spawn_pruner(db.clone(), config.notifications.retention());
+    }
+
+    let content = content::Content::load(config.content_path.as_deref()).unwrap_or_else(|e| {
+        eprint!("{e}");
+        std::process::exit(1);
+    });
+    let content = Arc::new(content);
+    content.reload_on_sighup();
+
+    let metrics_app = telemetry::metrics_router(metrics, db.clone());
+    let leaderboard =
+        live::LeaderboardFeed::spawn(db.clone(), config.leaderboard.stream_interval());
 
     let state = AppState {
-        db: pool,
-        jwt_secret,
+        db: db.clone(),
+        jwt: Arc::new(jwt),
+        config: Arc::new(config.clone()),
+        leaderboard: leaderboard.clone(),
+        notifier: Arc::default(),
+        content,
+        started_at: Instant::now(),
     };
 
-    let app = Router::new()
-        .route("/api/health", get(|| async { "ok" }))
-        .route("/api/players", post(handlers::create_player))
-        .route("/api/players/recover", post(handlers::recover_player))
-        .route("/api/players/register", post(handlers::register))
-        .route("/api/players/login", post(handlers::login))
-        .route("/api/players/me", patch(handlers::update_player))
-        .route("/api/scores", put(handlers::submit_scores))
-        .route("/api/leaderboard", get(handlers::get_leaderboard))
-        .route("/api/saves", put(handlers::upload_save))
-        .route("/api/saves/me", get(handlers::download_save))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
-
-    let listener = tokio::net::TcpListener::bind(listen_addr).await.unwrap();
-    println!("Listening on {listen_addr}");
-    axum::serve(listener, app).await.unwrap();
+    let app = routes::router(state);
+
+    let app = match config.metrics_addr {
+        Some(addr) => {
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            tracing::info!("Serving metrics on {addr}");
+            tokio::spawn(async move { axum::serve(listener, metrics_app).await.unwrap() });
+            app
+        }
+        None => app.merge(metrics_app),
+    };
+    let app = telemetry::trace_requests(app);
+
+    let listener = lifecycle::bind(&config)
+        .await
+        .expect("Failed to set up the listener");
+    let shutdown = lifecycle::Shutdown::on_signal();
+    // Live leaderboard streams never finish on their own
+    let streams_shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        streams_shutdown.wait().await;
+        leaderboard.close();
+    });
+    let server = lifecycle::serve(listener, app, shutdown.clone());
+
+    lifecycle::notify_ready();
+    lifecycle::serve_until_drained(server, shutdown, config.drain_timeout())
+        .await
+        .unwrap();
+
+    db.close().await;
+    tracing::info!("Shut down");
 }
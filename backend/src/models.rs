@@ -1,8 +1,43 @@
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum Role {
+    Player,
+    Admin,
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum BanKind {
+    /// Every authenticated endpoint returns 403.
+    #[default]
+    Hard,
+    /// The player can keep playing and sees themselves on the leaderboard,
+    /// but nobody else does.
+    Shadow,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ActiveBan {
+    #[sqlx(rename = "ban_kind")]
+    pub kind: BanKind,
+    #[sqlx(rename = "ban_reason")]
+    pub reason: Option<String>,
+    #[sqlx(rename = "ban_expires_at")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Player {
     pub id: Uuid,
     pub display_name: String,
@@ -10,53 +45,100 @@ pub struct Player {
     pub username: Option<String>,
     pub password_hash: Option<String>,
     pub show_on_leaderboard: bool,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    pub role: Role,
+    pub hidden_by_admin: bool,
+    pub banned_at: Option<DateTime<Utc>>,
+    pub ban_kind: Option<BanKind>,
+    pub ban_reason: Option<String>,
+    pub ban_expires_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreatePlayerRequest {
     pub display_name: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CreatePlayerResponse {
     pub id: Uuid,
     pub passphrase: String,
     pub token: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RecoverRequest {
     pub passphrase: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AuthResponse {
     pub id: Uuid,
     pub display_name: String,
     pub token: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RegisterRequest {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// Response to the password step of login. Accounts without 2FA get a
+/// session token straight away; others get a challenge to complete.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authenticated(AuthResponse),
+    TwoFactorRequired(TwoFactorChallenge),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    /// Either a current TOTP code or one of the player's backup codes.
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TotpEnrollResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TotpCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BackupCodesResponse {
+    pub backup_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdatePlayerRequest {
     pub display_name: Option<String>,
     pub show_on_leaderboard: Option<bool>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct ScoreSubmission {
     pub total_money_earned: f64,
     pub reputation: f64,
@@ -66,7 +148,7 @@ pub struct ScoreSubmission {
     pub manual_tasks_completed: i32,
 }
 
-#[derive(Debug, Serialize, sqlx::FromRow)]
+#[derive(Debug, PartialEq, Serialize, sqlx::FromRow, ToSchema)]
 pub struct LeaderboardEntry {
     pub rank: i64,
     pub display_name: String,
@@ -79,22 +161,386 @@ pub struct LeaderboardEntry {
     pub manual_tasks_completed: i32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct LeaderboardResponse {
     pub entries: Vec<LeaderboardEntry>,
     pub player_rank: Option<i64>,
     pub player_score: Option<f64>,
 }
 
-#[derive(Debug, Deserialize)]
+/// `/api/v2` leaderboard: the viewer's standing as one object.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LeaderboardResponseV2 {
+    pub entries: Vec<LeaderboardEntry>,
+    /// Absent for anonymous requests and players not on the board.
+    pub me: Option<PlayerStanding>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct PlayerStanding {
+    pub rank: i64,
+    pub score: f64,
+}
+
+/// Where a signed-in player watching the live leaderboard stands, as they
+/// see it.
+#[derive(Debug, sqlx::FromRow)]
+pub struct ViewerStanding {
+    pub player_id: Uuid,
+    pub rank: i64,
+    pub score: f64,
+    /// Ranked only in their own view, so they need a board of their own.
+    pub shadow_banned: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SaveUpload {
     pub save_data: serde_json::Value,
     pub version: i32,
 }
 
-#[derive(Debug, Serialize, sqlx::FromRow)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SaveDownload {
     pub save_data: serde_json::Value,
     pub version: i32,
     pub updated_at: DateTime<Utc>,
 }
+
+// ── Admin ──
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AdminPlayerSearch {
+    /// Matches display name or username (case-insensitive substring), or an exact player id.
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct AdminPlayerSummary {
+    pub id: Uuid,
+    pub display_name: String,
+    pub username: Option<String>,
+    pub role: Role,
+    pub show_on_leaderboard: bool,
+    pub hidden_by_admin: bool,
+    pub banned_at: Option<DateTime<Utc>>,
+    pub ban_kind: Option<BanKind>,
+    pub ban_reason: Option<String>,
+    pub ban_expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct ScoreComponents {
+    pub score: f64,
+    pub total_money_earned: f64,
+    pub reputation: f64,
+    pub skill_levels_sum: i32,
+    pub consultants_count: i32,
+    pub ai_tool_tiers_sum: i32,
+    pub manual_tasks_completed: i32,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct SaveMetadata {
+    pub version: i32,
+    /// Size of the save's JSON.
+    pub size_bytes: i64,
+    /// Bytes actually stored, smaller than `size_bytes` when compressed.
+    pub stored_bytes: i64,
+    pub compressed: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AdminPlayerDetail {
+    #[serde(flatten)]
+    pub player: AdminPlayerSummary,
+    pub totp_enabled: bool,
+    pub rank: Option<i64>,
+    pub scores: Option<ScoreComponents>,
+    pub save: Option<SaveMetadata>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AdminActionRequest {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BanRequest {
+    #[serde(default)]
+    pub kind: BanKind,
+    pub reason: String,
+    /// When the ban lifts by itself; permanent if omitted.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditLogQuery {
+    pub player_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    /// `None` when the action was taken through the management CLI.
+    pub admin_id: Option<Uuid>,
+    pub action: String,
+    pub target_player_id: Option<Uuid>,
+    #[sqlx(json)]
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+// ── Idempotency ──
+
+/// The request that first used an `Idempotency-Key`, and its response once
+/// it has one.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct IdempotentRequest {
+    /// Hash of the method, path and body, to catch keys reused for other requests.
+    pub fingerprint: String,
+    /// `None` while the request is still being handled.
+    pub status: Option<i16>,
+    pub content_type: Option<String>,
+    pub body: Option<Vec<u8>>,
+}
+
+// ── Notifications ──
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "text", rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// Another player passed you on the leaderboard. `data` has `by`, their
+    /// display name, and `rank`, yours now.
+    Overtaken,
+    /// A message to every player from the admins, e.g. that a season is
+    /// ending. `data` has `title` and `message`.
+    Announcement,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub struct Notification {
+    pub id: i64,
+    #[serde(skip)]
+    pub player_id: Uuid,
+    pub kind: NotificationKind,
+    #[sqlx(json)]
+    pub data: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationQuery {
+    /// Leave out notifications already marked read.
+    #[serde(default)]
+    pub unread_only: bool,
+    /// Only notifications older than this id, for the next page.
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NotificationList {
+    /// Newest first.
+    pub notifications: Vec<Notification>,
+    /// Unread notifications in total, not just on this page.
+    pub unread: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MarkNotificationsRead {
+    /// Absent marks every notification read.
+    pub ids: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UnreadNotifications {
+    pub unread: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AnnouncementRequest {
+    pub title: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AnnouncementSent {
+    /// Players the announcement went to.
+    pub notified: u64,
+}
+
+// ── Remote config ──
+
+/// Tuning values for the game, and overrides of them for some players.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigDocument {
+    /// Every key the game reads, with the value most players get, e.g.
+    /// `"random_event.expiry_time": 180`.
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub values: BTreeMap<String, serde_json::Value>,
+    /// Applied in order to the players they target; a later match wins.
+    #[serde(default)]
+    pub overrides: Vec<ConfigOverride>,
+}
+
+/// A different value for one key, for the players matching every target
+/// given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigOverride {
+    pub key: String,
+    pub value: serde_json::Value,
+    /// Share of signed-in players, 0 to 100, picked by a hash of their id
+    /// and `key`. Raising it keeps the players already in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout_percent: Option<u8>,
+    /// Oldest game version targeted, like `1.4.0`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_client_version: Option<String>,
+    /// Newest game version targeted, inclusive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_client_version: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub struct ConfigVersion {
+    pub version: i64,
+    #[sqlx(json)]
+    pub document: ConfigDocument,
+    pub note: String,
+    /// `None` when published through the management CLI.
+    pub published_by: Option<Uuid>,
+    pub published_at: DateTime<Utc>,
+}
+
+/// The config as it applies to the caller.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RemoteConfig {
+    /// 0 before anything has been published.
+    pub version: i64,
+    #[schema(value_type = Object)]
+    pub values: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PublishConfigRequest {
+    pub document: ConfigDocument,
+    /// What changed, for the version history.
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RollbackConfigRequest {
+    /// The version whose document to publish again.
+    pub version: i64,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ConfigVersionQuery {
+    pub limit: Option<i64>,
+}
+
+// ── Experiments ──
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ExperimentVariant {
+    pub name: String,
+    /// Share of players relative to the other variants' weights.
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+pub struct Experiment {
+    pub key: String,
+    pub description: String,
+    /// The first is the control the others are compared with.
+    #[sqlx(json)]
+    pub variants: Vec<ExperimentVariant>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    /// Ended experiments assign no one, but keep their report.
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateExperimentRequest {
+    /// Like `contract_payouts`: lowercase letters, digits, `_`, `-` and `.`.
+    pub key: String,
+    #[serde(default)]
+    pub description: String,
+    pub variants: Vec<ExperimentVariant>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExperimentAssignments {
+    /// Variant name by experiment key, for every running experiment.
+    pub assignments: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExposureRecorded {
+    pub experiment: String,
+    pub variant: String,
+}
+
+/// Averages over a variant's exposed players' current score components.
+/// Players left out of rankings, e.g. banned or hidden ones, don't count.
+#[derive(Debug, Clone, Default, Serialize, sqlx::FromRow, ToSchema)]
+pub struct VariantMetrics {
+    pub variant: String,
+    pub players: i64,
+    pub avg_score: f64,
+    pub avg_total_money_earned: f64,
+    pub avg_reputation: f64,
+    pub avg_skill_levels_sum: f64,
+    pub avg_consultants_count: f64,
+    pub avg_ai_tool_tiers_sum: f64,
+    pub avg_manual_tasks_completed: f64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VariantReport {
+    #[serde(flatten)]
+    pub metrics: VariantMetrics,
+    /// `avg_score` relative to the control's, e.g. 0.05 for 5% higher.
+    /// `None` for the control itself, or when the control has no score.
+    pub score_change_vs_control: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ExperimentReport {
+    pub experiment: Experiment,
+    /// In the experiment's order, including variants nobody was exposed to.
+    pub variants: Vec<VariantReport>,
+}
+
+// ── Health ──
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthResponse {
+    /// `live`, `ready` or `unavailable`.
+    pub status: &'static str,
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub uptime_seconds: u64,
+    /// Per-dependency results, `ok` or what's wrong. Only reported by readiness.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<&'static str, String>,
+}
@@ -0,0 +1,267 @@
+//! Notifications for players: stored so they can be listed later, and
+//! pushed as `notification` events on `/api/leaderboard/stream` to players
+//! who have it open. The generators are called from the handlers whose
+//! changes players should hear about.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::State,
+    response::IntoResponse,
+    routing::{get, post},
+    Router,
+};
+use chrono::{TimeDelta, Utc};
+use serde_json::json;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthPlayer,
+    error::{ApiError, ErrorBody, Json, Query},
+    handlers,
+    models::*,
+    repo::Repository,
+    AppState,
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// Notifications a connection can fall behind by before it misses some.
+/// Missed ones are still listed by `GET /api/notifications`.
+const BACKLOG: usize = 16;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Players an announcement is stored for per query.
+const ANNOUNCEMENT_BATCH: i64 = 1000;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/notifications", get(list_notifications))
+        .route("/notifications/read", post(mark_read))
+}
+
+/// Connected players, to push new notifications to.
+#[derive(Default)]
+pub struct Notifier {
+    players: Mutex<HashMap<Uuid, broadcast::Sender<Arc<Notification>>>>,
+}
+
+/// New notifications for one player, for as long as it's held.
+pub struct Subscription {
+    notifier: Arc<Notifier>,
+    player_id: Uuid,
+    receiver: broadcast::Receiver<Arc<Notification>>,
+}
+
+impl Notifier {
+    pub fn subscribe(self: &Arc<Self>, player_id: Uuid) -> Subscription {
+        let mut players = self.players.lock().unwrap();
+        let receiver = players
+            .entry(player_id)
+            .or_insert_with(|| broadcast::channel(BACKLOG).0)
+            .subscribe();
+        Subscription {
+            notifier: self.clone(),
+            player_id,
+            receiver,
+        }
+    }
+
+    /// Players with a connection open.
+    pub fn connected(&self) -> Vec<Uuid> {
+        self.players.lock().unwrap().keys().copied().collect()
+    }
+
+    /// Hand `notification` to its player's connections, if they have any.
+    pub fn push(&self, notification: Notification) {
+        let players = self.players.lock().unwrap();
+        if let Some(sender) = players.get(&notification.player_id) {
+            // Every receiver may have gone between the check and here
+            let _ = sender.send(Arc::new(notification));
+        }
+    }
+}
+
+impl Subscription {
+    /// The next notification. Ones missed by falling behind are skipped.
+    pub async fn recv(&mut self) -> Arc<Notification> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(notification) => return notification,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                // The sender stays in the map while this receiver exists
+                Err(broadcast::error::RecvError::Closed) => std::future::pending::<()>().await,
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut players = self.notifier.players.lock().unwrap();
+        // This receiver is still alive, so 1 means it's the last
+        if players
+            .get(&self.player_id)
+            .is_some_and(|sender| sender.receiver_count() <= 1)
+        {
+            players.remove(&self.player_id);
+        }
+    }
+}
+
+/// Tell the players on the leaderboard that `player_id`, who went from
+/// `old_score` to `new_score` with a submission, has passed them. Run off
+/// the request path, so failures are only logged.
+pub async fn overtaken(state: AppState, player_id: Uuid, old_score: f64, new_score: f64) {
+    let passed = match state
+        .db
+        .overtaken_by(player_id, old_score, new_score, handlers::LEADERBOARD_SIZE)
+        .await
+    {
+        Ok(passed) if passed.is_empty() => return,
+        Ok(passed) => passed,
+        Err(e) => {
+            tracing::warn!(error = %e, "finding overtaken players failed");
+            return;
+        }
+    };
+    let by = match state.db.find_player_by_id(player_id).await {
+        Ok(Some(player)) => player.display_name,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::warn!(error = %e, "finding overtaking player failed");
+            return;
+        }
+    };
+
+    let notifications: Vec<_> = passed
+        .into_iter()
+        .map(|(passed_id, rank)| (passed_id, json!({ "by": by, "rank": rank })))
+        .collect();
+    match state
+        .db
+        .add_notifications(NotificationKind::Overtaken, &notifications)
+        .await
+    {
+        Ok(added) => added
+            .into_iter()
+            .for_each(|notification| state.notifier.push(notification)),
+        Err(e) => tracing::warn!(error = %e, "storing overtaken notifications failed"),
+    }
+}
+
+/// Send an announcement from `admin_id` to every player. Returns how many
+/// got it. Connected players' copies are stored first and pushed; everyone
+/// else's are stored in batches, so no query touches every player at once.
+pub async fn announce(
+    state: &AppState,
+    admin_id: Uuid,
+    title: &str,
+    message: &str,
+) -> Result<u64, ApiError> {
+    let data = json!({ "title": title, "message": message });
+    state
+        .db
+        .add_audit_entry(Some(admin_id), "announce", None, data.clone())
+        .await?;
+
+    let kind = NotificationKind::Announcement;
+    let connected = state.notifier.connected();
+    let copies: Vec<_> = connected.iter().map(|&id| (id, data.clone())).collect();
+    let pushed = state.db.add_notifications(kind, &copies).await?;
+    let mut notified = pushed.len() as u64;
+    for notification in pushed {
+        state.notifier.push(notification);
+    }
+
+    let mut after = None;
+    loop {
+        let (added, last) = state
+            .db
+            .add_notification_batch(kind, data.clone(), after, &connected, ANNOUNCEMENT_BATCH)
+            .await?;
+        notified += added;
+        match last {
+            Some(last) => after = Some(last),
+            None => return Ok(notified),
+        }
+    }
+}
+
+/// GET /api/notifications — The caller's notifications, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/notifications",
+    tag = "notifications",
+    params(NotificationQuery),
+    responses(
+        (status = 200, body = NotificationList),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+pub async fn list_notifications(
+    AuthPlayer(player_id): AuthPlayer,
+    State(state): State<AppState>,
+    Query(query): Query<NotificationQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let notifications = state
+        .db
+        .list_notifications(player_id, query.unread_only, query.before, limit)
+        .await?;
+    let unread = state.db.count_unread_notifications(player_id).await?;
+
+    Ok(Json(NotificationList {
+        notifications,
+        unread,
+    }))
+}
+
+/// POST /api/notifications/read — Mark some or all of the caller's notifications read.
+#[utoipa::path(
+    post,
+    path = "/api/notifications/read",
+    tag = "notifications",
+    request_body = MarkNotificationsRead,
+    responses(
+        (status = 200, body = UnreadNotifications),
+        (status = 401, description = "Missing or invalid token", body = ErrorBody),
+    ),
+    security(("bearer" = [])),
+)]
+pub async fn mark_read(
+    AuthPlayer(player_id): AuthPlayer,
+    State(state): State<AppState>,
+    Json(req): Json<MarkNotificationsRead>,
+) -> Result<impl IntoResponse, ApiError> {
+    state
+        .db
+        .mark_notifications_read(player_id, req.ids.as_deref())
+        .await?;
+    let unread = state.db.count_unread_notifications(player_id).await?;
+
+    Ok(Json(UnreadNotifications { unread }))
+}
+
+/// Delete notifications past the retention period every hour.
+pub fn spawn_pruner(db: Arc<dyn Repository>, retention: TimeDelta) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match db.prune_notifications(Utc::now() - retention).await {
+                Ok(0) => {}
+                Ok(pruned) => tracing::debug!(pruned, "pruned old notifications"),
+                Err(e) => tracing::warn!(error = %e, "pruning notifications failed"),
+            }
+        }
+    });
+}
@@ -0,0 +1,171 @@
+use std::sync::LazyLock;
+
+use axum::{
+    http::header,
+    response::{Html, IntoResponse},
+    routing::get,
+    Router,
+};
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    admin, config::Config, content, experiments, handlers, live, notifications, remote_config,
+    routes::v2,
+};
+
+/// The API description, built from the `#[utoipa::path]` attributes on the
+/// handlers and the `ToSchema` models. `openapi.json` next to `Cargo.toml`
+/// is a committed copy for the game client; a test keeps it current.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Consultancy Tycoon API",
+        description = "Accounts, leaderboard and cloud saves for the Consultancy Tycoon game.\n\n\
+            Game routes are listed at `/api`, which is version 1 and also served at `/api/v1`. \
+            `/api/v2` serves the same routes except those listed under it."
+    ),
+    paths(
+        handlers::create_player,
+        handlers::recover_player,
+        handlers::register,
+        handlers::login,
+        handlers::login_two_factor,
+        handlers::enroll_totp,
+        handlers::verify_totp,
+        handlers::disable_totp,
+        handlers::update_player,
+        handlers::submit_scores,
+        handlers::get_leaderboard,
+        v2::get_leaderboard,
+        live::stream_leaderboard,
+        notifications::list_notifications,
+        notifications::mark_read,
+        handlers::upload_save,
+        handlers::download_save,
+        handlers::jwks,
+        handlers::health_live,
+        handlers::health_ready,
+        admin::search_players,
+        admin::get_player,
+        admin::hide_player,
+        admin::unhide_player,
+        admin::reset_scores,
+        admin::ban_player,
+        admin::unban_player,
+        admin::get_audit_log,
+        admin::announce,
+        admin::list_config_versions,
+        admin::publish_config,
+        admin::rollback_config,
+        remote_config::get_config,
+        content::get_content,
+        admin::list_experiments,
+        admin::create_experiment,
+        admin::end_experiment,
+        admin::experiment_report,
+        experiments::get_assignments,
+        experiments::record_exposure,
+    ),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "players", description = "Anonymous and registered player accounts"),
+        (name = "auth", description = "Login, two-factor authentication and token keys"),
+        (name = "scores", description = "Score submission and the leaderboard"),
+        (name = "saves", description = "Cloud saves"),
+        (name = "notifications", description = "Messages for players, also pushed on the leaderboard stream"),
+        (name = "config", description = "Tuning values and feature flags for the game"),
+        (name = "content", description = "Skills, AI tools and contracts in the game"),
+        (name = "experiments", description = "A/B experiment assignments and exposures"),
+        (name = "health", description = "Liveness and readiness probes"),
+        (name = "admin", description = "Moderation; requires the admin role"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Session tokens from login, recovery or player creation go in `Authorization: Bearer`.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The document as served and committed.
+pub fn document() -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    // Filled from Cargo.toml, which has no license to report
+    doc.info.license = None;
+    doc
+}
+
+static SPEC: LazyLock<String> = LazyLock::new(|| document().to_json().expect("spec serializes"));
+
+/// Swagger UI from a CDN, pointed at our spec. Only for poking at the API
+/// by hand, so it isn't worth bundling.
+const SWAGGER_UI: &str = r##"<!doctype html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>Consultancy Tycoon API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>
+"##;
+
+/// `GET /api/openapi.json`, plus Swagger UI at `GET /api/docs` when enabled.
+pub fn router<S>(config: &Config) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let router = Router::new().route("/api/openapi.json", get(spec));
+    if config.swagger_ui_enabled() {
+        router.route("/api/docs", get(|| async { Html(SWAGGER_UI) }))
+    } else {
+        router
+    }
+}
+
+async fn spec() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], SPEC.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMITTED: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// Run with `UPDATE_OPENAPI=1` to rewrite the committed copy.
+    #[test]
+    fn committed_spec_is_up_to_date() {
+        let spec = document().to_pretty_json().unwrap() + "\n";
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(COMMITTED, &spec).unwrap();
+            return;
+        }
+
+        let committed = std::fs::read_to_string(COMMITTED).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "openapi.json is out of date; regenerate it with `UPDATE_OPENAPI=1 cargo test openapi`"
+        );
+    }
+}
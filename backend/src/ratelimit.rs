@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+use crate::{config::RateLimitConfig, error::ApiError};
+
+/// Past this many tracked clients, buckets that have refilled are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Token bucket per client IP: `burst` requests up front, refilled at the
+/// configured rate. State is in memory, so it resets on restart.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+    per_second: f64,
+    burst: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// `None` when rate limiting is turned off.
+    pub fn new(config: &RateLimitConfig) -> Option<Self> {
+        (config.auth_per_minute > 0).then(|| RateLimiter {
+            buckets: Arc::default(),
+            per_second: f64::from(config.auth_per_minute) / 60.0,
+            burst: f64::from(config.auth_burst),
+        })
+    }
+
+    /// Take a token for `client`, or say how long until one is available.
+    fn acquire(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|_, b| self.refilled(b, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+}
+
+/// Middleware rejecting clients that are over their rate with 429 and `Retry-After`.
+pub async fn limit(State(limiter): State<RateLimiter>, req: Request, next: Next) -> Response {
+    let Some(client) = client_ip(&req) else {
+        return next.run(req).await;
+    };
+
+    match limiter.acquire(client, Instant::now()) {
+        Ok(()) => next.run(req).await,
+        Err(wait) => {
+            let retry_after = (wait.as_secs_f64().ceil() as u64).max(1);
+            metrics::counter!("rate_limited_total").increment(1);
+            let error = ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "Too many requests, try again later",
+            )
+            .with_details(json!({ "retry_after_secs": retry_after }));
+            ([(header::RETRY_AFTER, retry_after.to_string())], error).into_response()
+        }
+    }
+}
+
+/// The connecting peer, or the client a local reverse proxy forwarded for.
+/// `X-Forwarded-For` is only trusted from loopback, since anyone else can
+/// set it; the proxy appends the address it saw, so the last entry is used.
+fn client_ip(req: &Request) -> Option<IpAddr> {
+    let peer = req.extensions().get::<ConnectInfo<SocketAddr>>()?.0.ip();
+    if !peer.is_loopback() {
+        return Some(peer);
+    }
+    let forwarded = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok());
+    Some(forwarded.unwrap_or(peer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_burst_then_refills() {
+        let limiter = RateLimiter::new(&RateLimitConfig {
+            auth_per_minute: 60,
+            auth_burst: 2,
+        })
+        .unwrap();
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let other = IpAddr::from([192, 0, 2, 2]);
+        let start = Instant::now();
+
+        assert!(limiter.acquire(client, start).is_ok());
+        assert!(limiter.acquire(client, start).is_ok());
+        let wait = limiter.acquire(client, start).unwrap_err();
+        assert!(wait <= Duration::from_secs(1), "{wait:?}");
+        assert!(limiter.acquire(other, start).is_ok());
+
+        assert!(limiter
+            .acquire(client, start + Duration::from_secs(1))
+            .is_ok());
+    }
+}
@@ -0,0 +1,232 @@
+//! Remote config: tuning values and feature flags the game reads at start
+//! instead of compiling in. Admins publish whole documents as new versions
+//! (`/api/admin/config`); the newest is live. Each player gets the default
+//! values with the overrides targeting them applied, and an ETag so an
+//! unchanged config costs a 304.
+
+use std::collections::BTreeMap;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    auth::OptionalAuthPlayer,
+    client_version::{self, ClientVersion},
+    error::{ApiError, FieldErrors},
+    etag,
+    models::*,
+    AppState,
+};
+
+/// GET /api/config — The live config as it applies to the caller.
+/// A missing or invalid token gets the config for anonymous players.
+#[utoipa::path(
+    get,
+    path = "/api/config",
+    tag = "config",
+    params(
+        ("If-None-Match" = Option<String>, Header, description = "ETag of the config the client has"),
+    ),
+    responses(
+        (status = 200, body = RemoteConfig, headers(("ETag" = String))),
+        (status = 304, description = "The client's copy is current"),
+    ),
+    security((), ("bearer" = [])),
+)]
+pub async fn get_config(
+    auth: OptionalAuthPlayer,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let live = state.db.get_config_version(None).await?;
+    let client = client_version::requesting_version(&headers);
+    let config = match live {
+        Some(live) => RemoteConfig {
+            version: live.version,
+            values: resolve(&live.document, auth.0, client),
+        },
+        None => RemoteConfig {
+            version: 0,
+            values: BTreeMap::new(),
+        },
+    };
+
+    // The values differ between players, so the tag is of what this one gets
+    let body = serde_json::to_vec(&config).expect("config serializes");
+    Ok(etag::json_or_not_modified(
+        &headers,
+        body.into(),
+        [
+            (
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("private, no-cache"),
+            ),
+            (
+                header::VARY,
+                HeaderValue::from_static("authorization, x-client-version"),
+            ),
+        ],
+    ))
+}
+
+/// The values for one player: the defaults, then every matching override
+/// in order.
+pub fn resolve(
+    document: &ConfigDocument,
+    player_id: Option<Uuid>,
+    client: Option<ClientVersion>,
+) -> BTreeMap<String, serde_json::Value> {
+    let mut values = document.values.clone();
+    for o in &document.overrides {
+        if targets(o, player_id, client) {
+            values.insert(o.key.clone(), o.value.clone());
+        }
+    }
+    values
+}
+
+fn targets(o: &ConfigOverride, player_id: Option<Uuid>, client: Option<ClientVersion>) -> bool {
+    // Clients that don't say their version only get untargeted overrides
+    let version = |bound: &Option<String>, ok: fn(ClientVersion, ClientVersion) -> bool| match bound
+        .as_deref()
+        .map(str::parse::<ClientVersion>)
+    {
+        None => true,
+        Some(Ok(bound)) => client.is_some_and(|client| ok(client, bound)),
+        Some(Err(_)) => false,
+    };
+    let in_rollout = match o.rollout_percent {
+        None | Some(100..) => true,
+        Some(percent) => player_id.is_some_and(|id| bucket(&o.key, id, 100) < u64::from(percent)),
+    };
+    in_rollout
+        && version(&o.min_client_version, |client, min| client >= min)
+        && version(&o.max_client_version, |client, max| client <= max)
+}
+
+/// A player's place among `buckets`, the same on every call. Salted, e.g.
+/// with the config key, so the same players aren't first in line for
+/// everything.
+pub fn bucket(salt: &str, player_id: Uuid, buckets: u64) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(b"\n");
+    hasher.update(player_id.as_bytes());
+    let hash = hasher.finalize();
+    u64::from_be_bytes(hash[..8].try_into().expect("8 bytes")) % buckets
+}
+
+/// Validate a document before it's published.
+pub fn check_document(errors: &mut FieldErrors, document: &ConfigDocument) {
+    errors.check(
+        "values",
+        document.values.keys().all(|key| !key.trim().is_empty()),
+        "keys must not be empty",
+    );
+    for (i, o) in document.overrides.iter().enumerate() {
+        errors.check(
+            "overrides",
+            document.values.contains_key(&o.key),
+            format!("#{i}: {:?} has no default in values", o.key),
+        );
+        errors.check(
+            "overrides",
+            o.rollout_percent.is_none_or(|percent| percent <= 100),
+            format!("#{i}: rollout_percent must be at most 100"),
+        );
+        for bound in [&o.min_client_version, &o.max_client_version]
+            .into_iter()
+            .flatten()
+        {
+            errors.check(
+                "overrides",
+                bound.parse::<ClientVersion>().is_ok(),
+                format!("#{i}: {bound:?} is not a version like 1.4.0"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn document(overrides: serde_json::Value) -> ConfigDocument {
+        serde_json::from_value(json!({
+            "values": { "expiry": 180, "new_events": false },
+            "overrides": overrides,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn later_matching_overrides_win() {
+        let document = document(json!([
+            { "key": "expiry", "value": 120, "min_client_version": "1.4.0" },
+            { "key": "expiry", "value": 90, "max_client_version": "1.4.9" },
+        ]));
+        let expiry = |client: Option<&str>| {
+            let client = client.map(|v| v.parse().unwrap());
+            resolve(&document, None, client)["expiry"].clone()
+        };
+        assert_eq!(expiry(None), 180);
+        assert_eq!(expiry(Some("1.3.0")), 90);
+        assert_eq!(expiry(Some("1.4.2")), 90);
+        assert_eq!(expiry(Some("1.5.0")), 120);
+    }
+
+    #[test]
+    fn rollouts_grow_without_reshuffling() {
+        let players: Vec<_> = (0..1000).map(|_| Uuid::new_v4()).collect();
+        let enabled = |percent: u8| {
+            let document = document(json!([
+                { "key": "new_events", "value": true, "rollout_percent": percent },
+            ]));
+            players
+                .iter()
+                .filter(|&&id| resolve(&document, Some(id), None)["new_events"] == true)
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        let (ten, fifty) = (enabled(10), enabled(50));
+        assert!((50..=150).contains(&ten.len()), "{}", ten.len());
+        assert!((400..=600).contains(&fifty.len()), "{}", fifty.len());
+        assert!(ten.iter().all(|id| fifty.contains(id)));
+        assert!(enabled(0).is_empty());
+        assert_eq!(enabled(100).len(), players.len());
+
+        // Players who aren't signed in only get full rollouts
+        let document = document(json!([
+            { "key": "new_events", "value": true, "rollout_percent": 99 },
+        ]));
+        assert_eq!(resolve(&document, None, None)["new_events"], false);
+    }
+
+    #[test]
+    fn checks_overrides_against_the_values() {
+        let mut errors = FieldErrors::default();
+        check_document(
+            &mut errors,
+            &document(json!([{ "key": "expiry", "value": 1, "min_client_version": "1.4" }])),
+        );
+        assert!(errors.is_empty());
+
+        for bad in [
+            json!([{ "key": "expirey", "value": 1 }]),
+            json!([{ "key": "expiry", "value": 1, "rollout_percent": 101 }]),
+            json!([{ "key": "expiry", "value": 1, "max_client_version": "latest" }]),
+        ] {
+            let mut errors = FieldErrors::default();
+            check_document(&mut errors, &document(bad.clone()));
+            assert!(!errors.is_empty(), "{bad}");
+        }
+    }
+}
@@ -0,0 +1,816 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{migrate::Migrator, PgPool};
+use uuid::Uuid;
+
+#[cfg(feature = "sqlite")]
+use crate::db::sqlite;
+use crate::{
+    config::Config,
+    db,
+    models::{
+        ActiveBan, AdminPlayerSummary, AuditLogEntry, BanKind, ConfigDocument, ConfigVersion,
+        Experiment, ExperimentVariant, IdempotentRequest, LeaderboardEntry, Notification,
+        NotificationKind, Player, Role, SaveDownload, SaveMetadata, ScoreComponents,
+        ScoreSubmission, VariantMetrics, ViewerStanding,
+    },
+};
+
+#[cfg(test)]
+pub mod memory;
+
+/// Storage used by the HTTP layer. The methods mirror the queries in `db`,
+/// which has the documentation for each; `PgRepository` is a thin wrapper
+/// around them and `memory::MemoryRepository` stands in for tests.
+///
+/// Errors are `sqlx::Error` for every backend so `ApiError` keeps mapping
+/// unique violations to 409.
+#[async_trait]
+pub trait Repository: Send + Sync {
+    // ── Players ──
+
+    async fn create_player(
+        &self,
+        id: Uuid,
+        display_name: &str,
+        passphrase: &str,
+    ) -> Result<(), sqlx::Error>;
+    async fn find_player_by_passphrase(
+        &self,
+        passphrase: &str,
+    ) -> Result<Option<Player>, sqlx::Error>;
+    async fn find_player_by_username(&self, username: &str) -> Result<Option<Player>, sqlx::Error>;
+    async fn find_player_by_id(&self, player_id: Uuid) -> Result<Option<Player>, sqlx::Error>;
+    async fn update_player(
+        &self,
+        player_id: Uuid,
+        display_name: Option<&str>,
+        show_on_leaderboard: Option<bool>,
+    ) -> Result<(), sqlx::Error>;
+    async fn set_credentials(
+        &self,
+        player_id: Uuid,
+        username: &str,
+        password_hash: &str,
+    ) -> Result<(), sqlx::Error>;
+    async fn set_pending_totp_secret(
+        &self,
+        player_id: Uuid,
+        secret: &str,
+    ) -> Result<(), sqlx::Error>;
+    async fn enable_totp(
+        &self,
+        player_id: Uuid,
+        verified_step: i64,
+        backup_code_hashes: &[String],
+    ) -> Result<(), sqlx::Error>;
+    async fn disable_totp(&self, player_id: Uuid) -> Result<(), sqlx::Error>;
+    async fn record_totp_step(&self, player_id: Uuid, step: i64) -> Result<bool, sqlx::Error>;
+    async fn consume_backup_code(
+        &self,
+        player_id: Uuid,
+        code_hash: &str,
+    ) -> Result<bool, sqlx::Error>;
+    async fn create_login_challenge(
+        &self,
+        id: Uuid,
+        player_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error>;
+    async fn login_challenge_player(&self, id: Uuid) -> Result<Option<Uuid>, sqlx::Error>;
+    async fn record_two_factor_attempt(&self, player_id: Uuid) -> Result<i64, sqlx::Error>;
+    async fn consume_login_challenge(&self, id: Uuid) -> Result<bool, sqlx::Error>;
+    async fn revoke_login_challenges(&self, player_id: Uuid) -> Result<(), sqlx::Error>;
+
+    // ── Scores ──
+
+    async fn upsert_scores(
+        &self,
+        player_id: Uuid,
+        scores: &ScoreSubmission,
+    ) -> Result<(), sqlx::Error>;
+    async fn get_leaderboard(
+        &self,
+        limit: i64,
+        viewer: Option<Uuid>,
+    ) -> Result<Vec<LeaderboardEntry>, sqlx::Error>;
+    async fn get_player_rank(&self, player_id: Uuid) -> Result<Option<(i64, f64)>, sqlx::Error>;
+    async fn viewer_standings(&self, viewers: &[Uuid]) -> Result<Vec<ViewerStanding>, sqlx::Error>;
+    async fn overtaken_by(
+        &self,
+        player_id: Uuid,
+        old_score: f64,
+        new_score: f64,
+        limit: i64,
+    ) -> Result<Vec<(Uuid, i64)>, sqlx::Error>;
+    async fn get_score_components(
+        &self,
+        player_id: Uuid,
+    ) -> Result<Option<ScoreComponents>, sqlx::Error>;
+
+    // ── Saves ──
+
+    async fn upsert_save(
+        &self,
+        player_id: Uuid,
+        save_data: &serde_json::Value,
+        version: i32,
+        compress_above: usize,
+    ) -> Result<(usize, usize), sqlx::Error>;
+    async fn get_save(&self, player_id: Uuid) -> Result<Option<SaveDownload>, sqlx::Error>;
+    async fn get_save_metadata(&self, player_id: Uuid)
+        -> Result<Option<SaveMetadata>, sqlx::Error>;
+
+    // ── Admin ──
+
+    async fn get_active_ban(&self, player_id: Uuid) -> Result<Option<ActiveBan>, sqlx::Error>;
+    async fn get_player_role(&self, player_id: Uuid) -> Result<Option<Role>, sqlx::Error>;
+    async fn search_players(
+        &self,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<AdminPlayerSummary>, sqlx::Error>;
+    async fn set_hidden_by_admin(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        hidden: bool,
+        details: serde_json::Value,
+    ) -> Result<bool, sqlx::Error>;
+    async fn reset_scores(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        details: serde_json::Value,
+    ) -> Result<bool, sqlx::Error>;
+    async fn ban_player(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        kind: BanKind,
+        reason: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, sqlx::Error>;
+    async fn unban_player(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        details: serde_json::Value,
+    ) -> Result<bool, sqlx::Error>;
+    async fn set_role(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        role: Role,
+    ) -> Result<bool, sqlx::Error>;
+    async fn get_audit_log(
+        &self,
+        target_player_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<AuditLogEntry>, sqlx::Error>;
+    async fn add_audit_entry(
+        &self,
+        admin_id: Option<Uuid>,
+        action: &str,
+        target_player_id: Option<Uuid>,
+        details: serde_json::Value,
+    ) -> Result<(), sqlx::Error>;
+
+    // ── Idempotency ──
+
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        player_id: Uuid,
+        fingerprint: &str,
+        expired_before: DateTime<Utc>,
+        abandoned_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotentRequest>, sqlx::Error>;
+    async fn complete_idempotency_key(
+        &self,
+        key: &str,
+        player_id: Uuid,
+        status: i16,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> Result<(), sqlx::Error>;
+    async fn release_idempotency_key(&self, key: &str, player_id: Uuid) -> Result<(), sqlx::Error>;
+    async fn prune_idempotency_keys(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    // ── Notifications ──
+
+    async fn add_notification(
+        &self,
+        player_id: Uuid,
+        kind: NotificationKind,
+        data: serde_json::Value,
+    ) -> Result<Notification, sqlx::Error>;
+    async fn add_notifications(
+        &self,
+        kind: NotificationKind,
+        notifications: &[(Uuid, serde_json::Value)],
+    ) -> Result<Vec<Notification>, sqlx::Error>;
+    async fn add_notification_batch(
+        &self,
+        kind: NotificationKind,
+        data: serde_json::Value,
+        after: Option<Uuid>,
+        skip: &[Uuid],
+        limit: i64,
+    ) -> Result<(u64, Option<Uuid>), sqlx::Error>;
+    async fn list_notifications(
+        &self,
+        player_id: Uuid,
+        unread_only: bool,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Notification>, sqlx::Error>;
+    async fn count_unread_notifications(&self, player_id: Uuid) -> Result<i64, sqlx::Error>;
+    async fn mark_notifications_read(
+        &self,
+        player_id: Uuid,
+        ids: Option<&[i64]>,
+    ) -> Result<u64, sqlx::Error>;
+    async fn prune_notifications(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    // ── Remote config ──
+
+    async fn publish_config(
+        &self,
+        admin_id: Option<Uuid>,
+        document: &ConfigDocument,
+        note: &str,
+        rollback_of: Option<i64>,
+    ) -> Result<ConfigVersion, sqlx::Error>;
+    async fn get_config_version(
+        &self,
+        version: Option<i64>,
+    ) -> Result<Option<ConfigVersion>, sqlx::Error>;
+    async fn list_config_versions(&self, limit: i64) -> Result<Vec<ConfigVersion>, sqlx::Error>;
+
+    // ── Experiments ──
+
+    async fn create_experiment(
+        &self,
+        admin_id: Option<Uuid>,
+        key: &str,
+        description: &str,
+        variants: &[ExperimentVariant],
+    ) -> Result<Experiment, sqlx::Error>;
+    async fn get_experiment(&self, key: &str) -> Result<Option<Experiment>, sqlx::Error>;
+    async fn list_experiments(&self, running_only: bool) -> Result<Vec<Experiment>, sqlx::Error>;
+    async fn end_experiment(
+        &self,
+        admin_id: Option<Uuid>,
+        key: &str,
+    ) -> Result<Option<Experiment>, sqlx::Error>;
+    async fn record_exposure(
+        &self,
+        key: &str,
+        player_id: Uuid,
+        variant: &str,
+    ) -> Result<(), sqlx::Error>;
+    async fn experiment_metrics(&self, key: &str) -> Result<Vec<VariantMetrics>, sqlx::Error>;
+
+    // ── Maintenance ──
+
+    async fn players_with_saves(&self) -> Result<Vec<Uuid>, sqlx::Error>;
+    async fn count_saves_before(&self, cutoff: DateTime<Utc>) -> Result<i64, sqlx::Error>;
+    async fn delete_saves_before(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error>;
+
+    // ── Health ──
+
+    async fn ping(&self) -> Result<(), sqlx::Error>;
+    async fn pending_migrations(&self) -> Result<Vec<i64>, sqlx::Error>;
+
+    /// The migrations for this backend, applied by `run_migrations`.
+    fn migrator(&self) -> &'static Migrator;
+    async fn run_migrations(&self) -> Result<(), sqlx::Error>;
+
+    /// Connection pool usage for the metrics endpoint.
+    fn pool_stats(&self) -> Option<PoolStats>;
+    /// Wait for open connections to be returned and close them.
+    async fn close(&self);
+}
+
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
+
+/// Open the backend `DATABASE_URL` points at. Migrations are left to the caller.
+pub async fn connect(config: &Config) -> Result<Arc<dyn Repository>, sqlx::Error> {
+    #[cfg(feature = "sqlite")]
+    if sqlite::is_sqlite_url(&config.database_url) {
+        let pool = sqlite::connect(config).await?;
+        return Ok(Arc::new(SqliteRepository(pool)));
+    }
+    Ok(Arc::new(PgRepository(db::connect(config).await?)))
+}
+
+/// Implements `Repository` for a pool newtype by calling the functions of
+/// the same name in a query module, which take the pool as first argument.
+macro_rules! delegate_to_queries {
+    ($repository:ty, $queries:ident) => {
+        #[async_trait]
+        impl Repository for $repository {
+            async fn create_player(
+                &self,
+                id: Uuid,
+                display_name: &str,
+                passphrase: &str,
+            ) -> Result<(), sqlx::Error> {
+                $queries::create_player(&self.0, id, display_name, passphrase).await
+            }
+
+            async fn find_player_by_passphrase(
+                &self,
+                passphrase: &str,
+            ) -> Result<Option<Player>, sqlx::Error> {
+                $queries::find_player_by_passphrase(&self.0, passphrase).await
+            }
+
+            async fn find_player_by_username(
+                &self,
+                username: &str,
+            ) -> Result<Option<Player>, sqlx::Error> {
+                $queries::find_player_by_username(&self.0, username).await
+            }
+
+            async fn find_player_by_id(
+                &self,
+                player_id: Uuid,
+            ) -> Result<Option<Player>, sqlx::Error> {
+                $queries::find_player_by_id(&self.0, player_id).await
+            }
+
+            async fn update_player(
+                &self,
+                player_id: Uuid,
+                display_name: Option<&str>,
+                show_on_leaderboard: Option<bool>,
+            ) -> Result<(), sqlx::Error> {
+                $queries::update_player(&self.0, player_id, display_name, show_on_leaderboard).await
+            }
+
+            async fn set_credentials(
+                &self,
+                player_id: Uuid,
+                username: &str,
+                password_hash: &str,
+            ) -> Result<(), sqlx::Error> {
+                $queries::set_credentials(&self.0, player_id, username, password_hash).await
+            }
+
+            async fn set_pending_totp_secret(
+                &self,
+                player_id: Uuid,
+                secret: &str,
+            ) -> Result<(), sqlx::Error> {
+                $queries::set_pending_totp_secret(&self.0, player_id, secret).await
+            }
+
+            async fn enable_totp(
+                &self,
+                player_id: Uuid,
+                verified_step: i64,
+                backup_code_hashes: &[String],
+            ) -> Result<(), sqlx::Error> {
+                $queries::enable_totp(&self.0, player_id, verified_step, backup_code_hashes).await
+            }
+
+            async fn disable_totp(&self, player_id: Uuid) -> Result<(), sqlx::Error> {
+                $queries::disable_totp(&self.0, player_id).await
+            }
+
+            async fn record_totp_step(
+                &self,
+                player_id: Uuid,
+                step: i64,
+            ) -> Result<bool, sqlx::Error> {
+                $queries::record_totp_step(&self.0, player_id, step).await
+            }
+
+            async fn consume_backup_code(
+                &self,
+                player_id: Uuid,
+                code_hash: &str,
+            ) -> Result<bool, sqlx::Error> {
+                $queries::consume_backup_code(&self.0, player_id, code_hash).await
+            }
+
+            async fn create_login_challenge(
+                &self,
+                id: Uuid,
+                player_id: Uuid,
+                expires_at: DateTime<Utc>,
+            ) -> Result<(), sqlx::Error> {
+                $queries::create_login_challenge(&self.0, id, player_id, expires_at).await
+            }
+
+            async fn login_challenge_player(&self, id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+                $queries::login_challenge_player(&self.0, id).await
+            }
+
+            async fn record_two_factor_attempt(&self, player_id: Uuid) -> Result<i64, sqlx::Error> {
+                $queries::record_two_factor_attempt(&self.0, player_id).await
+            }
+
+            async fn consume_login_challenge(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+                $queries::consume_login_challenge(&self.0, id).await
+            }
+
+            async fn revoke_login_challenges(&self, player_id: Uuid) -> Result<(), sqlx::Error> {
+                $queries::revoke_login_challenges(&self.0, player_id).await
+            }
+
+            async fn upsert_scores(
+                &self,
+                player_id: Uuid,
+                scores: &ScoreSubmission,
+            ) -> Result<(), sqlx::Error> {
+                $queries::upsert_scores(&self.0, player_id, scores).await
+            }
+
+            async fn get_leaderboard(
+                &self,
+                limit: i64,
+                viewer: Option<Uuid>,
+            ) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+                $queries::get_leaderboard(&self.0, limit, viewer).await
+            }
+
+            async fn get_player_rank(
+                &self,
+                player_id: Uuid,
+            ) -> Result<Option<(i64, f64)>, sqlx::Error> {
+                $queries::get_player_rank(&self.0, player_id).await
+            }
+
+            async fn viewer_standings(
+                &self,
+                viewers: &[Uuid],
+            ) -> Result<Vec<ViewerStanding>, sqlx::Error> {
+                $queries::viewer_standings(&self.0, viewers).await
+            }
+
+            async fn overtaken_by(
+                &self,
+                player_id: Uuid,
+                old_score: f64,
+                new_score: f64,
+                limit: i64,
+            ) -> Result<Vec<(Uuid, i64)>, sqlx::Error> {
+                $queries::overtaken_by(&self.0, player_id, old_score, new_score, limit).await
+            }
+
+            async fn get_score_components(
+                &self,
+                player_id: Uuid,
+            ) -> Result<Option<ScoreComponents>, sqlx::Error> {
+                $queries::get_score_components(&self.0, player_id).await
+            }
+
+            async fn upsert_save(
+                &self,
+                player_id: Uuid,
+                save_data: &serde_json::Value,
+                version: i32,
+                compress_above: usize,
+            ) -> Result<(usize, usize), sqlx::Error> {
+                $queries::upsert_save(&self.0, player_id, save_data, version, compress_above).await
+            }
+
+            async fn get_save(&self, player_id: Uuid) -> Result<Option<SaveDownload>, sqlx::Error> {
+                $queries::get_save(&self.0, player_id).await
+            }
+
+            async fn get_save_metadata(
+                &self,
+                player_id: Uuid,
+            ) -> Result<Option<SaveMetadata>, sqlx::Error> {
+                $queries::get_save_metadata(&self.0, player_id).await
+            }
+
+            async fn get_active_ban(
+                &self,
+                player_id: Uuid,
+            ) -> Result<Option<ActiveBan>, sqlx::Error> {
+                $queries::get_active_ban(&self.0, player_id).await
+            }
+
+            async fn get_player_role(&self, player_id: Uuid) -> Result<Option<Role>, sqlx::Error> {
+                $queries::get_player_role(&self.0, player_id).await
+            }
+
+            async fn search_players(
+                &self,
+                query: &str,
+                limit: i64,
+            ) -> Result<Vec<AdminPlayerSummary>, sqlx::Error> {
+                $queries::search_players(&self.0, query, limit).await
+            }
+
+            async fn set_hidden_by_admin(
+                &self,
+                admin_id: Option<Uuid>,
+                player_id: Uuid,
+                hidden: bool,
+                details: serde_json::Value,
+            ) -> Result<bool, sqlx::Error> {
+                $queries::set_hidden_by_admin(&self.0, admin_id, player_id, hidden, details).await
+            }
+
+            async fn reset_scores(
+                &self,
+                admin_id: Option<Uuid>,
+                player_id: Uuid,
+                details: serde_json::Value,
+            ) -> Result<bool, sqlx::Error> {
+                $queries::reset_scores(&self.0, admin_id, player_id, details).await
+            }
+
+            async fn ban_player(
+                &self,
+                admin_id: Option<Uuid>,
+                player_id: Uuid,
+                kind: BanKind,
+                reason: &str,
+                expires_at: Option<DateTime<Utc>>,
+            ) -> Result<bool, sqlx::Error> {
+                $queries::ban_player(&self.0, admin_id, player_id, kind, reason, expires_at).await
+            }
+
+            async fn unban_player(
+                &self,
+                admin_id: Option<Uuid>,
+                player_id: Uuid,
+                details: serde_json::Value,
+            ) -> Result<bool, sqlx::Error> {
+                $queries::unban_player(&self.0, admin_id, player_id, details).await
+            }
+
+            async fn get_audit_log(
+                &self,
+                target_player_id: Option<Uuid>,
+                limit: i64,
+            ) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+                $queries::get_audit_log(&self.0, target_player_id, limit).await
+            }
+
+            async fn add_audit_entry(
+                &self,
+                admin_id: Option<Uuid>,
+                action: &str,
+                target_player_id: Option<Uuid>,
+                details: serde_json::Value,
+            ) -> Result<(), sqlx::Error> {
+                $queries::add_audit_entry(&self.0, admin_id, action, target_player_id, details)
+                    .await
+            }
+
+            async fn set_role(
+                &self,
+                admin_id: Option<Uuid>,
+                player_id: Uuid,
+                role: Role,
+            ) -> Result<bool, sqlx::Error> {
+                $queries::set_role(&self.0, admin_id, player_id, role).await
+            }
+
+            async fn claim_idempotency_key(
+                &self,
+                key: &str,
+                player_id: Uuid,
+                fingerprint: &str,
+                expired_before: DateTime<Utc>,
+                abandoned_before: DateTime<Utc>,
+            ) -> Result<Option<IdempotentRequest>, sqlx::Error> {
+                $queries::claim_idempotency_key(
+                    &self.0,
+                    key,
+                    player_id,
+                    fingerprint,
+                    expired_before,
+                    abandoned_before,
+                )
+                .await
+            }
+
+            async fn complete_idempotency_key(
+                &self,
+                key: &str,
+                player_id: Uuid,
+                status: i16,
+                content_type: Option<&str>,
+                body: &[u8],
+            ) -> Result<(), sqlx::Error> {
+                $queries::complete_idempotency_key(
+                    &self.0,
+                    key,
+                    player_id,
+                    status,
+                    content_type,
+                    body,
+                )
+                .await
+            }
+
+            async fn release_idempotency_key(
+                &self,
+                key: &str,
+                player_id: Uuid,
+            ) -> Result<(), sqlx::Error> {
+                $queries::release_idempotency_key(&self.0, key, player_id).await
+            }
+
+            async fn prune_idempotency_keys(
+                &self,
+                cutoff: DateTime<Utc>,
+            ) -> Result<u64, sqlx::Error> {
+                $queries::prune_idempotency_keys(&self.0, cutoff).await
+            }
+
+            async fn add_notification(
+                &self,
+                player_id: Uuid,
+                kind: NotificationKind,
+                data: serde_json::Value,
+            ) -> Result<Notification, sqlx::Error> {
+                $queries::add_notification(&self.0, player_id, kind, data).await
+            }
+
+            async fn add_notifications(
+                &self,
+                kind: NotificationKind,
+                notifications: &[(Uuid, serde_json::Value)],
+            ) -> Result<Vec<Notification>, sqlx::Error> {
+                $queries::add_notifications(&self.0, kind, notifications).await
+            }
+
+            async fn add_notification_batch(
+                &self,
+                kind: NotificationKind,
+                data: serde_json::Value,
+                after: Option<Uuid>,
+                skip: &[Uuid],
+                limit: i64,
+            ) -> Result<(u64, Option<Uuid>), sqlx::Error> {
+                $queries::add_notification_batch(&self.0, kind, data, after, skip, limit).await
+            }
+
+            async fn list_notifications(
+                &self,
+                player_id: Uuid,
+                unread_only: bool,
+                before: Option<i64>,
+                limit: i64,
+            ) -> Result<Vec<Notification>, sqlx::Error> {
+                $queries::list_notifications(&self.0, player_id, unread_only, before, limit).await
+            }
+
+            async fn count_unread_notifications(
+                &self,
+                player_id: Uuid,
+            ) -> Result<i64, sqlx::Error> {
+                $queries::count_unread_notifications(&self.0, player_id).await
+            }
+
+            async fn mark_notifications_read(
+                &self,
+                player_id: Uuid,
+                ids: Option<&[i64]>,
+            ) -> Result<u64, sqlx::Error> {
+                $queries::mark_notifications_read(&self.0, player_id, ids).await
+            }
+
+            async fn prune_notifications(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+                $queries::prune_notifications(&self.0, cutoff).await
+            }
+
+            async fn publish_config(
+                &self,
+                admin_id: Option<Uuid>,
+                document: &ConfigDocument,
+                note: &str,
+                rollback_of: Option<i64>,
+            ) -> Result<ConfigVersion, sqlx::Error> {
+                $queries::publish_config(&self.0, admin_id, document, note, rollback_of).await
+            }
+
+            async fn get_config_version(
+                &self,
+                version: Option<i64>,
+            ) -> Result<Option<ConfigVersion>, sqlx::Error> {
+                $queries::get_config_version(&self.0, version).await
+            }
+
+            async fn list_config_versions(
+                &self,
+                limit: i64,
+            ) -> Result<Vec<ConfigVersion>, sqlx::Error> {
+                $queries::list_config_versions(&self.0, limit).await
+            }
+
+            async fn create_experiment(
+                &self,
+                admin_id: Option<Uuid>,
+                key: &str,
+                description: &str,
+                variants: &[ExperimentVariant],
+            ) -> Result<Experiment, sqlx::Error> {
+                $queries::create_experiment(&self.0, admin_id, key, description, variants).await
+            }
+
+            async fn get_experiment(&self, key: &str) -> Result<Option<Experiment>, sqlx::Error> {
+                $queries::get_experiment(&self.0, key).await
+            }
+
+            async fn list_experiments(
+                &self,
+                running_only: bool,
+            ) -> Result<Vec<Experiment>, sqlx::Error> {
+                $queries::list_experiments(&self.0, running_only).await
+            }
+
+            async fn end_experiment(
+                &self,
+                admin_id: Option<Uuid>,
+                key: &str,
+            ) -> Result<Option<Experiment>, sqlx::Error> {
+                $queries::end_experiment(&self.0, admin_id, key).await
+            }
+
+            async fn record_exposure(
+                &self,
+                key: &str,
+                player_id: Uuid,
+                variant: &str,
+            ) -> Result<(), sqlx::Error> {
+                $queries::record_exposure(&self.0, key, player_id, variant).await
+            }
+
+            async fn experiment_metrics(
+                &self,
+                key: &str,
+            ) -> Result<Vec<VariantMetrics>, sqlx::Error> {
+                $queries::experiment_metrics(&self.0, key).await
+            }
+
+            async fn players_with_saves(&self) -> Result<Vec<Uuid>, sqlx::Error> {
+                $queries::players_with_saves(&self.0).await
+            }
+
+            async fn count_saves_before(&self, cutoff: DateTime<Utc>) -> Result<i64, sqlx::Error> {
+                $queries::count_saves_before(&self.0, cutoff).await
+            }
+
+            async fn delete_saves_before(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+                $queries::delete_saves_before(&self.0, cutoff).await
+            }
+
+            async fn ping(&self) -> Result<(), sqlx::Error> {
+                $queries::ping(&self.0).await
+            }
+
+            async fn pending_migrations(&self) -> Result<Vec<i64>, sqlx::Error> {
+                $queries::pending_migrations(&self.0).await
+            }
+
+            fn migrator(&self) -> &'static Migrator {
+                &$queries::MIGRATOR
+            }
+
+            async fn run_migrations(&self) -> Result<(), sqlx::Error> {
+                Ok($queries::MIGRATOR.run(&self.0).await?)
+            }
+
+            fn pool_stats(&self) -> Option<PoolStats> {
+                Some(PoolStats {
+                    size: self.0.size(),
+                    idle: self.0.num_idle(),
+                    max: self.0.options().get_max_connections(),
+                })
+            }
+
+            async fn close(&self) {
+                self.0.close().await
+            }
+        }
+    };
+}
+
+/// The production backend.
+pub struct PgRepository(pub PgPool);
+
+delegate_to_queries!(PgRepository, db);
+
+/// SQLite, for LAN parties and local development.
+#[cfg(feature = "sqlite")]
+pub struct SqliteRepository(pub sqlx::SqlitePool);
+
+#[cfg(feature = "sqlite")]
+delegate_to_queries!(SqliteRepository, sqlite);
@@ -0,0 +1,1194 @@
+//! An in-process `Repository` for handler tests. It follows the Postgres
+//! queries closely enough for the API to behave the same: scores never go
+//! down, the leaderboard hides the same players, saves above the threshold
+//! are compressed and admin actions are audited.
+
+use std::{borrow::Cow, collections::HashMap, error::Error, fmt, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{
+    error::{DatabaseError, ErrorKind},
+    migrate::Migrator,
+};
+use uuid::Uuid;
+
+use super::{PoolStats, Repository};
+use crate::{
+    db,
+    models::{
+        ActiveBan, AdminPlayerSummary, AuditLogEntry, BanKind, ConfigDocument, ConfigVersion,
+        Experiment, ExperimentVariant, IdempotentRequest, LeaderboardEntry, Notification,
+        NotificationKind, Player, Role, SaveDownload, SaveMetadata, ScoreComponents,
+        ScoreSubmission, VariantMetrics, ViewerStanding,
+    },
+};
+
+#[derive(Default)]
+pub struct MemoryRepository {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    players: Vec<Player>,
+    scores: HashMap<Uuid, Scores>,
+    saves: HashMap<Uuid, Save>,
+    /// (player, code hash, used)
+    backup_codes: Vec<(Uuid, String, bool)>,
+    /// id to (player, expiry)
+    login_challenges: HashMap<Uuid, (Uuid, DateTime<Utc>)>,
+    two_factor_attempts: HashMap<Uuid, i64>,
+    audit_log: Vec<AuditLogEntry>,
+    /// (key, player) to the request and when it was claimed
+    idempotency: HashMap<(String, Uuid), (IdempotentRequest, DateTime<Utc>)>,
+    notifications: Vec<Notification>,
+    config_versions: Vec<ConfigVersion>,
+    experiments: Vec<Experiment>,
+    /// (experiment, player, variant)
+    exposures: Vec<(String, Uuid, String)>,
+}
+
+struct Scores {
+    components: ScoreSubmission,
+    updated_at: DateTime<Utc>,
+}
+
+struct Save {
+    data: serde_json::Value,
+    version: i32,
+    size: usize,
+    stored: usize,
+    updated_at: DateTime<Utc>,
+}
+
+/// Same weights as `db::SCORE_FORMULA`.
+fn score(s: &ScoreSubmission) -> f64 {
+    s.total_money_earned
+        + s.reputation * 500.0
+        + f64::from(s.skill_levels_sum) * 100.0
+        + f64::from(s.consultants_count) * 250.0
+        + f64::from(s.ai_tool_tiers_sum) * 150.0
+        + f64::from(s.manual_tasks_completed) * 50.0
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl State {
+    fn player(&self, id: Uuid) -> Option<&Player> {
+        self.players.iter().find(|p| p.id == id)
+    }
+
+    fn player_mut(&mut self, id: Uuid) -> Option<&mut Player> {
+        self.players.iter_mut().find(|p| p.id == id)
+    }
+
+    /// Same filter as `db::RANKED_PLAYERS`.
+    fn is_ranked(player: &Player, viewer: Option<Uuid>, now: DateTime<Utc>) -> bool {
+        player.show_on_leaderboard
+            && !player.hidden_by_admin
+            && match player.ban_kind {
+                None => true,
+                Some(kind) => {
+                    player.ban_expires_at.is_some_and(|t| t <= now)
+                        || (kind == BanKind::Shadow && Some(player.id) == viewer)
+                }
+            }
+    }
+
+    /// Ranked players as `viewer` sees them, best first and ties by id,
+    /// like the queries.
+    fn ranking(&self, viewer: Option<Uuid>) -> Vec<(&Player, &ScoreSubmission, f64)> {
+        let now = Utc::now();
+        let mut ranked: Vec<_> = self
+            .players
+            .iter()
+            .filter(|p| Self::is_ranked(p, viewer, now))
+            .filter_map(|p| {
+                let scores = &self.scores.get(&p.id)?.components;
+                Some((p, scores, score(scores)))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.id.cmp(&b.0.id)));
+        ranked
+    }
+
+    fn notify(
+        &mut self,
+        player_id: Uuid,
+        kind: NotificationKind,
+        data: serde_json::Value,
+    ) -> Notification {
+        let notification = Notification {
+            id: self.notifications.len() as i64 + 1,
+            player_id,
+            kind,
+            data,
+            created_at: Utc::now(),
+            read_at: None,
+        };
+        self.notifications.push(notification.clone());
+        notification
+    }
+
+    fn audit(
+        &mut self,
+        admin_id: Option<Uuid>,
+        action: &str,
+        target: Option<Uuid>,
+        details: serde_json::Value,
+    ) {
+        let id = self.audit_log.len() as i64 + 1;
+        self.audit_log.push(AuditLogEntry {
+            id,
+            admin_id,
+            action: action.to_string(),
+            target_player_id: target,
+            details,
+            created_at: Utc::now(),
+        });
+    }
+}
+
+/// What Postgres reports for a duplicate username, so `ApiError` maps it to 409.
+#[derive(Debug)]
+struct UniqueViolation(&'static str);
+
+impl fmt::Display for UniqueViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duplicate key value violates unique constraint {:?}",
+            self.0
+        )
+    }
+}
+
+impl Error for UniqueViolation {}
+
+impl DatabaseError for UniqueViolation {
+    fn message(&self) -> &str {
+        "duplicate key value violates unique constraint"
+    }
+
+    fn constraint(&self) -> Option<&str> {
+        Some(self.0)
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some("23505".into())
+    }
+
+    fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::UniqueViolation
+    }
+}
+
+#[async_trait]
+impl Repository for MemoryRepository {
+    async fn create_player(
+        &self,
+        id: Uuid,
+        display_name: &str,
+        passphrase: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.players.iter().any(|p| p.passphrase == passphrase) {
+            return Err(sqlx::Error::Database(Box::new(UniqueViolation(
+                "players_passphrase_key",
+            ))));
+        }
+        let now = Utc::now();
+        state.players.push(Player {
+            id,
+            display_name: display_name.to_string(),
+            passphrase: passphrase.to_string(),
+            username: None,
+            password_hash: None,
+            show_on_leaderboard: true,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_step: None,
+            role: Role::Player,
+            hidden_by_admin: false,
+            banned_at: None,
+            ban_kind: None,
+            ban_reason: None,
+            ban_expires_at: None,
+            created_at: now,
+            updated_at: now,
+        });
+        state.scores.insert(
+            id,
+            Scores {
+                components: ScoreSubmission::default(),
+                updated_at: now,
+            },
+        );
+        Ok(())
+    }
+
+    async fn find_player_by_passphrase(
+        &self,
+        passphrase: &str,
+    ) -> Result<Option<Player>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .players
+            .iter()
+            .find(|p| p.passphrase == passphrase)
+            .cloned())
+    }
+
+    async fn find_player_by_username(&self, username: &str) -> Result<Option<Player>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .players
+            .iter()
+            .find(|p| p.username.as_deref() == Some(username))
+            .cloned())
+    }
+
+    async fn find_player_by_id(&self, player_id: Uuid) -> Result<Option<Player>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.player(player_id).cloned())
+    }
+
+    async fn update_player(
+        &self,
+        player_id: Uuid,
+        display_name: Option<&str>,
+        show_on_leaderboard: Option<bool>,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(player) = state.player_mut(player_id) {
+            if let Some(name) = display_name {
+                player.display_name = name.to_string();
+            }
+            if let Some(show) = show_on_leaderboard {
+                player.show_on_leaderboard = show;
+            }
+            player.updated_at = Utc::now();
+        }
+        Ok(())
+    }
+
+    async fn set_credentials(
+        &self,
+        player_id: Uuid,
+        username: &str,
+        password_hash: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .players
+            .iter()
+            .any(|p| p.id != player_id && p.username.as_deref() == Some(username))
+        {
+            return Err(sqlx::Error::Database(Box::new(UniqueViolation(
+                "players_username_key",
+            ))));
+        }
+        if let Some(player) = state.player_mut(player_id) {
+            player.username = Some(username.to_string());
+            player.password_hash = Some(password_hash.to_string());
+            player.updated_at = Utc::now();
+        }
+        Ok(())
+    }
+
+    async fn set_pending_totp_secret(
+        &self,
+        player_id: Uuid,
+        secret: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(player) = state.player_mut(player_id) {
+            player.totp_secret = Some(secret.to_string());
+            player.totp_enabled = false;
+            player.totp_last_step = None;
+        }
+        Ok(())
+    }
+
+    async fn enable_totp(
+        &self,
+        player_id: Uuid,
+        verified_step: i64,
+        backup_code_hashes: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(player) = state.player_mut(player_id) {
+            player.totp_enabled = true;
+            player.totp_last_step = Some(verified_step);
+        }
+        state.backup_codes.retain(|(id, _, _)| *id != player_id);
+        state.backup_codes.extend(
+            backup_code_hashes
+                .iter()
+                .map(|hash| (player_id, hash.clone(), false)),
+        );
+        Ok(())
+    }
+
+    async fn disable_totp(&self, player_id: Uuid) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(player) = state.player_mut(player_id) {
+            player.totp_secret = None;
+            player.totp_enabled = false;
+            player.totp_last_step = None;
+        }
+        state.backup_codes.retain(|(id, _, _)| *id != player_id);
+        Ok(())
+    }
+
+    async fn record_totp_step(&self, player_id: Uuid, step: i64) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        match state.player_mut(player_id) {
+            Some(player) if player.totp_last_step.is_none_or(|last| last < step) => {
+                player.totp_last_step = Some(step);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn consume_backup_code(
+        &self,
+        player_id: Uuid,
+        code_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let unused = state
+            .backup_codes
+            .iter_mut()
+            .find(|(id, hash, used)| *id == player_id && hash == code_hash && !used);
+        match unused {
+            Some((_, _, used)) => {
+                *used = true;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn create_login_challenge(
+        &self,
+        id: Uuid,
+        player_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now();
+        state
+            .login_challenges
+            .retain(|_, (player, expiry)| *player != player_id || *expiry > now);
+        state.login_challenges.insert(id, (player_id, expires_at));
+        Ok(())
+    }
+
+    async fn login_challenge_player(&self, id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .login_challenges
+            .get(&id)
+            .filter(|(_, expiry)| *expiry > Utc::now())
+            .map(|(player, _)| *player))
+    }
+
+    async fn record_two_factor_attempt(&self, player_id: Uuid) -> Result<i64, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let attempts = state.two_factor_attempts.entry(player_id).or_default();
+        *attempts += 1;
+        Ok(*attempts)
+    }
+
+    async fn consume_login_challenge(&self, id: Uuid) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        match state.login_challenges.remove(&id) {
+            Some((player_id, _)) => {
+                state.two_factor_attempts.remove(&player_id);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn revoke_login_challenges(&self, player_id: Uuid) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .login_challenges
+            .retain(|_, (player, _)| *player != player_id);
+        state.two_factor_attempts.remove(&player_id);
+        Ok(())
+    }
+
+    async fn upsert_scores(
+        &self,
+        player_id: Uuid,
+        scores: &ScoreSubmission,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let entry = state.scores.entry(player_id).or_insert_with(|| Scores {
+            components: ScoreSubmission::default(),
+            updated_at: Utc::now(),
+        });
+        let c = &mut entry.components;
+        c.total_money_earned = c.total_money_earned.max(scores.total_money_earned);
+        c.reputation = c.reputation.max(scores.reputation);
+        c.skill_levels_sum = c.skill_levels_sum.max(scores.skill_levels_sum);
+        c.consultants_count = c.consultants_count.max(scores.consultants_count);
+        c.ai_tool_tiers_sum = c.ai_tool_tiers_sum.max(scores.ai_tool_tiers_sum);
+        c.manual_tasks_completed = c.manual_tasks_completed.max(scores.manual_tasks_completed);
+        entry.updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn get_leaderboard(
+        &self,
+        limit: i64,
+        viewer: Option<Uuid>,
+    ) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .ranking(viewer)
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .enumerate()
+            .map(|(i, (player, s, score))| LeaderboardEntry {
+                rank: i as i64 + 1,
+                display_name: player.display_name.clone(),
+                score,
+                total_money_earned: s.total_money_earned,
+                reputation: s.reputation,
+                skill_levels_sum: s.skill_levels_sum,
+                consultants_count: s.consultants_count,
+                ai_tool_tiers_sum: s.ai_tool_tiers_sum,
+                manual_tasks_completed: s.manual_tasks_completed,
+            })
+            .collect())
+    }
+
+    async fn get_player_rank(&self, player_id: Uuid) -> Result<Option<(i64, f64)>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .ranking(Some(player_id))
+            .into_iter()
+            .enumerate()
+            .find(|(_, (player, _, _))| player.id == player_id)
+            .map(|(i, (_, _, score))| (i as i64 + 1, score)))
+    }
+
+    async fn viewer_standings(&self, viewers: &[Uuid]) -> Result<Vec<ViewerStanding>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        let ranking = state.ranking(None);
+        Ok(viewers
+            .iter()
+            .filter_map(|&viewer| {
+                if let Some(i) = ranking.iter().position(|(p, _, _)| p.id == viewer) {
+                    return Some(ViewerStanding {
+                        player_id: viewer,
+                        rank: i as i64 + 1,
+                        score: ranking[i].2,
+                        shadow_banned: false,
+                    });
+                }
+                // Only a shadow ban ranks someone for themselves alone
+                let player = state.player(viewer)?;
+                if !State::is_ranked(player, Some(viewer), now) {
+                    return None;
+                }
+                let own = score(&state.scores.get(&viewer)?.components);
+                let ahead = ranking
+                    .iter()
+                    .filter(|(p, _, s)| *s > own || (*s == own && p.id < viewer))
+                    .count();
+                Some(ViewerStanding {
+                    player_id: viewer,
+                    rank: ahead as i64 + 1,
+                    score: own,
+                    shadow_banned: true,
+                })
+            })
+            .collect())
+    }
+
+    async fn overtaken_by(
+        &self,
+        player_id: Uuid,
+        old_score: f64,
+        new_score: f64,
+        limit: i64,
+    ) -> Result<Vec<(Uuid, i64)>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        let ranking = state.ranking(None);
+        if !ranking.iter().any(|(player, _, _)| player.id == player_id) {
+            return Ok(Vec::new());
+        }
+        Ok(ranking
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .enumerate()
+            .filter(|(_, (player, _, score))| {
+                player.id != player_id && *score > old_score && *score < new_score
+            })
+            .map(|(i, (player, _, _))| (player.id, i as i64 + 1))
+            .collect())
+    }
+
+    async fn get_score_components(
+        &self,
+        player_id: Uuid,
+    ) -> Result<Option<ScoreComponents>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.scores.get(&player_id).map(|entry| {
+            let s = &entry.components;
+            ScoreComponents {
+                score: score(s),
+                total_money_earned: s.total_money_earned,
+                reputation: s.reputation,
+                skill_levels_sum: s.skill_levels_sum,
+                consultants_count: s.consultants_count,
+                ai_tool_tiers_sum: s.ai_tool_tiers_sum,
+                manual_tasks_completed: s.manual_tasks_completed,
+                updated_at: entry.updated_at,
+            }
+        }))
+    }
+
+    async fn upsert_save(
+        &self,
+        player_id: Uuid,
+        save_data: &serde_json::Value,
+        version: i32,
+        compress_above: usize,
+    ) -> Result<(usize, usize), sqlx::Error> {
+        let json = serde_json::to_vec(save_data).map_err(|e| sqlx::Error::Encode(e.into()))?;
+        let size = json.len();
+        let stored = if size > compress_above {
+            zstd::encode_all(json.as_slice(), db::SAVE_ZSTD_LEVEL)
+                .map_err(|e| sqlx::Error::Encode(e.into()))?
+                .len()
+        } else {
+            size
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.saves.insert(
+            player_id,
+            Save {
+                data: save_data.clone(),
+                version,
+                size,
+                stored,
+                updated_at: Utc::now(),
+            },
+        );
+        Ok((size, stored))
+    }
+
+    async fn get_save(&self, player_id: Uuid) -> Result<Option<SaveDownload>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.saves.get(&player_id).map(|save| SaveDownload {
+            save_data: save.data.clone(),
+            version: save.version,
+            updated_at: save.updated_at,
+        }))
+    }
+
+    async fn get_save_metadata(
+        &self,
+        player_id: Uuid,
+    ) -> Result<Option<SaveMetadata>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.saves.get(&player_id).map(|save| SaveMetadata {
+            version: save.version,
+            size_bytes: save.size as i64,
+            stored_bytes: save.stored as i64,
+            compressed: save.stored != save.size,
+            updated_at: save.updated_at,
+        }))
+    }
+
+    async fn get_active_ban(&self, player_id: Uuid) -> Result<Option<ActiveBan>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        Ok(state.player(player_id).and_then(|p| {
+            let kind = p.ban_kind?;
+            if p.ban_expires_at.is_some_and(|t| t <= now) {
+                return None;
+            }
+            Some(ActiveBan {
+                kind,
+                reason: p.ban_reason.clone(),
+                expires_at: p.ban_expires_at,
+            })
+        }))
+    }
+
+    async fn get_player_role(&self, player_id: Uuid) -> Result<Option<Role>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.player(player_id).map(|p| p.role))
+    }
+
+    async fn search_players(
+        &self,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<AdminPlayerSummary>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        let exact_id = Uuid::parse_str(query.trim()).ok();
+        let needle = query.to_lowercase();
+        let matches = |text: &str| text.to_lowercase().contains(&needle);
+
+        let mut found: Vec<_> = state
+            .players
+            .iter()
+            .filter(|p| {
+                Some(p.id) == exact_id
+                    || matches(&p.display_name)
+                    || p.username.as_deref().is_some_and(matches)
+            })
+            .collect();
+        found.sort_by_key(|p| std::cmp::Reverse(p.created_at));
+
+        Ok(found
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .map(|p| AdminPlayerSummary {
+                id: p.id,
+                display_name: p.display_name.clone(),
+                username: p.username.clone(),
+                role: p.role,
+                show_on_leaderboard: p.show_on_leaderboard,
+                hidden_by_admin: p.hidden_by_admin,
+                banned_at: p.banned_at,
+                ban_kind: p.ban_kind,
+                ban_reason: p.ban_reason.clone(),
+                ban_expires_at: p.ban_expires_at,
+                created_at: p.created_at,
+            })
+            .collect())
+    }
+
+    async fn set_hidden_by_admin(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        hidden: bool,
+        details: serde_json::Value,
+    ) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(player) = state.player_mut(player_id) else {
+            return Ok(false);
+        };
+        player.hidden_by_admin = hidden;
+        let action = if hidden { "hide" } else { "unhide" };
+        state.audit(admin_id, action, Some(player_id), details);
+        Ok(true)
+    }
+
+    async fn reset_scores(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        details: serde_json::Value,
+    ) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(entry) = state.scores.get_mut(&player_id) else {
+            return Ok(false);
+        };
+        entry.components = ScoreSubmission::default();
+        entry.updated_at = Utc::now();
+        state.audit(admin_id, "reset_scores", Some(player_id), details);
+        Ok(true)
+    }
+
+    async fn ban_player(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        kind: BanKind,
+        reason: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(player) = state.player_mut(player_id) else {
+            return Ok(false);
+        };
+        player.banned_at = Some(Utc::now());
+        player.ban_kind = Some(kind);
+        player.ban_reason = Some(reason.to_string());
+        player.ban_expires_at = expires_at;
+        let details = serde_json::json!({
+            "kind": kind,
+            "reason": reason,
+            "expires_at": expires_at,
+        });
+        state.audit(admin_id, "ban", Some(player_id), details);
+        Ok(true)
+    }
+
+    async fn unban_player(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        details: serde_json::Value,
+    ) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(player) = state.player_mut(player_id) else {
+            return Ok(false);
+        };
+        player.banned_at = None;
+        player.ban_kind = None;
+        player.ban_reason = None;
+        player.ban_expires_at = None;
+        state.audit(admin_id, "unban", Some(player_id), details);
+        Ok(true)
+    }
+
+    async fn set_role(
+        &self,
+        admin_id: Option<Uuid>,
+        player_id: Uuid,
+        role: Role,
+    ) -> Result<bool, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(player) = state.player_mut(player_id) else {
+            return Ok(false);
+        };
+        player.role = role;
+        state.audit(
+            admin_id,
+            "set_role",
+            Some(player_id),
+            serde_json::json!({ "role": role }),
+        );
+        Ok(true)
+    }
+
+    async fn add_audit_entry(
+        &self,
+        admin_id: Option<Uuid>,
+        action: &str,
+        target_player_id: Option<Uuid>,
+        details: serde_json::Value,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.audit(admin_id, action, target_player_id, details);
+        Ok(())
+    }
+
+    async fn get_audit_log(
+        &self,
+        target_player_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .audit_log
+            .iter()
+            .rev()
+            .filter(|e| target_player_id.is_none_or(|id| e.target_player_id == Some(id)))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn claim_idempotency_key(
+        &self,
+        key: &str,
+        player_id: Uuid,
+        fingerprint: &str,
+        expired_before: DateTime<Utc>,
+        abandoned_before: DateTime<Utc>,
+    ) -> Result<Option<IdempotentRequest>, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let id = (key.to_string(), player_id);
+        if let Some((request, claimed_at)) = state.idempotency.get(&id) {
+            let stale = *claimed_at < expired_before
+                || (request.status.is_none() && *claimed_at < abandoned_before);
+            if !stale {
+                return Ok(Some(request.clone()));
+            }
+        }
+        let request = IdempotentRequest {
+            fingerprint: fingerprint.to_string(),
+            status: None,
+            content_type: None,
+            body: None,
+        };
+        state.idempotency.insert(id, (request, Utc::now()));
+        Ok(None)
+    }
+
+    async fn complete_idempotency_key(
+        &self,
+        key: &str,
+        player_id: Uuid,
+        status: i16,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if let Some((request, _)) = state.idempotency.get_mut(&(key.to_string(), player_id)) {
+            request.status = Some(status);
+            request.content_type = content_type.map(String::from);
+            request.body = Some(body.to_vec());
+        }
+        Ok(())
+    }
+
+    async fn release_idempotency_key(&self, key: &str, player_id: Uuid) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.idempotency.remove(&(key.to_string(), player_id));
+        Ok(())
+    }
+
+    async fn prune_idempotency_keys(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.idempotency.len();
+        state
+            .idempotency
+            .retain(|_, (_, claimed_at)| *claimed_at >= cutoff);
+        Ok((before - state.idempotency.len()) as u64)
+    }
+
+    async fn add_notification(
+        &self,
+        player_id: Uuid,
+        kind: NotificationKind,
+        data: serde_json::Value,
+    ) -> Result<Notification, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.notify(player_id, kind, data))
+    }
+
+    async fn add_notifications(
+        &self,
+        kind: NotificationKind,
+        notifications: &[(Uuid, serde_json::Value)],
+    ) -> Result<Vec<Notification>, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut added = Vec::new();
+        for (id, data) in notifications {
+            if state.players.iter().any(|p| p.id == *id) {
+                added.push(state.notify(*id, kind, data.clone()));
+            }
+        }
+        Ok(added)
+    }
+
+    async fn add_notification_batch(
+        &self,
+        kind: NotificationKind,
+        data: serde_json::Value,
+        after: Option<Uuid>,
+        skip: &[Uuid],
+        limit: i64,
+    ) -> Result<(u64, Option<Uuid>), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut batch: Vec<Uuid> = state
+            .players
+            .iter()
+            .map(|p| p.id)
+            .filter(|&id| after.is_none_or(|after| id > after))
+            .collect();
+        batch.sort();
+        batch.truncate(limit.max(0) as usize);
+        let last = batch.last().copied();
+        let mut added = 0;
+        for id in batch.into_iter().filter(|id| !skip.contains(id)) {
+            state.notify(id, kind, data.clone());
+            added += 1;
+        }
+        Ok((added, last))
+    }
+
+    async fn list_notifications(
+        &self,
+        player_id: Uuid,
+        unread_only: bool,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Notification>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .notifications
+            .iter()
+            .rev()
+            .filter(|n| n.player_id == player_id)
+            .filter(|n| !unread_only || n.read_at.is_none())
+            .filter(|n| before.is_none_or(|before| n.id < before))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn count_unread_notifications(&self, player_id: Uuid) -> Result<i64, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .notifications
+            .iter()
+            .filter(|n| n.player_id == player_id && n.read_at.is_none())
+            .count() as i64)
+    }
+
+    async fn mark_notifications_read(
+        &self,
+        player_id: Uuid,
+        ids: Option<&[i64]>,
+    ) -> Result<u64, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let mut marked = 0;
+        for n in &mut state.notifications {
+            if n.player_id == player_id
+                && n.read_at.is_none()
+                && ids.is_none_or(|ids| ids.contains(&n.id))
+            {
+                n.read_at = Some(Utc::now());
+                marked += 1;
+            }
+        }
+        Ok(marked)
+    }
+
+    async fn prune_notifications(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.notifications.len();
+        state.notifications.retain(|n| n.created_at >= cutoff);
+        Ok((before - state.notifications.len()) as u64)
+    }
+
+    async fn publish_config(
+        &self,
+        admin_id: Option<Uuid>,
+        document: &ConfigDocument,
+        note: &str,
+        rollback_of: Option<i64>,
+    ) -> Result<ConfigVersion, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let version = ConfigVersion {
+            version: state.config_versions.len() as i64 + 1,
+            document: document.clone(),
+            note: note.to_owned(),
+            published_by: admin_id,
+            published_at: Utc::now(),
+        };
+        state.config_versions.push(version.clone());
+        let (action, details) = db::config_audit(&version, rollback_of);
+        state.audit(admin_id, action, None, details);
+        Ok(version)
+    }
+
+    async fn get_config_version(
+        &self,
+        version: Option<i64>,
+    ) -> Result<Option<ConfigVersion>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        let found = match version {
+            Some(version) => state.config_versions.iter().find(|v| v.version == version),
+            None => state.config_versions.last(),
+        };
+        Ok(found.cloned())
+    }
+
+    async fn create_experiment(
+        &self,
+        admin_id: Option<Uuid>,
+        key: &str,
+        description: &str,
+        variants: &[ExperimentVariant],
+    ) -> Result<Experiment, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.experiments.iter().any(|e| e.key == key) {
+            return Err(sqlx::Error::Database(Box::new(UniqueViolation(
+                "experiments_pkey",
+            ))));
+        }
+        let experiment = Experiment {
+            key: key.to_owned(),
+            description: description.to_owned(),
+            variants: variants.to_vec(),
+            created_by: admin_id,
+            created_at: Utc::now(),
+            ended_at: None,
+        };
+        state.experiments.push(experiment.clone());
+        Ok(experiment)
+    }
+
+    async fn get_experiment(&self, key: &str) -> Result<Option<Experiment>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state.experiments.iter().find(|e| e.key == key).cloned())
+    }
+
+    async fn list_experiments(&self, running_only: bool) -> Result<Vec<Experiment>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .experiments
+            .iter()
+            .rev()
+            .filter(|e| !running_only || e.ended_at.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn end_experiment(
+        &self,
+        admin_id: Option<Uuid>,
+        key: &str,
+    ) -> Result<Option<Experiment>, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let Some(experiment) = state.experiments.iter_mut().find(|e| e.key == key) else {
+            return Ok(None);
+        };
+        experiment.ended_at.get_or_insert_with(Utc::now);
+        let ended = experiment.clone();
+        let details = serde_json::json!({ "key": key, "ended_at": ended.ended_at });
+        state.audit(admin_id, "end_experiment", None, details);
+        Ok(Some(ended))
+    }
+
+    async fn record_exposure(
+        &self,
+        key: &str,
+        player_id: Uuid,
+        variant: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        if !state
+            .exposures
+            .iter()
+            .any(|(k, player, _)| k == key && *player == player_id)
+        {
+            state
+                .exposures
+                .push((key.to_owned(), player_id, variant.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn experiment_metrics(&self, key: &str) -> Result<Vec<VariantMetrics>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now();
+        let mut metrics: Vec<VariantMetrics> = Vec::new();
+        for (_, player_id, variant) in state.exposures.iter().filter(|(k, _, _)| k == key) {
+            let ranked = state
+                .player(*player_id)
+                .is_some_and(|p| State::is_ranked(p, None, now));
+            let Some(scores) = state.scores.get(player_id).filter(|_| ranked) else {
+                continue;
+            };
+            let m = match metrics.iter_mut().position(|m| m.variant == *variant) {
+                Some(i) => &mut metrics[i],
+                None => {
+                    metrics.push(VariantMetrics {
+                        variant: variant.clone(),
+                        ..Default::default()
+                    });
+                    metrics.last_mut().unwrap()
+                }
+            };
+            // Sums for now, averaged below
+            let s = &scores.components;
+            m.players += 1;
+            m.avg_score += score(s);
+            m.avg_total_money_earned += s.total_money_earned;
+            m.avg_reputation += s.reputation;
+            m.avg_skill_levels_sum += f64::from(s.skill_levels_sum);
+            m.avg_consultants_count += f64::from(s.consultants_count);
+            m.avg_ai_tool_tiers_sum += f64::from(s.ai_tool_tiers_sum);
+            m.avg_manual_tasks_completed += f64::from(s.manual_tasks_completed);
+        }
+        for m in &mut metrics {
+            let n = m.players as f64;
+            for avg in [
+                &mut m.avg_score,
+                &mut m.avg_total_money_earned,
+                &mut m.avg_reputation,
+                &mut m.avg_skill_levels_sum,
+                &mut m.avg_consultants_count,
+                &mut m.avg_ai_tool_tiers_sum,
+                &mut m.avg_manual_tasks_completed,
+            ] {
+                *avg /= n;
+            }
+        }
+        Ok(metrics)
+    }
+
+    async fn list_config_versions(&self, limit: i64) -> Result<Vec<ConfigVersion>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .config_versions
+            .iter()
+            .rev()
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn players_with_saves(&self) -> Result<Vec<Uuid>, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        let mut saves: Vec<_> = state.saves.iter().collect();
+        saves.sort_by_key(|(_, save)| save.updated_at);
+        Ok(saves.into_iter().map(|(id, _)| *id).collect())
+    }
+
+    async fn count_saves_before(&self, cutoff: DateTime<Utc>) -> Result<i64, sqlx::Error> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .saves
+            .values()
+            .filter(|s| s.updated_at < cutoff)
+            .count() as i64)
+    }
+
+    async fn delete_saves_before(&self, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let mut state = self.state.lock().unwrap();
+        let before = state.saves.len();
+        state.saves.retain(|_, s| s.updated_at >= cutoff);
+        Ok((before - state.saves.len()) as u64)
+    }
+
+    async fn ping(&self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<i64>, sqlx::Error> {
+        Ok(Vec::new())
+    }
+
+    fn migrator(&self) -> &'static Migrator {
+        // Nothing to migrate; report the Postgres set for `migrate --status`
+        &db::MIGRATOR
+    }
+
+    async fn run_migrations(&self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+
+    async fn close(&self) {}
+}
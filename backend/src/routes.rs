@@ -0,0 +1,108 @@
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, Request},
+    middleware,
+    routing::{get, patch, post, put},
+    Router,
+};
+use tower::ServiceBuilder;
+use tower_http::{compression::CompressionLayer, decompression::RequestDecompressionLayer};
+
+use std::sync::Arc;
+
+use crate::{
+    admin, client_version, content, cors, experiments, handlers, idempotency, live, notifications,
+    openapi, ratelimit, remote_config, telemetry, AppState,
+};
+
+#[cfg(test)]
+mod tests;
+pub mod v2;
+
+/// The public API with its middleware. Metrics and request tracing are
+/// added by `main`, since they're process-wide.
+///
+/// The game API is mounted three times: at `/api/v1`, at `/api/v2` with the
+/// routes from `v2` replacing their v1 versions, and at plain `/api` for
+/// builds from before versioning, which get v1.
+pub fn router(state: AppState) -> Router {
+    let config = state.config.clone();
+    // Innermost on its routes, so it sees the decompressed body and the
+    // route's body limit
+    let idempotent = || middleware::from_fn_with_state(state.clone(), idempotency::idempotent);
+
+    // Endpoints that can be brute-forced or used to mass-create accounts
+    let mut rate_limited = Router::new()
+        .route(
+            "/players",
+            post(handlers::create_player).layer(idempotent()),
+        )
+        .route("/players/recover", post(handlers::recover_player))
+        .route("/players/login", post(handlers::login))
+        .route("/players/login/2fa", post(handlers::login_two_factor));
+    if let Some(limiter) = ratelimit::RateLimiter::new(&config.rate_limit) {
+        rate_limited =
+            rate_limited.route_layer(middleware::from_fn_with_state(limiter, ratelimit::limit));
+    }
+
+    // Credentials and codes only, so a small body limit
+    let auth = Router::new()
+        .route("/players/register", post(handlers::register))
+        .route("/players/2fa/enroll", post(handlers::enroll_totp))
+        .route("/players/2fa/verify", post(handlers::verify_totp))
+        .route("/players/2fa/disable", post(handlers::disable_totp))
+        .merge(rate_limited)
+        .layer(DefaultBodyLimit::max(config.body_limits.auth_bytes));
+
+    // Shaped the same in every version
+    let shared = Router::new()
+        .route("/players/me", patch(handlers::update_player))
+        .route("/scores", put(handlers::submit_scores).layer(idempotent()))
+        // Saves may be sent gzip- or zstd-compressed; the size limit applies
+        // to the decompressed body
+        .route(
+            "/saves",
+            put(handlers::upload_save).layer(
+                ServiceBuilder::new()
+                    .layer(RequestDecompressionLayer::new())
+                    .layer(DefaultBodyLimit::max(config.saves.max_bytes))
+                    .map_request(|req: Request<_>| req.map(Body::new))
+                    .layer(idempotent()),
+            ),
+        )
+        .route("/saves/me", get(handlers::download_save))
+        .route("/leaderboard/stream", get(live::stream_leaderboard))
+        .merge(notifications::router())
+        .route("/config", get(remote_config::get_config))
+        .route("/content", get(content::get_content))
+        .merge(experiments::router())
+        .merge(auth)
+        .nest("/admin", admin::router());
+
+    let v1 = shared
+        .clone()
+        .route("/leaderboard", get(handlers::get_leaderboard));
+    let mut api = Router::new()
+        .nest("/api", v1.clone())
+        .nest("/api/v1", v1)
+        .nest("/api/v2", shared.merge(v2::router()));
+    if let Some(min) = config.min_client_version() {
+        api = api.route_layer(middleware::from_fn_with_state(
+            Arc::new(min),
+            client_version::require,
+        ));
+    }
+
+    Router::new()
+        .route("/api/health", get(|| async { "ok" }))
+        .route("/api/health/live", get(handlers::health_live))
+        .route("/api/health/ready", get(handlers::health_ready))
+        .route("/.well-known/jwks.json", get(handlers::jwks))
+        .merge(api)
+        .merge(openapi::router(&config))
+        .layer(DefaultBodyLimit::max(config.body_limits.default_bytes))
+        .layer(middleware::from_fn(telemetry::track_http))
+        .layer(CompressionLayer::new())
+        .layer(cors::layer(&config.cors))
+        .with_state(state)
+}
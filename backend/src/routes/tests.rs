@@ -0,0 +1,1868 @@
+//! Every route, through the real router and middleware, on the in-memory
+//! repository and, with the `sqlite` feature, on an in-memory SQLite database.
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{Body, BodyDataStream},
+    extract::ConnectInfo,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    content::Content,
+    keys::JwtKeys,
+    live::LeaderboardFeed,
+    models::Role,
+    openapi,
+    repo::{memory::MemoryRepository, Repository},
+    totp, AppState,
+};
+
+const PASSWORD: &str = "correct horse battery";
+
+#[derive(Clone, Copy)]
+enum Backend {
+    Memory,
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+struct TestApp {
+    router: Router,
+    repo: Arc<dyn Repository>,
+}
+
+struct TestPlayer {
+    id: Uuid,
+    token: String,
+}
+
+impl TestApp {
+    async fn new(backend: Backend) -> Self {
+        Self::with_env(backend, &[]).await
+    }
+
+    /// Extra environment on top of a minimal development config.
+    async fn with_env(backend: Backend, extra: &[(&str, &str)]) -> Self {
+        let database_url = match backend {
+            Backend::Memory => "postgres://unused/db",
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => "sqlite::memory:",
+        };
+        let mut vars = vec![
+            ("DATABASE_URL", database_url),
+            ("JWT_SECRET", "0123456789abcdef0123456789abcdef"),
+            ("APP_ENV", "development"),
+        ];
+        vars.extend_from_slice(extra);
+        let config = Config::from_sources(None, |name| {
+            vars.iter()
+                .rev()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        })
+        .unwrap();
+
+        let repo: Arc<dyn Repository> = match backend {
+            Backend::Memory => Arc::new(MemoryRepository::new()),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => {
+                let repo = crate::repo::connect(&config).await.unwrap();
+                repo.run_migrations().await.unwrap();
+                repo
+            }
+        };
+        let state = AppState {
+            db: repo.clone(),
+            jwt: Arc::new(JwtKeys::from_config(&config.auth).unwrap()),
+            leaderboard: LeaderboardFeed::spawn(repo.clone(), config.leaderboard.stream_interval()),
+            notifier: Arc::default(),
+            content: Arc::new(Content::load(config.content_path.as_deref()).unwrap()),
+            config: Arc::new(config),
+            started_at: Instant::now(),
+        };
+        TestApp {
+            router: super::router(state),
+            repo,
+        }
+    }
+
+    async fn send(&self, request: Request<Body>) -> (StatusCode, Value) {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn call(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        self.send(request.body(body).unwrap()).await
+    }
+
+    /// Send `body` with an `Idempotency-Key`. The flag says whether the
+    /// response was replayed.
+    async fn with_key(
+        &self,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+        key: &str,
+        body: Value,
+    ) -> (StatusCode, bool, Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("idempotency-key", key)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let replayed = response.headers().contains_key("idempotent-replayed");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            replayed,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn get(&self, uri: &str, token: Option<&str>) -> (StatusCode, Value) {
+        self.call(Method::GET, uri, token, None).await
+    }
+
+    async fn post(&self, uri: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+        self.call(Method::POST, uri, token, Some(body)).await
+    }
+
+    async fn create_player(&self, display_name: &str) -> TestPlayer {
+        let (status, body) = self
+            .post(
+                "/api/players",
+                None,
+                json!({ "display_name": display_name }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        TestPlayer {
+            id: body["id"].as_str().unwrap().parse().unwrap(),
+            token: body["token"].as_str().unwrap().to_string(),
+        }
+    }
+
+    /// A player with `username` as both display name and username.
+    async fn registered_player(&self, username: &str) -> TestPlayer {
+        let player = self.create_player(username).await;
+        let (status, body) = self
+            .post(
+                "/api/players/register",
+                Some(&player.token),
+                json!({ "username": username, "password": PASSWORD }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        player
+    }
+
+    async fn admin(&self) -> TestPlayer {
+        let admin = self.create_player("Moderator").await;
+        let promoted = self.repo.set_role(None, admin.id, Role::Admin).await;
+        assert!(promoted.unwrap());
+        admin
+    }
+
+    async fn submit_scores(&self, player: &TestPlayer, money: f64) {
+        let (status, body) = self
+            .call(
+                Method::PUT,
+                "/api/scores",
+                Some(&player.token),
+                Some(scores(money)),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+
+    async fn leaderboard_names(&self, token: Option<&str>) -> Vec<String> {
+        let (status, body) = self.get("/api/leaderboard", token).await;
+        assert_eq!(status, StatusCode::OK);
+        body["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["display_name"].as_str().unwrap().to_string())
+            .collect()
+    }
+}
+
+/// The `leaderboard` events of a `/api/leaderboard/stream` response.
+struct LeaderboardEvents {
+    body: BodyDataStream,
+    buffer: String,
+}
+
+impl LeaderboardEvents {
+    async fn open(app: &TestApp, token: Option<&str>) -> Self {
+        let mut request = Request::get("/api/leaderboard/stream");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let request = request.body(Body::empty()).unwrap();
+        let response = app.router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        LeaderboardEvents {
+            body: response.into_body().into_data_stream(),
+            buffer: String::new(),
+        }
+    }
+
+    /// The next event's name and data. Fails if none arrives within a few
+    /// seconds.
+    async fn next_event(&mut self) -> (String, Value) {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let event: String = self.buffer.drain(..end + 2).collect();
+                let name = event.lines().find_map(|l| l.strip_prefix("event: "));
+                let data = event.lines().find_map(|l| l.strip_prefix("data: "));
+                return (
+                    name.unwrap().to_owned(),
+                    serde_json::from_str(data.unwrap()).unwrap(),
+                );
+            }
+            let chunk = tokio::time::timeout(Duration::from_secs(5), self.body.next())
+                .await
+                .expect("no event")
+                .expect("stream ended")
+                .unwrap();
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+
+    /// The next event, which must be a leaderboard update.
+    async fn next(&mut self) -> Value {
+        let (name, data) = self.next_event().await;
+        assert_eq!(name, "leaderboard", "{data}");
+        data
+    }
+
+    /// The next notification, skipping leaderboard updates before it.
+    async fn notification(&mut self) -> Value {
+        loop {
+            let (name, data) = self.next_event().await;
+            if name == "notification" {
+                return data;
+            }
+            assert_eq!(name, "leaderboard", "{data}");
+        }
+    }
+
+    /// The first update showing `expected`, skipping notifications. Several
+    /// changes close together can come through as more than one event.
+    async fn until(&mut self, expected: &[&str]) -> Value {
+        loop {
+            let (name, update) = self.next_event().await;
+            if name == "leaderboard" && names(&update) == expected {
+                return update;
+            }
+        }
+    }
+}
+
+fn names(update: &Value) -> Vec<&str> {
+    update["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["display_name"].as_str().unwrap())
+        .collect()
+}
+
+fn scores(money: f64) -> Value {
+    json!({
+        "total_money_earned": money,
+        "reputation": 1.0,
+        "skill_levels_sum": 2,
+        "consultants_count": 0,
+        "ai_tool_tiers_sum": 0,
+        "manual_tasks_completed": 0,
+    })
+}
+
+fn current_step() -> u64 {
+    totp::step_at(chrono::Utc::now().timestamp() as u64)
+}
+
+fn code(secret: &str, step: u64) -> String {
+    format!("{:06}", totp::code_at_step(secret, step).unwrap())
+}
+
+/// One `#[tokio::test]` per backend for each scenario below.
+macro_rules! backend_tests {
+    ($($name:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(super::Backend::Memory).await;
+                }
+            )*
+        }
+
+        #[cfg(feature = "sqlite")]
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(super::Backend::Sqlite).await;
+                }
+            )*
+        }
+    };
+}
+
+backend_tests!(
+    create_and_recover_player,
+    register_and_login,
+    two_factor_enrollment_login_and_disable,
+    recovery_requires_second_factor,
+    update_player,
+    scores_and_leaderboard,
+    cloud_saves,
+    health_keys_and_docs,
+    admin_routes_require_admin_role,
+    admin_moderation,
+    account_endpoints_are_rate_limited,
+    idempotency_keys_replay_responses,
+    versioned_routes,
+    outdated_clients_must_upgrade,
+    leaderboard_stream,
+    notifications,
+    remote_config,
+    experiments,
+    content,
+    spec_matches_the_router,
+);
+
+async fn create_and_recover_player(backend: Backend) {
+    let app = TestApp::new(backend).await;
+
+    let (status, body) = app
+        .post("/api/players", None, json!({ "display_name": "  Ada  " }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let passphrase = body["passphrase"].as_str().unwrap();
+
+    let (status, recovered) = app
+        .post(
+            "/api/players/recover",
+            None,
+            json!({ "passphrase": passphrase }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(recovered["id"], body["id"]);
+    assert_eq!(recovered["display_name"], "Ada");
+
+    let (status, body) = app
+        .post("/api/players", None, json!({ "display_name": " " }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "validation_failed");
+
+    let (status, body) = app
+        .post(
+            "/api/players/recover",
+            None,
+            json!({ "passphrase": "NOPE-NOPE-00" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "unknown_passphrase");
+}
+
+async fn register_and_login(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let player = app.registered_player("grace").await;
+
+    let credentials = json!({ "username": "grace", "password": PASSWORD });
+    let (status, _) = app
+        .post("/api/players/register", None, credentials.clone())
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let other = app.create_player("Other").await;
+    let (status, body) = app
+        .post(
+            "/api/players/register",
+            Some(&other.token),
+            credentials.clone(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "username_taken");
+
+    let (status, _) = app
+        .post(
+            "/api/players/register",
+            Some(&other.token),
+            json!({ "username": "x", "password": "short" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, body) = app
+        .post(
+            "/api/players/login",
+            None,
+            json!({ "username": "grace", "password": "wrong password" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_credentials");
+
+    let (status, body) = app.post("/api/players/login", None, credentials).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], player.id.to_string());
+    let (status, _) = app
+        .call(
+            Method::PATCH,
+            "/api/players/me",
+            body["token"].as_str(),
+            Some(json!({ "show_on_leaderboard": true })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn two_factor_enrollment_login_and_disable(backend: Backend) {
+    let app = TestApp::new(backend).await;
+
+    let anonymous = app.create_player("Anon").await;
+    let (status, body) = app
+        .post("/api/players/2fa/enroll", Some(&anonymous.token), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "registration_required");
+
+    let player = app.registered_player("linus").await;
+    let (status, body) = app
+        .post("/api/players/2fa/enroll", Some(&player.token), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let secret = body["secret"].as_str().unwrap().to_string();
+    assert!(body["otpauth_uri"].as_str().unwrap().contains(&secret));
+
+    let (status, _) = app
+        .post(
+            "/api/players/2fa/verify",
+            Some(&player.token),
+            json!({ "code": "000000x" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let step = current_step();
+    let (status, body) = app
+        .post(
+            "/api/players/2fa/verify",
+            Some(&player.token),
+            json!({ "code": code(&secret, step) }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let backup_code = body["backup_codes"][0].as_str().unwrap().to_string();
+
+    let (status, _) = app
+        .post("/api/players/2fa/enroll", Some(&player.token), json!({}))
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // The password alone now only gets a challenge
+    let login = || async {
+        let (status, body) = app
+            .post(
+                "/api/players/login",
+                None,
+                json!({ "username": "linus", "password": PASSWORD }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["two_factor_required"], true);
+        body["challenge_token"].as_str().unwrap().to_string()
+    };
+
+    let challenge = login().await;
+    let (status, body) = app
+        .post(
+            "/api/players/login/2fa",
+            None,
+            json!({ "challenge_token": challenge, "code": "ABCD-EFGH" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_code");
+
+    let (status, body) = app
+        .post(
+            "/api/players/login/2fa",
+            None,
+            json!({ "challenge_token": challenge, "code": backup_code }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], player.id.to_string());
+
+    // So are challenges, even with another valid code
+    let (status, body) = app
+        .post(
+            "/api/players/login/2fa",
+            None,
+            json!({ "challenge_token": challenge, "code": code(&secret, step + 1) }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_challenge");
+
+    // Too many wrong codes revoke every outstanding challenge, so guessing
+    // needs the password again after each few tries
+    let challenges = [login().await, login().await];
+    for i in 0..5 {
+        let (status, body) = app
+            .post(
+                "/api/players/login/2fa",
+                None,
+                json!({ "challenge_token": challenges[i % 2], "code": "000000" }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "invalid_code");
+    }
+    for challenge in &challenges {
+        let (status, body) = app
+            .post(
+                "/api/players/login/2fa",
+                None,
+                json!({ "challenge_token": challenge, "code": code(&secret, step + 1) }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["code"], "invalid_challenge");
+    }
+
+    // Backup codes are single use
+    let (status, _) = app
+        .post(
+            "/api/players/login/2fa",
+            None,
+            json!({ "challenge_token": login().await, "code": backup_code }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // A session token is not a challenge token
+    let (status, body) = app
+        .post(
+            "/api/players/login/2fa",
+            None,
+            json!({ "challenge_token": player.token, "code": backup_code }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_challenge");
+
+    // The enrollment code can't be replayed, but the next one works
+    let (status, _) = app
+        .post(
+            "/api/players/2fa/disable",
+            Some(&player.token),
+            json!({ "code": code(&secret, step) }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app
+        .post(
+            "/api/players/2fa/disable",
+            Some(&player.token),
+            json!({ "code": code(&secret, step + 1) }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app
+        .post(
+            "/api/players/login",
+            None,
+            json!({ "username": "linus", "password": PASSWORD }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["token"].is_string());
+}
+
+async fn recovery_requires_second_factor(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let (_, body) = app
+        .post("/api/players", None, json!({ "display_name": "Grace" }))
+        .await;
+    let passphrase = body["passphrase"].as_str().unwrap().to_string();
+    let token = body["token"].as_str().unwrap().to_string();
+    let (status, _) = app
+        .post(
+            "/api/players/register",
+            Some(&token),
+            json!({ "username": "grace", "password": PASSWORD }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = app
+        .post("/api/players/2fa/enroll", Some(&token), json!({}))
+        .await;
+    let secret = body["secret"].as_str().unwrap().to_string();
+    let step = current_step();
+    let (status, _) = app
+        .post(
+            "/api/players/2fa/verify",
+            Some(&token),
+            json!({ "code": code(&secret, step) }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The passphrase is only a first factor, like the password
+    let (status, body) = app
+        .post(
+            "/api/players/recover",
+            None,
+            json!({ "passphrase": passphrase }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["two_factor_required"], true);
+    assert!(body.get("token").is_none(), "{body}");
+
+    let (status, body) = app
+        .post(
+            "/api/players/login/2fa",
+            None,
+            json!({
+                "challenge_token": body["challenge_token"],
+                "code": code(&secret, step + 1),
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["token"].is_string());
+}
+
+async fn update_player(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let player = app.create_player("Before").await;
+    app.submit_scores(&player, 10.0).await;
+
+    let (status, _) = app
+        .call(
+            Method::PATCH,
+            "/api/players/me",
+            None,
+            Some(json!({ "display_name": "After" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = app
+        .call(
+            Method::PATCH,
+            "/api/players/me",
+            Some(&player.token),
+            Some(json!({ "display_name": "x".repeat(33) })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(
+        body["details"]["fields"]["display_name"].is_string(),
+        "{body}"
+    );
+
+    let (status, _) = app
+        .call(
+            Method::PATCH,
+            "/api/players/me",
+            Some(&player.token),
+            Some(json!({ "display_name": "After" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.leaderboard_names(None).await, ["After"]);
+
+    let (status, _) = app
+        .call(
+            Method::PATCH,
+            "/api/players/me",
+            Some(&player.token),
+            Some(json!({ "show_on_leaderboard": false })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(app.leaderboard_names(None).await.is_empty());
+}
+
+async fn scores_and_leaderboard(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let first = app.create_player("First").await;
+    let second = app.create_player("Second").await;
+    app.submit_scores(&first, 5000.0).await;
+    app.submit_scores(&second, 1000.0).await;
+
+    // Scores never go down
+    app.submit_scores(&first, 0.0).await;
+
+    let (status, body) = app.get("/api/leaderboard", Some(&second.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["entries"][0]["display_name"], "First");
+    assert_eq!(body["entries"][0]["rank"], 1);
+    assert_eq!(body["entries"][0]["score"], 5000.0 + 500.0 + 200.0);
+    assert_eq!(body["player_rank"], 2);
+    assert_eq!(body["player_score"], 1000.0 + 500.0 + 200.0);
+
+    let (_, body) = app.get("/api/leaderboard", None).await;
+    assert_eq!(body["player_rank"], Value::Null);
+
+    let (status, body) = app
+        .call(
+            Method::PUT,
+            "/api/scores",
+            Some(&first.token),
+            Some(scores(-1.0)),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(
+        body["details"]["fields"]["total_money_earned"].is_string(),
+        "{body}"
+    );
+
+    let (status, _) = app
+        .call(Method::PUT, "/api/scores", None, Some(scores(1.0)))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+async fn cloud_saves(backend: Backend) {
+    let app = TestApp::with_env(
+        backend,
+        &[
+            ("SAVE_COMPRESS_ABOVE_BYTES", "64"),
+            ("SAVE_MAX_BYTES", "4096"),
+        ],
+    )
+    .await;
+    let player = app.create_player("Saver").await;
+
+    let (status, body) = app.get("/api/saves/me", Some(&player.token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "save_not_found");
+
+    let save = json!({ "save_data": { "money": 12.5, "notes": "a".repeat(200) }, "version": 3 });
+    let (status, _) = app
+        .call(
+            Method::PUT,
+            "/api/saves",
+            Some(&player.token),
+            Some(save.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.get("/api/saves/me", Some(&player.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["save_data"], save["save_data"]);
+    assert_eq!(body["version"], 3);
+
+    // zstd-compressed uploads are unpacked before the size limit applies
+    let compressed = zstd::encode_all(
+        json!({ "save_data": { "money": 99 }, "version": 4 })
+            .to_string()
+            .as_bytes(),
+        3,
+    )
+    .unwrap();
+    let request = Request::put("/api/saves")
+        .header(header::AUTHORIZATION, format!("Bearer {}", player.token))
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_ENCODING, "zstd")
+        .body(Body::from(compressed))
+        .unwrap();
+    let (status, _) = app.send(request).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = app.get("/api/saves/me", Some(&player.token)).await;
+    assert_eq!(body["version"], 4);
+
+    let too_big = json!({ "save_data": "a".repeat(5000), "version": 5 });
+    let (status, _) = app
+        .call(
+            Method::PUT,
+            "/api/saves",
+            Some(&player.token),
+            Some(too_big),
+        )
+        .await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let (status, _) = app.get("/api/saves/me", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+async fn health_keys_and_docs(backend: Backend) {
+    let app = TestApp::new(backend).await;
+
+    let (status, _) = app.get("/api/health", None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.get("/api/health/live", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "live");
+
+    let (status, body) = app.get("/api/health/ready", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["database"], "ok");
+    assert_eq!(body["checks"]["migrations"], "ok");
+
+    let (status, body) = app.get("/.well-known/jwks.json", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["keys"].is_array());
+
+    let (status, body) = app.get("/api/openapi.json", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["paths"]["/api/leaderboard"].is_object());
+
+    let (status, _) = app.get("/api/docs", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn admin_routes_require_admin_role(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let player = app.create_player("Player").await;
+    let target = format!("/api/admin/players/{}", player.id);
+
+    let (status, _) = app.get("/api/admin/players", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    for (method, uri, body) in [
+        (Method::GET, "/api/admin/players".to_string(), None),
+        (Method::GET, target.clone(), None),
+        (Method::POST, format!("{target}/hide"), Some(json!({}))),
+        (Method::POST, format!("{target}/unhide"), Some(json!({}))),
+        (
+            Method::POST,
+            format!("{target}/reset-scores"),
+            Some(json!({})),
+        ),
+        (
+            Method::POST,
+            format!("{target}/ban"),
+            Some(json!({ "reason": "x" })),
+        ),
+        (Method::POST, format!("{target}/unban"), Some(json!({}))),
+        (Method::GET, "/api/admin/audit".to_string(), None),
+    ] {
+        let (status, _) = app.call(method, &uri, Some(&player.token), body).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{uri}");
+    }
+}
+
+async fn admin_moderation(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let admin = app.admin().await;
+    let cheater = app.registered_player("Cheater").await;
+    let honest = app.create_player("Honest").await;
+    app.submit_scores(&cheater, 1e9).await;
+    app.submit_scores(&honest, 10.0).await;
+    let target = format!("/api/admin/players/{}", cheater.id);
+    let token = Some(admin.token.as_str());
+
+    let (status, body) = app.get("/api/admin/players?q=cheat", token).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["id"], cheater.id.to_string());
+
+    let (status, body) = app.get(&target, token).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["display_name"], "Cheater");
+    assert_eq!(body["rank"], 1);
+    assert_eq!(body["scores"]["total_money_earned"], 1e9);
+    assert_eq!(body["save"], Value::Null);
+
+    let (status, _) = app
+        .get(&format!("/api/admin/players/{}", Uuid::new_v4()), token)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app
+        .post(&format!("{target}/hide"), token, json!({ "reason": "odd" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.leaderboard_names(None).await, ["Honest", "Moderator"]);
+
+    let (status, _) = app
+        .post(&format!("{target}/unhide"), token, json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        app.leaderboard_names(None).await,
+        ["Cheater", "Honest", "Moderator"]
+    );
+
+    // A shadow ban hides the player from everyone but themselves
+    let (status, _) = app
+        .post(
+            &format!("{target}/ban"),
+            token,
+            json!({ "kind": "shadow", "reason": "score tampering" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.leaderboard_names(None).await, ["Honest", "Moderator"]);
+    assert_eq!(
+        app.leaderboard_names(Some(&cheater.token)).await,
+        ["Cheater", "Honest", "Moderator"]
+    );
+
+    // A hard ban locks them out
+    let (status, body) = app
+        .post(
+            &format!("{target}/ban"),
+            token,
+            json!({ "reason": "again" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, body) = app.get("/api/saves/me", Some(&cheater.token)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "account_banned");
+    let login = json!({ "username": "Cheater", "password": PASSWORD });
+    let (status, body) = app.post("/api/players/login", None, login.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "account_banned");
+    assert_eq!(body["details"]["reason"], "again");
+
+    let (status, _) = app
+        .post(
+            &format!("{target}/ban"),
+            token,
+            json!({ "reason": "x", "expires_at": "2000-01-01T00:00:00Z" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = app.post(&format!("{target}/unban"), token, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get("/api/saves/me", Some(&cheater.token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.post("/api/players/login", None, login).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .post(&format!("{target}/reset-scores"), token, json!({}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = app.get(&target, token).await;
+    assert_eq!(body["scores"]["score"], 0.0);
+
+    let (status, body) = app
+        .get(&format!("/api/admin/audit?player_id={}", cheater.id), token)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let actions: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        actions,
+        ["reset_scores", "unban", "ban", "ban", "unhide", "hide"]
+    );
+    assert_eq!(body[0]["admin_id"], admin.id.to_string());
+    assert_eq!(body[0]["details"]["previous"]["total_money_earned"], 1e9);
+}
+
+async fn account_endpoints_are_rate_limited(backend: Backend) {
+    let app = TestApp::with_env(backend, &[("RATE_LIMIT_AUTH_BURST", "2")]).await;
+    let peer = SocketAddr::from(([192, 0, 2, 7], 40000));
+
+    let create = || {
+        let mut request = Request::post("/api/players")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({ "display_name": "Bot" }).to_string()))
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+    };
+
+    assert_eq!(app.send(create()).await.0, StatusCode::OK);
+    assert_eq!(app.send(create()).await.0, StatusCode::OK);
+    let (status, body) = app.send(create()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "rate_limited");
+}
+
+async fn idempotency_keys_replay_responses(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let create = json!({ "display_name": "Retrier" });
+    let key = Uuid::new_v4().to_string();
+
+    let (status, replayed, first) = app
+        .with_key(Method::POST, "/api/players", None, &key, create.clone())
+        .await;
+    assert_eq!(status, StatusCode::OK, "{first}");
+    assert!(!replayed);
+    let (status, replayed, retry) = app
+        .with_key(Method::POST, "/api/players", None, &key, create.clone())
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(replayed);
+    assert_eq!(retry, first);
+    // The same request whichever API version it was sent to
+    for uri in ["/api/v1/players", "/api/v2/players"] {
+        let (status, replayed, retry) = app
+            .with_key(Method::POST, uri, None, &key, create.clone())
+            .await;
+        assert_eq!(status, StatusCode::OK, "{uri}: {retry}");
+        assert!(replayed, "{uri}");
+        assert_eq!(retry, first);
+    }
+
+    let (status, _, body) = app
+        .with_key(
+            Method::POST,
+            "/api/players",
+            None,
+            &key,
+            json!({ "display_name": "Someone else" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["code"], "idempotency_key_reused");
+
+    let (status, _, body) = app
+        .with_key(Method::POST, "/api/players", None, "", create.clone())
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_idempotency_key");
+
+    // Without a player to scope to, short keys could be guessed
+    let (status, _, body) = app
+        .with_key(Method::POST, "/api/players", None, "create-1", create)
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_idempotency_key");
+
+    // Keys are per player, and failed requests don't hold on to them
+    let player = app.create_player("Scorer").await;
+    let other = app.create_player("Other").await;
+    let (status, replayed, _) = app
+        .with_key(
+            Method::PUT,
+            "/api/scores",
+            Some(&player.token),
+            "scores-1",
+            scores(-1.0),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!replayed);
+    for token in [&player.token, &player.token, &other.token] {
+        let (status, _, body) = app
+            .with_key(
+                Method::PUT,
+                "/api/scores",
+                Some(token),
+                "scores-1",
+                scores(100.0),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+    let (_, replayed, _) = app
+        .with_key(
+            Method::PUT,
+            "/api/scores",
+            Some(&other.token),
+            "scores-1",
+            scores(100.0),
+        )
+        .await;
+    assert!(replayed);
+
+    let save = json!({ "save_data": { "money": 1 }, "version": 1 });
+    for expect_replay in [false, true] {
+        let (status, replayed, _) = app
+            .with_key(
+                Method::PUT,
+                "/api/saves",
+                Some(&player.token),
+                "save-1",
+                save.clone(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(replayed, expect_replay);
+    }
+}
+
+async fn versioned_routes(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let (status, body) = app
+        .post(
+            "/api/v1/players",
+            None,
+            json!({ "display_name": "Versioned" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let player = TestPlayer {
+        id: body["id"].as_str().unwrap().parse().unwrap(),
+        token: body["token"].as_str().unwrap().to_string(),
+    };
+    app.submit_scores(&player, 1000.0).await;
+
+    let (_, unversioned) = app.get("/api/leaderboard", Some(&player.token)).await;
+    let (status, v1) = app.get("/api/v1/leaderboard", Some(&player.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v1, unversioned);
+    assert_eq!(v1["player_rank"], 1);
+
+    let (status, v2) = app.get("/api/v2/leaderboard", Some(&player.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v2["entries"], v1["entries"]);
+    assert_eq!(v2["me"], json!({ "rank": 1, "score": v1["player_score"] }));
+    assert_eq!(v2.get("player_rank"), None);
+    let (_, anonymous) = app.get("/api/v2/leaderboard", None).await;
+    assert_eq!(anonymous["me"], Value::Null);
+
+    // Unchanged routes are shared, admin included
+    let (status, _) = app.get("/api/v2/saves/me", Some(&player.token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = app.get("/api/v2/admin/audit", Some(&player.token)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "admin_required");
+    let (status, _) = app.get("/api/v3/leaderboard", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn outdated_clients_must_upgrade(backend: Backend) {
+    let app = TestApp::with_env(backend, &[("MIN_CLIENT_VERSION", "1.2.0")]).await;
+    let leaderboard = |headers: &[(&str, &str)]| {
+        let mut request = Request::get("/api/v1/leaderboard");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(Body::empty()).unwrap()
+    };
+    let godot = "GodotEngine/4.6.stable.official (Linux)";
+
+    for headers in [
+        &[("x-client-version", "1.1.9")][..],
+        &[("user-agent", godot)],
+        &[("user-agent", godot), ("x-client-version", "0.9")],
+    ] {
+        let (status, body) = app.send(leaderboard(headers)).await;
+        assert_eq!(status, StatusCode::UPGRADE_REQUIRED, "{headers:?}");
+        assert_eq!(body["code"], "client_outdated");
+        assert_eq!(body["details"]["min_client_version"], "1.2.0");
+    }
+
+    for headers in [
+        &[("x-client-version", "1.2.0")][..],
+        &[("user-agent", godot), ("x-client-version", "1.10.0-beta")],
+        &[("user-agent", "curl/8.5.0")],
+        &[],
+    ] {
+        let (status, _) = app.send(leaderboard(headers)).await;
+        assert_eq!(status, StatusCode::OK, "{headers:?}");
+    }
+
+    // Probes and docs don't depend on the game's version
+    let request = Request::get("/api/health/live")
+        .header("user-agent", godot)
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(app.send(request).await.0, StatusCode::OK);
+}
+
+async fn leaderboard_stream(backend: Backend) {
+    let app = TestApp::with_env(backend, &[("LEADERBOARD_STREAM_INTERVAL_MS", "0")]).await;
+    let alice = app.create_player("Alice").await;
+    let bob = app.create_player("Bob").await;
+    let admin = app.admin().await;
+    // Tied players are ordered by id, so start them apart
+    app.submit_scores(&alice, 100.0).await;
+    app.submit_scores(&bob, 50.0).await;
+
+    let mut watching = LeaderboardEvents::open(&app, Some(&alice.token)).await;
+    let mut anonymous = LeaderboardEvents::open(&app, None).await;
+    let first = watching.next().await;
+    assert_eq!(names(&first), ["Alice", "Bob", "Moderator"]);
+    assert_eq!(first["me"]["rank"], 1);
+    assert_eq!(anonymous.next().await["me"], Value::Null);
+
+    app.submit_scores(&bob, 2000.0).await;
+    let update = watching.until(&["Bob", "Alice", "Moderator"]).await;
+    assert_eq!(update["me"]["rank"], 2);
+    anonymous.until(&["Bob", "Alice", "Moderator"]).await;
+
+    app.submit_scores(&alice, 5000.0).await;
+    let update = watching.until(&["Alice", "Bob", "Moderator"]).await;
+    assert_eq!(update["me"]["rank"], 1);
+    assert_eq!(update["me"]["score"], update["entries"][0]["score"]);
+
+    // A shadow-banned subscriber keeps seeing themselves
+    let (status, _) = app
+        .post(
+            &format!("/api/admin/players/{}/ban", alice.id),
+            Some(&admin.token),
+            json!({ "kind": "shadow", "reason": "score tampering" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    anonymous.until(&["Bob", "Moderator"]).await;
+    app.submit_scores(&bob, 9000.0).await;
+    let update = watching.until(&["Bob", "Alice", "Moderator"]).await;
+    assert_eq!(update["me"]["rank"], 2);
+
+    // A token that is sent has to be good, and its player not banned
+    let (status, _) = app
+        .get("/api/leaderboard/stream", Some("not-a-token"))
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = app
+        .post(
+            &format!("/api/admin/players/{}/ban", bob.id),
+            Some(&admin.token),
+            json!({ "kind": "hard", "reason": "botting" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = app.get("/api/leaderboard/stream", Some(&bob.token)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "account_banned");
+}
+
+async fn notifications(backend: Backend) {
+    let app = TestApp::with_env(backend, &[("LEADERBOARD_STREAM_INTERVAL_MS", "0")]).await;
+    let alice = app.create_player("Alice").await;
+    let bob = app.create_player("Bob").await;
+    let admin = app.admin().await;
+    app.submit_scores(&alice, 1000.0).await;
+    app.submit_scores(&bob, 500.0).await;
+
+    let (status, body) = app.get("/api/notifications", Some(&alice.token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "notifications": [], "unread": 0 }));
+
+    // Passing someone on the board tells them, live and in their list
+    let mut watching = LeaderboardEvents::open(&app, Some(&alice.token)).await;
+    watching.next().await;
+    app.submit_scores(&bob, 2000.0).await;
+    let pushed = watching.notification().await;
+    assert_eq!(pushed["kind"], "overtaken");
+    assert_eq!(pushed["data"], json!({ "by": "Bob", "rank": 2 }));
+    assert_eq!(pushed["read_at"], Value::Null);
+
+    // Climbing further past nobody new sends nothing
+    app.submit_scores(&bob, 3000.0).await;
+    let (_, body) = app.get("/api/notifications", Some(&alice.token)).await;
+    assert_eq!(body["unread"], 1);
+    assert_eq!(body["notifications"], json!([pushed]));
+    let (_, body) = app.get("/api/notifications", Some(&bob.token)).await;
+    assert_eq!(body["unread"], 0);
+
+    // Announcements reach everyone
+    let (status, body) = app
+        .post(
+            "/api/admin/announcements",
+            Some(&admin.token),
+            json!({ "title": "Season 2", "message": "Ends Friday" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["notified"], 3);
+    let pushed = watching.notification().await;
+    assert_eq!(pushed["kind"], "announcement");
+    assert_eq!(
+        pushed["data"],
+        json!({ "title": "Season 2", "message": "Ends Friday" })
+    );
+    let (_, body) = app.get("/api/notifications", Some(&bob.token)).await;
+    assert_eq!(body["notifications"][0]["kind"], "announcement");
+    let (_, body) = app.get("/api/admin/audit", Some(&admin.token)).await;
+    assert_eq!(body[0]["action"], "announce");
+    assert_eq!(body[0]["admin_id"], admin.id.to_string());
+    assert_eq!(body[0]["details"], pushed["data"]);
+    let (status, _) = app
+        .post(
+            "/api/admin/announcements",
+            Some(&admin.token),
+            json!({ "title": " ", "message": "Ends Friday" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = app
+        .post(
+            "/api/admin/announcements",
+            Some(&alice.token),
+            json!({ "title": "Free money", "message": "Not really" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Newest first, with paging and an unread filter
+    let (_, body) = app.get("/api/notifications", Some(&alice.token)).await;
+    let list = body["notifications"].as_array().unwrap();
+    assert_eq!(body["unread"], 2);
+    assert_eq!(list[0]["kind"], "announcement");
+    assert_eq!(list[1]["kind"], "overtaken");
+    let uri = format!("/api/notifications?limit=1&before={}", list[0]["id"]);
+    let (_, body) = app.get(&uri, Some(&alice.token)).await;
+    assert_eq!(body["notifications"], json!([list[1]]));
+
+    let ids = json!({ "ids": [list[1]["id"]] });
+    let (status, body) = app
+        .post("/api/notifications/read", Some(&alice.token), ids)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "unread": 1 }));
+    let (_, body) = app
+        .get("/api/notifications?unread_only=true", Some(&alice.token))
+        .await;
+    assert_eq!(body["notifications"][0]["kind"], "announcement");
+    assert_eq!(body["notifications"].as_array().unwrap().len(), 1);
+
+    // Someone else's ids are left alone, and no ids means all of them
+    let ids = json!({ "ids": [list[0]["id"]] });
+    let (_, body) = app
+        .post("/api/notifications/read", Some(&bob.token), ids)
+        .await;
+    assert_eq!(body, json!({ "unread": 1 }));
+    let (_, body) = app
+        .post("/api/notifications/read", Some(&alice.token), json!({}))
+        .await;
+    assert_eq!(body, json!({ "unread": 0 }));
+    let (_, body) = app.get("/api/notifications", Some(&alice.token)).await;
+    assert!(body["notifications"][0]["read_at"].is_string());
+
+    let (status, _) = app.get("/api/notifications", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+async fn remote_config(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let admin = app.admin().await;
+    let player = app.create_player("Player").await;
+    // (status, ETag, body) of GET /api/config
+    let fetch = |headers: Vec<(&'static str, String)>| {
+        let router = app.router.clone();
+        async move {
+            let mut request = Request::get("/api/config");
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            let response = router
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let etag = response.headers()[header::ETAG]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+            (status, etag, body)
+        }
+    };
+
+    let (status, etag, body) = fetch(vec![]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "version": 0, "values": {} }));
+    let (status, _, body) = fetch(vec![("if-none-match", etag.clone())]).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(body, Value::Null);
+
+    let document = json!({
+        "values": { "random_event.expiry_time": 180, "new_events": false },
+        "overrides": [
+            { "key": "random_event.expiry_time", "value": 120, "min_client_version": "1.4.0" },
+            { "key": "new_events", "value": true, "rollout_percent": 100 },
+        ],
+    });
+    let (status, body) = app
+        .post(
+            "/api/admin/config",
+            Some(&admin.token),
+            json!({ "document": document, "note": "Shorter events" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["published_by"], admin.id.to_string());
+
+    // A new version changes the tag, so the old copy is sent again
+    let (status, etag, body) = fetch(vec![("if-none-match", etag)]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({
+            "version": 1,
+            "values": { "random_event.expiry_time": 180, "new_events": true },
+        })
+    );
+    let newer = vec![
+        ("x-client-version", "1.4.2".to_owned()),
+        ("authorization", format!("Bearer {}", player.token)),
+    ];
+    let (_, newer_etag, body) = fetch(newer.clone()).await;
+    assert_eq!(body["values"]["random_event.expiry_time"], 120);
+    assert_ne!(newer_etag, etag);
+    let mut revalidate = newer.clone();
+    revalidate.push(("if-none-match", format!("\"other\", {newer_etag}")));
+    assert_eq!(fetch(revalidate).await.0, StatusCode::NOT_MODIFIED);
+
+    // Overrides must target a key with a default, and only admins publish
+    let typo = json!({
+        "document": { "values": {}, "overrides": [{ "key": "new_event", "value": true }] },
+    });
+    let (status, body) = app
+        .post("/api/admin/config", Some(&admin.token), typo)
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["details"]["fields"]["overrides"].is_string());
+    let (status, _) = app
+        .post(
+            "/api/admin/config",
+            Some(&player.token),
+            json!({ "document": {} }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app
+        .post(
+            "/api/admin/config",
+            Some(&admin.token),
+            json!({ "document": { "values": { "new_events": false } } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        fetch(vec![]).await.2["values"],
+        json!({ "new_events": false })
+    );
+
+    // Rolling back republishes the old document as the newest version
+    let (status, body) = app
+        .post(
+            "/api/admin/config/rollback",
+            Some(&admin.token),
+            json!({ "version": 1 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["version"], 3);
+    assert_eq!(body["note"], "Rollback to version 1");
+    assert_eq!(body["document"], document);
+    let (_, rolled_back_etag, body) = fetch(vec![]).await;
+    assert_eq!(body["values"]["new_events"], true);
+    assert_ne!(rolled_back_etag, etag, "the version is part of the tag");
+    let (_, body) = app.get("/api/admin/audit", Some(&admin.token)).await;
+    assert_eq!(body[0]["action"], "rollback_config");
+    assert_eq!(body[0]["admin_id"], admin.id.to_string());
+    assert_eq!(
+        body[0]["details"],
+        json!({ "version": 3, "note": "Rollback to version 1", "rollback_of": 1 })
+    );
+    assert_eq!(body[1]["action"], "publish_config");
+    assert_eq!(body[1]["details"]["version"], 2);
+
+    let (status, body) = app
+        .post(
+            "/api/admin/config/rollback",
+            Some(&admin.token),
+            json!({ "version": 9 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "config_version_not_found");
+
+    let (status, body) = app
+        .get("/api/admin/config?limit=2", Some(&admin.token))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let versions: Vec<_> = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["version"].as_i64().unwrap())
+        .collect();
+    assert_eq!(versions, [3, 2]);
+}
+
+async fn experiments(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let admin = app.admin().await;
+    let mut players = Vec::new();
+    for i in 0..6 {
+        players.push(app.create_player(&format!("Player {i}")).await);
+    }
+
+    let experiment = json!({
+        "key": "contract_payouts",
+        "description": "Steeper payout curve",
+        "variants": [
+            { "name": "control", "weight": 1 },
+            { "name": "steep", "weight": 1 },
+        ],
+    });
+    let (status, body) = app
+        .post(
+            "/api/admin/experiments",
+            Some(&admin.token),
+            experiment.clone(),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["ended_at"], Value::Null);
+    let (status, _) = app
+        .post("/api/admin/experiments", Some(&admin.token), experiment)
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, body) = app
+        .post(
+            "/api/admin/experiments",
+            Some(&admin.token),
+            json!({ "key": "Bad Key", "variants": [{ "name": "only", "weight": 0 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields = &body["details"]["fields"];
+    assert!(fields["key"].is_string() && fields["variants"].is_string());
+
+    // Assignments are stable, and exposures record the server's choice
+    let mut variants = Vec::new();
+    for player in &players {
+        let (status, body) = app.get("/api/experiments", Some(&player.token)).await;
+        assert_eq!(status, StatusCode::OK);
+        let variant = body["assignments"]["contract_payouts"].clone();
+        assert!(variant == "control" || variant == "steep", "{variant}");
+        let (_, again) = app.get("/api/experiments", Some(&player.token)).await;
+        assert_eq!(again["assignments"]["contract_payouts"], variant);
+        variants.push(variant);
+    }
+    let (status, _) = app.get("/api/experiments", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Players 0 to 3 see their variant, one of them twice; the rest never do
+    for (i, player) in players[..4].iter().enumerate() {
+        app.submit_scores(player, 1000.0 * (i + 1) as f64).await;
+        let (status, body) = app
+            .post(
+                "/api/experiments/contract_payouts/exposures",
+                Some(&player.token),
+                json!({}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["variant"], variants[i]);
+    }
+    app.post(
+        "/api/experiments/contract_payouts/exposures",
+        Some(&players[0].token),
+        json!({}),
+    )
+    .await;
+    app.submit_scores(&players[5], 99_000.0).await;
+    let (status, body) = app
+        .post(
+            "/api/experiments/nope/exposures",
+            Some(&players[0].token),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "experiment_not_found");
+
+    // Banned players' scores are left out of the report
+    let (status, _) = app
+        .post(
+            &format!("/api/admin/players/{}/ban", players[3].id),
+            Some(&admin.token),
+            json!({ "kind": "hard", "reason": "botting" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, report) = app
+        .get(
+            "/api/admin/experiments/contract_payouts/report",
+            Some(&admin.token),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let report_variants = report["variants"].as_array().unwrap();
+    assert_eq!(report_variants[0]["variant"], "control");
+    assert_eq!(report_variants[1]["variant"], "steep");
+    assert_eq!(report_variants[0]["score_change_vs_control"], Value::Null);
+    for report_variant in report_variants {
+        let name = &report_variant["variant"];
+        let money: Vec<f64> = (0..3)
+            .filter(|&i| &variants[i] == name)
+            .map(|i| 1000.0 * (i + 1) as f64)
+            .collect();
+        assert_eq!(report_variant["players"], money.len(), "{report}");
+        if !money.is_empty() {
+            let avg = money.iter().sum::<f64>() / money.len() as f64;
+            assert_eq!(report_variant["avg_total_money_earned"], avg, "{report}");
+        }
+    }
+
+    // Ending keeps the report but stops assignments and exposures
+    let (status, body) = app
+        .post(
+            "/api/admin/experiments/contract_payouts/end",
+            Some(&admin.token),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["ended_at"].is_string());
+    let (_, audit) = app.get("/api/admin/audit", Some(&admin.token)).await;
+    assert_eq!(audit[0]["action"], "end_experiment");
+    assert_eq!(audit[0]["admin_id"], admin.id.to_string());
+    assert_eq!(audit[0]["details"]["key"], "contract_payouts");
+    let (_, body) = app.get("/api/experiments", Some(&players[0].token)).await;
+    assert_eq!(body["assignments"], json!({}));
+    let (status, body) = app
+        .post(
+            "/api/experiments/contract_payouts/exposures",
+            Some(&players[0].token),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "experiment_ended");
+
+    let (_, body) = app.get("/api/admin/experiments", Some(&admin.token)).await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    let (status, _) = app
+        .get("/api/admin/experiments", Some(&players[0].token))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+async fn content(backend: Backend) {
+    let app = TestApp::new(backend).await;
+
+    // No token needed; the catalog is the same for everyone
+    let response = app
+        .router
+        .clone()
+        .oneshot(Request::get("/api/v2/content").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[header::ETAG].clone();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(body["version"], 1);
+    let skill = &body["skills"][0];
+    assert_eq!(skill["id"], "coding_speed");
+    assert_eq!(skill["category"], "soft_skill");
+    assert_eq!(body["ai_tools"][0]["target_state"], "writing");
+    assert_eq!(body["contracts"]["tiers"][3]["tier"], 4);
+
+    let response = app
+        .router
+        .clone()
+        .oneshot(
+            Request::get("/api/content")
+                .header(header::IF_NONE_MATCH, etag)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+/// Served outside the documented API.
+const UNDOCUMENTED: &[&str] = &["/api/health", "/api/openapi.json", "/api/docs", "/metrics"];
+
+/// (method, path) of every `.route(..)` in the router sources. axum can't
+/// list a router's routes, so they're read from the code: paths in
+/// `admin.rs` are under `/api/admin`, those in `routes/v2.rs` under
+/// `/api/v2`, `/metrics` is at the root and any other relative path is
+/// under `/api`.
+fn registered_routes() -> Vec<(String, String)> {
+    const METHODS: &[&str] = &["get", "post", "put", "patch", "delete"];
+    let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut files = vec![src.clone()];
+    let mut routes = Vec::new();
+    while let Some(path) = files.pop() {
+        if path.is_dir() {
+            files.extend(std::fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()));
+            continue;
+        }
+        let file = path
+            .strip_prefix(&src)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        if !file.ends_with(".rs") || file.ends_with("tests.rs") {
+            continue;
+        }
+        let prefix = match file.as_str() {
+            "admin.rs" => "/api/admin",
+            "routes/v2.rs" => "/api/v2",
+            "telemetry.rs" => "",
+            _ => "/api",
+        };
+
+        let code = std::fs::read_to_string(&path).unwrap();
+        for (start, _) in code.match_indices(".route(") {
+            let rest = code[start + ".route(".len()..].trim_start();
+            let Some(rest) = rest.strip_prefix('"') else {
+                continue;
+            };
+            let (route, rest) = rest.split_once('"').unwrap();
+            let path = if route.starts_with("/api") || route.starts_with("/.") {
+                route.to_owned()
+            } else {
+                format!("{prefix}{route}")
+            };
+
+            // Method routers are the calls at the top level of the argument
+            let mut depth = 1;
+            let mut ident = String::new();
+            for c in rest.chars() {
+                match c {
+                    '(' => {
+                        if depth == 1 && METHODS.contains(&ident.as_str()) {
+                            routes.push((ident.to_uppercase(), path.clone()));
+                        }
+                        depth += 1;
+                    }
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 {
+                    break;
+                }
+                if c.is_alphanumeric() || c == '_' {
+                    ident.push(c);
+                } else {
+                    ident.clear();
+                }
+            }
+        }
+    }
+    routes.sort();
+    routes.dedup();
+    routes
+}
+
+async fn spec_matches_the_router(backend: Backend) {
+    let app = TestApp::new(backend).await;
+    let spec = serde_json::to_value(openapi::document()).unwrap();
+    let documented: Vec<(String, String)> = spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, item)| {
+            item.as_object()
+                .unwrap()
+                .keys()
+                .map(|method| (method.to_uppercase(), path.clone()))
+        })
+        .collect();
+
+    let registered = registered_routes();
+    assert!(registered.len() > 30, "found only {registered:?}");
+    for (method, path) in &registered {
+        assert!(
+            documented.contains(&(method.clone(), path.clone()))
+                || UNDOCUMENTED.contains(&path.as_str()),
+            "{method} {path} is routed but not in the spec; add its handler to openapi::ApiDoc"
+        );
+    }
+
+    // And everything documented is routed. An unrouted path gets axum's
+    // bare 404 or a 405; handlers always answer with a body.
+    for (method, path) in &documented {
+        let uri = path
+            .replace("{id}", &Uuid::nil().to_string())
+            .replace("{key}", "missing");
+        let request = Request::builder()
+            .method(method.as_str())
+            .uri(&uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{method} {path}");
+        if status == StatusCode::NOT_FOUND {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(
+                !body.is_empty(),
+                "{method} {path} is documented but not routed"
+            );
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! `/api/v2`: the routes whose responses have changed shape since v1. Every
+//! other route is shared with v1 and mounted alongside these by
+//! `routes::router`; a route moves here when its v2 shape diverges.
+
+use axum::{extract::State, response::IntoResponse, routing::get, Router};
+
+use crate::{
+    auth::OptionalAuthPlayer,
+    error::{ApiError, Json},
+    handlers,
+    models::LeaderboardResponseV2,
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/leaderboard", get(get_leaderboard))
+}
+
+/// GET /api/v2/leaderboard — Top 50, with the caller's standing as `me`.
+#[utoipa::path(
+    get,
+    path = "/api/v2/leaderboard",
+    operation_id = "get_leaderboard_v2",
+    tag = "scores",
+    responses(
+        (status = 200, body = LeaderboardResponseV2),
+    ),
+    security((), ("bearer" = [])),
+)]
+pub async fn get_leaderboard(
+    auth: OptionalAuthPlayer,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, ApiError> {
+    let (entries, me) = handlers::leaderboard(&state, auth.0).await?;
+    Ok(Json(LeaderboardResponseV2 { entries, me }))
+}
@@ -0,0 +1,170 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderName, Response},
+    middleware::Next,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use tower::ServiceBuilder;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+use crate::repo::Repository;
+
+const DEFAULT_FILTER: &str = "consultancy_tycoon_api=info,tower_http=warn,sqlx=warn";
+
+/// Histogram buckets in seconds, shared by every `*_duration_seconds` metric.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Install the global subscriber.
+///
+/// `RUST_LOG` sets the level filter (e.g. `debug` or
+/// `consultancy_tycoon_api=debug,sqlx=info`). `LOG_FORMAT=json` writes one
+/// JSON object per line; the default is plain text, without timestamps and
+/// colours when running under systemd since journald adds its own. Logs go
+/// to stderr, leaving stdout to the management commands' output.
+pub fn init() {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+
+    if std::env::var("LOG_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("json")) {
+        builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init();
+    } else if std::env::var_os("JOURNAL_STREAM").is_some() {
+        builder.with_ansi(false).without_time().init();
+    } else {
+        builder.init();
+    }
+}
+
+/// Give every request an id (reusing the client's `x-request-id` if it sent
+/// one), run the handler inside a span carrying it, echo it back in the
+/// response and log status and latency once the response is ready.
+pub fn trace_requests<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(make_span)
+                    .on_request(())
+                    .on_response(on_response)
+                    .on_failure(()),
+            )
+            .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER)),
+    )
+}
+
+fn make_span(req: &Request) -> Span {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+
+    tracing::info_span!(
+        "request",
+        request_id,
+        method = %req.method(),
+        path = req.uri().path(),
+    )
+}
+
+fn on_response<B>(res: &Response<B>, latency: Duration, _span: &Span) {
+    let status = res.status().as_u16();
+    let latency_ms = latency.as_secs_f64() * 1000.0;
+    if res.status().is_server_error() {
+        tracing::error!(status, latency_ms, "request failed");
+    } else {
+        tracing::info!(status, latency_ms, "request finished");
+    }
+}
+
+/// Install the global Prometheus recorder. Metrics recorded before this are lost.
+pub fn install_metrics() -> PrometheusHandle {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("duration_seconds".to_string()),
+            LATENCY_BUCKETS,
+        )
+        .expect("latency buckets are not empty")
+        .install_recorder()
+        .expect("Failed to install metrics recorder")
+}
+
+/// Middleware recording request count and latency per route template, so
+/// `/api/admin/players/{id}` is one series rather than one per player.
+pub async fn track_http(req: Request, next: Next) -> axum::response::Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |p| p.as_str().to_string());
+    let method = req.method().to_string();
+    let started = Instant::now();
+
+    let res = next.run(req).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", res.status().as_u16().to_string()),
+    ];
+    metrics::counter!("http_requests_total", &labels).increment(1);
+    metrics::histogram!("http_request_duration_seconds", &labels)
+        .record(started.elapsed().as_secs_f64());
+    res
+}
+
+#[derive(Clone)]
+struct MetricsState {
+    handle: PrometheusHandle,
+    db: Arc<dyn Repository>,
+}
+
+/// `GET /metrics` in the Prometheus text format. Either merged into the main
+/// app or served on its own listener (`METRICS_ADDR`).
+pub fn metrics_router(handle: PrometheusHandle, db: Arc<dyn Repository>) -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(MetricsState { handle, db })
+}
+
+async fn render_metrics(State(state): State<MetricsState>) -> impl IntoResponse {
+    // Pool gauges are sampled at scrape time rather than tracked continuously
+    if let Some(pool) = state.db.pool_stats() {
+        metrics::gauge!("db_pool_connections").set(pool.size as f64);
+        metrics::gauge!("db_pool_idle_connections").set(pool.idle as f64);
+        metrics::gauge!("db_pool_max_connections").set(pool.max as f64);
+    }
+
+    state.handle.run_upkeep();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.handle.render(),
+    )
+}
@@ -0,0 +1,190 @@
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use tokio::{
+    net::{TcpListener, TcpStream},
+    signal::unix::SignalKind,
+    sync::mpsc,
+};
+use tokio_rustls::{
+    rustls::{
+        crypto::{ring, CryptoProvider},
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+        ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+
+/// Clients that haven't finished the handshake by now are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshakes run concurrently; this many finished connections can wait for
+/// the server to pick them up.
+const ACCEPT_QUEUE: usize = 64;
+
+/// TLS on top of a TCP listener, for running without a reverse proxy.
+///
+/// Handshakes happen on their own tasks so one slow client can't hold up
+/// everyone else's `accept`.
+pub struct TlsListener {
+    accepted: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    /// Load the certificate and start accepting. SIGHUP reloads the
+    /// certificate from the same files, e.g. after a renewal.
+    pub fn new(listener: TcpListener, cert_path: &Path, key_path: &Path) -> io::Result<Self> {
+        let provider = Arc::new(ring::default_provider());
+        let resolver = Arc::new(ReloadingCert::load(cert_path, key_path, provider.clone())?);
+        reload_on_sighup(resolver.clone());
+
+        let server_config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+
+        let local_addr = listener.local_addr()?;
+        let (tx, accepted) = mpsc::channel(ACCEPT_QUEUE);
+        tokio::spawn(accept_loop(listener, acceptor, tx));
+        Ok(TlsListener {
+            accepted,
+            local_addr,
+        })
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.accepted.recv().await {
+            Some(conn) => conn,
+            // The accept loop only ends once we're dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+) {
+    loop {
+        let (stream, peer) = tokio::select! {
+            _ = tx.closed() => return,
+            accepted = listener.accept() => match accepted {
+                Ok(conn) => conn,
+                Err(e) => {
+                    // Usually out of file descriptors; back off like axum does
+                    tracing::error!(error = %e, "accept failed");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+        };
+
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = tx.send((stream, peer)).await;
+                }
+                Ok(Err(e)) => tracing::debug!(%peer, error = %e, "TLS handshake failed"),
+                Err(_) => tracing::debug!(%peer, "TLS handshake timed out"),
+            }
+        });
+    }
+}
+
+/// Serves whichever certificate was loaded last.
+#[derive(Debug)]
+struct ReloadingCert {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadingCert {
+    fn load(cert_path: &Path, key_path: &Path, provider: Arc<CryptoProvider>) -> io::Result<Self> {
+        let current = RwLock::new(load_cert(cert_path, key_path, &provider)?);
+        Ok(ReloadingCert {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            provider,
+            current,
+        })
+    }
+
+    /// Swap in the certificate on disk. On failure the old one stays in use.
+    fn reload(&self) -> io::Result<()> {
+        let cert = load_cert(&self.cert_path, &self.key_path, &self.provider)?;
+        *self.current.write().unwrap() = cert;
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ReloadingCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// A PEM certificate chain and the private key for its first certificate.
+fn load_cert(
+    cert_path: &Path,
+    key_path: &Path,
+    provider: &CryptoProvider,
+) -> io::Result<Arc<CertifiedKey>> {
+    let with_path = |path: &Path, e: &dyn std::fmt::Display| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {e}", path.display()),
+        )
+    };
+
+    let chain = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| with_path(cert_path, &e))?;
+    if chain.is_empty() {
+        return Err(with_path(cert_path, &"no certificates found"));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| with_path(key_path, &e))?;
+
+    // Also checks that the key belongs to the certificate
+    let cert = CertifiedKey::from_der(chain, key, provider).map_err(|e| with_path(key_path, &e))?;
+    Ok(Arc::new(cert))
+}
+
+fn reload_on_sighup(cert: Arc<ReloadingCert>) {
+    let mut hup = tokio::signal::unix::signal(SignalKind::hangup())
+        .expect("Failed to install SIGHUP handler");
+    tokio::spawn(async move {
+        while hup.recv().await.is_some() {
+            match cert.reload() {
+                Ok(()) => tracing::info!("Reloaded TLS certificate"),
+                Err(e) => {
+                    tracing::error!(error = %e, "TLS certificate reload failed, keeping the old one")
+                }
+            }
+        }
+    });
+}
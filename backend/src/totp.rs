@@ -0,0 +1,196 @@
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// Length of one TOTP time step in seconds (RFC 6238 default).
+pub const STEP_SECONDS: u64 = 30;
+
+/// Number of steps before/after the current one that are still accepted,
+/// to tolerate clock drift on the player's device.
+pub const ALLOWED_SKEW: u64 = 1;
+
+const DIGITS: u32 = 6;
+const SECRET_BYTES: usize = 20;
+const BACKUP_CODE_COUNT: usize = 10;
+const BACKUP_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+const BASE32: base32::Alphabet = base32::Alphabet::Rfc4648 { padding: false };
+
+/// Generate a new random shared secret, base32-encoded for authenticator apps.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::rng().fill(&mut bytes);
+    base32::encode(BASE32, &bytes)
+}
+
+/// Build the `otpauth://` URI that authenticator apps scan as a QR code.
+pub fn otpauth_uri(secret: &str, account: &str, issuer: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer_label}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+        issuer_label = percent_encode(issuer),
+        account = percent_encode(account),
+        issuer = percent_encode(issuer),
+    )
+}
+
+/// The time step a unix timestamp falls into.
+pub fn step_at(unix_time: u64) -> u64 {
+    unix_time / STEP_SECONDS
+}
+
+/// Check a submitted code against the secret at `unix_time`.
+///
+/// Returns the matched time step so the caller can reject replays of the same
+/// code; steps at or before `last_used_step` are never accepted.
+pub fn verify(
+    secret: &str,
+    code: &str,
+    unix_time: u64,
+    last_used_step: Option<u64>,
+) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let key = base32::decode(BASE32, secret)?;
+
+    let current = step_at(unix_time);
+    let first = current.saturating_sub(ALLOWED_SKEW);
+    (first..=current + ALLOWED_SKEW)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| hotp(&key, *step) == code)
+}
+
+/// Generate a fresh set of single-use backup codes, formatted `XXXX-XXXX`.
+pub fn generate_backup_codes() -> Vec<String> {
+    let mut rng = rand::rng();
+    (0..BACKUP_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..8)
+                .map(|_| {
+                    BACKUP_CODE_ALPHABET[rng.random_range(0..BACKUP_CODE_ALPHABET.len())] as char
+                })
+                .collect();
+            format!("{}-{}", &chars[..4], &chars[4..])
+        })
+        .collect()
+}
+
+/// Hash a backup code for storage. Codes are normalized first so that
+/// case and the separator don't matter when the player types them in.
+pub fn hash_backup_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// RFC 4226 HOTP with HMAC-SHA1 and dynamic truncation.
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+fn percent_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// The code an authenticator app would show for `secret` at time step `step`.
+#[cfg(test)]
+pub fn code_at_step(secret: &str, step: u64) -> Option<u32> {
+    let key = base32::decode(BASE32, secret)?;
+    Some(hotp(&key, step))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B test secret ("12345678901234567890") in base32.
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_rfc6238_vectors() {
+        let cases = [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+        ];
+        for (time, expected) in cases {
+            assert_eq!(
+                code_at_step(RFC_SECRET, step_at(time)),
+                Some(expected),
+                "t={time}"
+            );
+        }
+    }
+
+    #[test]
+    fn verify_accepts_adjacent_steps_only() {
+        let now = 1_700_000_000;
+        let step = step_at(now);
+        let prev = format!("{:06}", code_at_step(RFC_SECRET, step - 1).unwrap());
+        let stale = format!("{:06}", code_at_step(RFC_SECRET, step - 2).unwrap());
+
+        assert_eq!(verify(RFC_SECRET, &prev, now, None), Some(step - 1));
+        assert_eq!(verify(RFC_SECRET, &stale, now, None), None);
+    }
+
+    #[test]
+    fn verify_rejects_replayed_step() {
+        let now = 1_700_000_000;
+        let step = step_at(now);
+        let code = format!("{:06}", code_at_step(RFC_SECRET, step).unwrap());
+
+        assert_eq!(verify(RFC_SECRET, &code, now, Some(step - 1)), Some(step));
+        assert_eq!(verify(RFC_SECRET, &code, now, Some(step)), None);
+    }
+
+    #[test]
+    fn verify_rejects_malformed_codes() {
+        assert_eq!(verify(RFC_SECRET, "12345", 59, None), None);
+        assert_eq!(verify(RFC_SECRET, "28708a", 59, None), None);
+        assert_eq!(verify("not base32!", "287082", 59, None), None);
+    }
+
+    #[test]
+    fn backup_code_hash_ignores_case_and_separator() {
+        let codes = generate_backup_codes();
+        assert_eq!(codes.len(), BACKUP_CODE_COUNT);
+        let code = &codes[0];
+        assert_eq!(
+            hash_backup_code(code),
+            hash_backup_code(&code.replace('-', "").to_lowercase())
+        );
+    }
+
+    #[test]
+    fn otpauth_uri_escapes_label() {
+        let uri = otpauth_uri("ABC", "jane doe", "Consultancy Tycoon");
+        assert!(uri.starts_with("otpauth://totp/Consultancy%20Tycoon:jane%20doe?secret=ABC&"));
+    }
+}
@@ -1,21 +1,49 @@
 extends Node
 
 const AUTH_PATH = "user://cloud_auth.json"
+const CONFIG_PATH = "user://remote_config.json"
+const CONTENT_PATH = "user://content.json"
 const PRODUCTION_URL = "https://tycoon.jpro.dev"
 const LOCAL_URL = "http://localhost:3080"
+# Pinned so server-side changes to unversioned /api routes can't break this build
+const API_PREFIX = "/api/v1"
+const WRITE_ATTEMPTS = 3
+# Account limits the server enforces; checked here so players get a clear message
+const MAX_DISPLAY_NAME_LENGTH = 32
+const MIN_USERNAME_LENGTH = 3
+const MAX_USERNAME_LENGTH = 32
+const MIN_PASSWORD_LENGTH = 8
 
 var base_url: String = ""
 var player_id: String = ""
 var auth_token: String = ""
 var passphrase: String = ""
+# Set when a login needs a two-factor code; redeemed by login_two_factor()
+var two_factor_challenge: String = ""
 var _syncing: bool = false
+var _stream: HTTPClient
+var _stream_requested: bool = false
+var _stream_buffer: PackedByteArray
+var _config: Dictionary = {}
+var _config_etag: String = ""
+var _content: Dictionary = {}
+var _content_etag: String = ""
+var _assignments: Dictionary = {}
+var _exposed: Dictionary = {}
+# Idempotency-Key of each write that hasn't had a final answer yet, by path
+var _pending_writes: Dictionary = {}
 
 signal player_created(player_id: String, passphrase: String)
 signal player_recovered(player_id: String)
 signal sync_completed(success: bool)
 signal leaderboard_fetched(data: Dictionary)
+signal notification_received(notification: Dictionary)
+signal config_updated
+signal content_updated
+signal experiments_fetched
 
 func _ready():
+	set_process(false)
 	base_url = LOCAL_URL if OS.is_debug_build() else PRODUCTION_URL
 	print("[Cloud] Base URL: ", base_url)
 	_load_auth()
@@ -23,6 +51,50 @@ func _ready():
 		print("[Cloud] Loaded auth for player ", player_id.left(8))
 	else:
 		print("[Cloud] No saved auth found")
+	_load_config()
+	fetch_config()
+	_load_content()
+	fetch_content()
+	fetch_experiments()
+
+# The game's version, so the server can turn away builds too old for its API.
+# Every request needs it: on the web the User-Agent is the browser's, so the
+# server can't tell the game apart any other way
+func _headers(headers: Array = []) -> PackedStringArray:
+	var version = str(ProjectSettings.get_setting("application/config/version", "0.0.0"))
+	return PackedStringArray(headers + ["X-Client-Version: " + version])
+
+# Sends a write with an Idempotency-Key, retrying dropped connections and
+# server errors with the same key so the server applies it at most once. A
+# write that never got an answer keeps its key, so sending the same body
+# again later is still the same request.
+func _send_write(path: String, headers: Array, method: int, body: String) -> Array:
+	var pending = _pending_writes.get(path, {})
+	if pending.get("body") != body:
+		pending = {"body": body, "key": Crypto.new().generate_random_bytes(16).hex_encode()}
+		_pending_writes[path] = pending
+	headers = headers + ["Idempotency-Key: " + pending["key"]]
+	var result = []
+	for attempt in WRITE_ATTEMPTS:
+		if attempt > 0:
+			await get_tree().create_timer(pow(2, attempt)).timeout
+		var http = HTTPRequest.new()
+		add_child(http)
+		var err = http.request(base_url + API_PREFIX + path, _headers(headers), method, body)
+		if err != OK:
+			print("[Cloud] HTTP request failed to send: ", err)
+			http.queue_free()
+			return [HTTPRequest.RESULT_CANT_CONNECT, 0, PackedStringArray(), PackedByteArray()]
+		result = await http.request_completed
+		http.queue_free()
+		if not _should_retry(result):
+			_pending_writes.erase(path)
+			break
+	return result
+
+# 409 is the same key still in flight from an earlier attempt
+func _should_retry(result: Array) -> bool:
+	return result[0] != HTTPRequest.RESULT_SUCCESS or result[1] == 409 or result[1] >= 500
 
 # ── Auth persistence ──
 
@@ -51,23 +123,147 @@ func _save_auth():
 	if OS.has_feature("web"):
 		JavaScriptBridge.eval("if(window.Module&&Module.FS&&Module.FS.syncfs)Module.FS.syncfs(false,function(e){});")
 
-func is_authenticated() -> bool:
-	return player_id != "" and auth_token != ""
+# ── Remote config ──
 
-# ── Player creation ──
+# A tuning value published on the server, or default until one is. Values
+# fetched this session are saved for the next, so data defined at startup
+# picks them up then.
+func config_value(key: String, default: Variant) -> Variant:
+	return _config.get(key, default)
 
-func create_player(display_name: String) -> void:
-	print("[Cloud] Creating player: ", display_name, " via ", base_url + "/api/players")
+func fetch_config() -> void:
 	var http = HTTPRequest.new()
 	add_child(http)
-	var body = JSON.stringify({"display_name": display_name})
-	var err = http.request(base_url + "/api/players", ["Content-Type: application/json"], HTTPClient.METHOD_POST, body)
-	if err != OK:
-		print("[Cloud] HTTP request failed to send: ", err)
-		http.queue_free()
+	var headers = []
+	if is_authenticated():
+		headers.append("Authorization: Bearer " + auth_token)
+	if _config_etag != "":
+		headers.append("If-None-Match: " + _config_etag)
+	http.request(base_url + API_PREFIX + "/config", _headers(headers), HTTPClient.METHOD_GET)
+	var result = await http.request_completed
+	http.queue_free()
+	if result[1] != 200:
+		return
+	var json = JSON.new()
+	if json.parse(result[3].get_string_from_utf8()) != OK or not json.data is Dictionary:
+		return
+	_config = json.data.get("values", {})
+	_config_etag = ""
+	for header in result[2]:
+		if header.to_lower().begins_with("etag:"):
+			_config_etag = header.substr(5).strip_edges()
+	_save_config()
+	config_updated.emit()
+
+func _load_config():
+	if not FileAccess.file_exists(CONFIG_PATH):
+		return
+	var file = FileAccess.open(CONFIG_PATH, FileAccess.READ)
+	if not file:
 		return
+	var json = JSON.new()
+	if json.parse(file.get_as_text()) == OK and json.data is Dictionary:
+		_config = json.data.get("values", {})
+		_config_etag = str(json.data.get("etag", ""))
+
+func _save_config():
+	var file = FileAccess.open(CONFIG_PATH, FileAccess.WRITE)
+	if file:
+		file.store_string(JSON.stringify({"values": _config, "etag": _config_etag}, "\t"))
+		file.close()
+
+# ── Content ──
+
+# A section of the server's content catalog ("skills", "ai_tools" or
+# "contracts"), or null before one has been fetched. Like config values, a
+# catalog fetched this session is used from the next.
+func content(section: String) -> Variant:
+	return _content.get(section)
+
+func fetch_content() -> void:
+	var http = HTTPRequest.new()
+	add_child(http)
+	var headers = []
+	if _content_etag != "":
+		headers.append("If-None-Match: " + _content_etag)
+	http.request(base_url + API_PREFIX + "/content", _headers(headers), HTTPClient.METHOD_GET)
+	var result = await http.request_completed
+	http.queue_free()
+	if result[1] != 200:
+		return
+	var json = JSON.new()
+	if json.parse(result[3].get_string_from_utf8()) != OK or not json.data is Dictionary:
+		return
+	_content = json.data
+	_content_etag = ""
+	for header in result[2]:
+		if header.to_lower().begins_with("etag:"):
+			_content_etag = header.substr(5).strip_edges()
+	_save_content()
+	content_updated.emit()
+
+func _load_content():
+	if not FileAccess.file_exists(CONTENT_PATH):
+		return
+	var file = FileAccess.open(CONTENT_PATH, FileAccess.READ)
+	if not file:
+		return
+	var json = JSON.new()
+	if json.parse(file.get_as_text()) == OK and json.data is Dictionary:
+		_content = json.data.get("catalog", {})
+		_content_etag = str(json.data.get("etag", ""))
+
+func _save_content():
+	var file = FileAccess.open(CONTENT_PATH, FileAccess.WRITE)
+	if file:
+		file.store_string(JSON.stringify({"catalog": _content, "etag": _content_etag}, "\t"))
+		file.close()
+
+# ── Experiments ──
+
+# The variant this player is in, or default when not in the experiment
+func experiment_variant(key: String, default: String = "control") -> String:
+	return str(_assignments.get(key, default))
+
+# Call where the player actually sees the difference; only the first call
+# per experiment is sent
+func log_exposure(key: String) -> void:
+	if not is_authenticated() or not _assignments.has(key) or _exposed.has(key):
+		return
+	_exposed[key] = true
+	var http = HTTPRequest.new()
+	add_child(http)
+	var headers = ["Content-Type: application/json", "Authorization: Bearer " + auth_token]
+	http.request(base_url + API_PREFIX + "/experiments/" + key.uri_encode() + "/exposures", _headers(headers), HTTPClient.METHOD_POST, "{}")
+	var result = await http.request_completed
+	http.queue_free()
+
+func fetch_experiments() -> void:
+	if not is_authenticated():
+		return
+	var http = HTTPRequest.new()
+	add_child(http)
+	var headers = ["Authorization: Bearer " + auth_token]
+	http.request(base_url + API_PREFIX + "/experiments", _headers(headers), HTTPClient.METHOD_GET)
 	var result = await http.request_completed
 	http.queue_free()
+	if result[1] == 200:
+		var json = JSON.new()
+		if json.parse(result[3].get_string_from_utf8()) == OK and json.data is Dictionary:
+			_assignments = json.data.get("assignments", {})
+			experiments_fetched.emit()
+
+func is_authenticated() -> bool:
+	return player_id != "" and auth_token != ""
+
+# ── Player creation ──
+
+func create_player(display_name: String) -> void:
+	# Names from before the limit could be longer than the server accepts
+	display_name = display_name.left(MAX_DISPLAY_NAME_LENGTH)
+	print("[Cloud] Creating player: ", display_name, " via ", base_url + API_PREFIX + "/players")
+	var body = JSON.stringify({"display_name": display_name})
+	var result = await _send_write("/players", ["Content-Type: application/json"], HTTPClient.METHOD_POST, body)
 	var result_code = result[0]
 	var response_code = result[1]
 	var response_body = result[3].get_string_from_utf8()
@@ -81,6 +277,7 @@ func create_player(display_name: String) -> void:
 			_save_auth()
 			print("[Cloud] Player created: ", player_id.left(8), " passphrase: ", passphrase)
 			player_created.emit(player_id, passphrase)
+			fetch_experiments()
 		else:
 			print("[Cloud] Failed to parse response JSON")
 	else:
@@ -90,43 +287,41 @@ func recover_player(input_passphrase: String) -> void:
 	var http = HTTPRequest.new()
 	add_child(http)
 	var body = JSON.stringify({"passphrase": input_passphrase})
-	http.request(base_url + "/api/players/recover", ["Content-Type: application/json"], HTTPClient.METHOD_POST, body)
+	http.request(base_url + API_PREFIX + "/players/recover", _headers(["Content-Type: application/json"]), HTTPClient.METHOD_POST, body)
 	var result = await http.request_completed
 	http.queue_free()
 	var response_code = result[1]
 	if response_code == 200:
 		var json = JSON.new()
 		if json.parse(result[3].get_string_from_utf8()) == OK:
+			# Accounts with two-factor authentication get a challenge instead
+			if json.data.get("two_factor_required", false):
+				two_factor_challenge = str(json.data.get("challenge_token", ""))
+				print("[Cloud] Recovery needs a two-factor code")
+				return
 			player_id = str(json.data.get("id", ""))
 			auth_token = str(json.data.get("token", ""))
 			_save_auth()
 			player_recovered.emit(player_id)
+			fetch_experiments()
 
 # ── Score submission ──
 
 func submit_scores(components: Dictionary) -> void:
 	if not is_authenticated():
 		return
-	var http = HTTPRequest.new()
-	add_child(http)
 	var body = JSON.stringify(components)
 	var headers = ["Content-Type: application/json", "Authorization: Bearer " + auth_token]
-	http.request(base_url + "/api/scores", headers, HTTPClient.METHOD_PUT, body)
-	var result = await http.request_completed
-	http.queue_free()
+	await _send_write("/scores", headers, HTTPClient.METHOD_PUT, body)
 
 # ── Cloud save ──
 
 func upload_save(save_data: Dictionary) -> void:
 	if not is_authenticated():
 		return
-	var http = HTTPRequest.new()
-	add_child(http)
 	var body = JSON.stringify({"save_data": save_data, "version": 1})
 	var headers = ["Content-Type: application/json", "Authorization: Bearer " + auth_token]
-	http.request(base_url + "/api/saves", headers, HTTPClient.METHOD_PUT, body)
-	var result = await http.request_completed
-	http.queue_free()
+	await _send_write("/saves", headers, HTTPClient.METHOD_PUT, body)
 
 func download_save() -> Dictionary:
 	if not is_authenticated():
@@ -134,7 +329,7 @@ func download_save() -> Dictionary:
 	var http = HTTPRequest.new()
 	add_child(http)
 	var headers = ["Authorization: Bearer " + auth_token]
-	http.request(base_url + "/api/saves/me", headers, HTTPClient.METHOD_GET)
+	http.request(base_url + API_PREFIX + "/saves/me", _headers(headers), HTTPClient.METHOD_GET)
 	var result = await http.request_completed
 	http.queue_free()
 	if result[1] == 200:
@@ -151,7 +346,7 @@ func fetch_leaderboard() -> void:
 	var headers = []
 	if is_authenticated():
 		headers = ["Authorization: Bearer " + auth_token]
-	http.request(base_url + "/api/leaderboard", headers, HTTPClient.METHOD_GET)
+	http.request(base_url + API_PREFIX + "/leaderboard", _headers(headers), HTTPClient.METHOD_GET)
 	var result = await http.request_completed
 	http.queue_free()
 	if result[1] == 200:
@@ -159,6 +354,125 @@ func fetch_leaderboard() -> void:
 		if json.parse(result[3].get_string_from_utf8()) == OK:
 			leaderboard_fetched.emit(json.data)
 
+# Keep emitting leaderboard_fetched as the standings change, until
+# stop_watching_leaderboard(). Falls back to a single fetch if the server
+# can't stream.
+func watch_leaderboard() -> void:
+	stop_watching_leaderboard()
+	var parts = base_url.split("://")
+	var tls = TLSOptions.client() if parts[0] == "https" else null
+	var host_port = parts[1].split(":")
+	var port = int(host_port[1]) if host_port.size() > 1 else -1
+	_stream = HTTPClient.new()
+	if _stream.connect_to_host(host_port[0], port, tls) != OK:
+		_stream = null
+		fetch_leaderboard()
+		return
+	_stream_requested = false
+	_stream_buffer = PackedByteArray()
+	set_process(true)
+
+func stop_watching_leaderboard() -> void:
+	if _stream:
+		_stream.close()
+		_stream = null
+	set_process(false)
+
+func _process(_delta):
+	_stream.poll()
+	match _stream.get_status():
+		HTTPClient.STATUS_CONNECTED:
+			if _stream_requested:
+				stop_watching_leaderboard()
+				return
+			var headers = ["Accept: text/event-stream"]
+			if is_authenticated():
+				headers.append("Authorization: Bearer " + auth_token)
+			_stream.request(HTTPClient.METHOD_GET, API_PREFIX + "/leaderboard/stream", _headers(headers))
+			_stream_requested = true
+		HTTPClient.STATUS_BODY:
+			if _stream.get_response_code() != 200:
+				print("[Cloud] Leaderboard stream refused: ", _stream.get_response_code())
+				stop_watching_leaderboard()
+				fetch_leaderboard()
+				return
+			var chunk = _stream.read_response_body_chunk()
+			if chunk.size() > 0:
+				_stream_buffer.append_array(chunk)
+				_take_stream_events()
+		HTTPClient.STATUS_DISCONNECTED, HTTPClient.STATUS_CANT_RESOLVE, HTTPClient.STATUS_CANT_CONNECT, \
+		HTTPClient.STATUS_CONNECTION_ERROR, HTTPClient.STATUS_TLS_HANDSHAKE_ERROR:
+			print("[Cloud] Leaderboard stream closed")
+			stop_watching_leaderboard()
+
+# Events end with a blank line; keep-alive comments have no data
+func _take_stream_events() -> void:
+	while true:
+		var end = -1
+		for i in range(_stream_buffer.size() - 1):
+			if _stream_buffer[i] == 10 and _stream_buffer[i + 1] == 10:
+				end = i
+				break
+		if end == -1:
+			return
+		var event = _stream_buffer.slice(0, end).get_string_from_utf8()
+		_stream_buffer = _stream_buffer.slice(end + 2)
+		var event_name = ""
+		var data = ""
+		for line in event.split("\n"):
+			if line.begins_with("event: "):
+				event_name = line.substr(7)
+			elif line.begins_with("data: "):
+				data = line.substr(6)
+		var json = JSON.new()
+		if data == "" or json.parse(data) != OK:
+			continue
+		match event_name:
+			"leaderboard":
+				leaderboard_fetched.emit(_v1_leaderboard(json.data))
+			"notification":
+				notification_received.emit(json.data)
+
+# Stream events are shaped like the v2 leaderboard; listeners expect v1
+func _v1_leaderboard(data: Dictionary) -> Dictionary:
+	var me = data.get("me")
+	return {
+		"entries": data.get("entries", []),
+		"player_rank": me.get("rank") if me is Dictionary else null,
+		"player_score": me.get("score") if me is Dictionary else null,
+	}
+
+# ── Notifications ──
+
+# New ones also arrive as notification_received while watching the leaderboard
+func fetch_notifications(unread_only: bool = false) -> Dictionary:
+	if not is_authenticated():
+		return {}
+	var http = HTTPRequest.new()
+	add_child(http)
+	var headers = ["Authorization: Bearer " + auth_token]
+	var url = base_url + API_PREFIX + "/notifications?unread_only=" + str(unread_only).to_lower()
+	http.request(url, _headers(headers), HTTPClient.METHOD_GET)
+	var result = await http.request_completed
+	http.queue_free()
+	if result[1] == 200:
+		var json = JSON.new()
+		if json.parse(result[3].get_string_from_utf8()) == OK:
+			return json.data
+	return {}
+
+# Marks every notification read when ids is empty
+func mark_notifications_read(ids: Array = []) -> void:
+	if not is_authenticated():
+		return
+	var http = HTTPRequest.new()
+	add_child(http)
+	var body = JSON.stringify({"ids": ids} if not ids.is_empty() else {})
+	var headers = ["Content-Type: application/json", "Authorization: Bearer " + auth_token]
+	http.request(base_url + API_PREFIX + "/notifications/read", _headers(headers), HTTPClient.METHOD_POST, body)
+	var result = await http.request_completed
+	http.queue_free()
+
 # ── Profile update ──
 
 func update_display_name(new_name: String) -> void:
@@ -168,7 +482,7 @@ func update_display_name(new_name: String) -> void:
 	add_child(http)
 	var body = JSON.stringify({"display_name": new_name})
 	var headers = ["Content-Type: application/json", "Authorization: Bearer " + auth_token]
-	http.request(base_url + "/api/players/me", headers, HTTPClient.METHOD_PATCH, body)
+	http.request(base_url + API_PREFIX + "/players/me", _headers(headers), HTTPClient.METHOD_PATCH, body)
 	var result = await http.request_completed
 	http.queue_free()
 
@@ -179,12 +493,23 @@ func set_leaderboard_visibility(visible: bool) -> void:
 	add_child(http)
 	var body = JSON.stringify({"show_on_leaderboard": visible})
 	var headers = ["Content-Type: application/json", "Authorization: Bearer " + auth_token]
-	http.request(base_url + "/api/players/me", headers, HTTPClient.METHOD_PATCH, body)
+	http.request(base_url + API_PREFIX + "/players/me", _headers(headers), HTTPClient.METHOD_PATCH, body)
 	var result = await http.request_completed
 	http.queue_free()
 
 # ── Account upgrade ──
 
+# Why the server would reject these credentials, or "" if it won't
+func credentials_error(username: String, password: String) -> String:
+	if username.length() < MIN_USERNAME_LENGTH or username.length() > MAX_USERNAME_LENGTH:
+		return "Username must be %d-%d characters" % [MIN_USERNAME_LENGTH, MAX_USERNAME_LENGTH]
+	var allowed = RegEx.create_from_string("^[A-Za-z0-9_.-]+$")
+	if allowed.search(username) == null:
+		return "Username may only contain letters, digits, '_', '-' and '.'"
+	if password.length() < MIN_PASSWORD_LENGTH:
+		return "Password must be at least %d characters" % MIN_PASSWORD_LENGTH
+	return ""
+
 func register_account(username: String, password: String) -> bool:
 	if not is_authenticated():
 		return false
@@ -192,23 +517,52 @@ func register_account(username: String, password: String) -> bool:
 	add_child(http)
 	var body = JSON.stringify({"username": username, "password": password})
 	var headers = ["Content-Type: application/json", "Authorization: Bearer " + auth_token]
-	http.request(base_url + "/api/players/register", headers, HTTPClient.METHOD_POST, body)
+	http.request(base_url + API_PREFIX + "/players/register", _headers(headers), HTTPClient.METHOD_POST, body)
 	var result = await http.request_completed
 	http.queue_free()
 	return result[1] == 200
 
+# False when the login failed or still needs a two-factor code, in which
+# case two_factor_challenge is set and the saved auth is left alone
 func login(username: String, password: String) -> bool:
+	two_factor_challenge = ""
 	var http = HTTPRequest.new()
 	add_child(http)
 	var body = JSON.stringify({"username": username, "password": password})
-	http.request(base_url + "/api/players/login", ["Content-Type: application/json"], HTTPClient.METHOD_POST, body)
+	http.request(base_url + API_PREFIX + "/players/login", _headers(["Content-Type: application/json"]), HTTPClient.METHOD_POST, body)
 	var result = await http.request_completed
 	http.queue_free()
 	if result[1] == 200:
 		var json = JSON.new()
 		if json.parse(result[3].get_string_from_utf8()) == OK:
+			if json.data.get("two_factor_required", false):
+				two_factor_challenge = str(json.data.get("challenge_token", ""))
+				return false
 			player_id = str(json.data.get("id", ""))
 			auth_token = str(json.data.get("token", ""))
 			_save_auth()
 			return true
 	return false
+
+# Finish a login that needed a second factor, with a TOTP or backup code
+func login_two_factor(code: String) -> bool:
+	if two_factor_challenge == "":
+		return false
+	var http = HTTPRequest.new()
+	add_child(http)
+	var body = JSON.stringify({"challenge_token": two_factor_challenge, "code": code})
+	http.request(base_url + API_PREFIX + "/players/login/2fa", _headers(["Content-Type: application/json"]), HTTPClient.METHOD_POST, body)
+	var result = await http.request_completed
+	http.queue_free()
+	if result[1] != 200:
+		return false
+	var json = JSON.new()
+	if json.parse(result[3].get_string_from_utf8()) != OK:
+		return false
+	two_factor_challenge = ""
+	player_id = str(json.data.get("id", ""))
+	auth_token = str(json.data.get("token", ""))
+	_save_auth()
+	player_recovered.emit(player_id)
+	fetch_experiments()
+	return true
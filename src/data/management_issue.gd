@@ -1,7 +1,7 @@
 extends Resource
 class_name ManagementIssue
 
-const EXPIRY_TIME: float = 120.0  # seconds before auto-expiring
+const EXPIRY_TIME: float = 120.0  # seconds before auto-expiring, unless remote config says otherwise
 
 var id: String = ""
 var title: String = ""
@@ -13,7 +13,10 @@ var created_at: float = 0.0
 # Effects: { "type": "morale_change"|"fire"|"add_money"|"spend_money", "amount": float, "target": String }
 
 func is_expired(current_time: float) -> bool:
-	return current_time - created_at >= EXPIRY_TIME
+	return current_time - created_at >= expiry_time()
+
+static func expiry_time() -> float:
+	return float(CloudManager.config_value("management_issue.expiry_time", EXPIRY_TIME))
 
 static func create(p_id: String, p_title: String, p_desc: String, p_consultant_id: String, p_choices: Array) -> ManagementIssue:
 	var issue = ManagementIssue.new()
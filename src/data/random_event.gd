@@ -1,7 +1,7 @@
 class_name RandomEvent
 extends Resource
 
-const EXPIRY_TIME: float = 180.0  # seconds before auto-expiring
+const EXPIRY_TIME: float = 180.0  # seconds before auto-expiring, unless remote config says otherwise
 
 var id: String
 var title: String
@@ -12,7 +12,10 @@ var created_at: float = 0.0
 # Each effect: { "type": "add_money"|"spend_money"|"add_reputation", "amount": float }
 
 func is_expired(current_time: float) -> bool:
-	return current_time - created_at >= EXPIRY_TIME
+	return current_time - created_at >= expiry_time()
+
+static func expiry_time() -> float:
+	return float(CloudManager.config_value("random_event.expiry_time", EXPIRY_TIME))
 
 static func create(p_id: String, p_title: String, p_description: String, p_choices: Array) -> RandomEvent:
 	var event = RandomEvent.new()
@@ -7,22 +7,28 @@ func _init():
 	_define_tools()
 
 func _define_tools():
+	var tools = CloudManager.content("ai_tools")
+	if tools is Array and not tools.is_empty():
+		for t in tools:
+			_tools.append(_make_tool(t["id"], t["name"], t["description"], t["target_state"], float(t["base_cost"]), int(t["max_tier"]), float(t["cooldown"]), float(t["reliability"]), float(t["cost_multiplier"])))
+		return
 	_tools.append(_make_tool("auto_writer", "Auto-Writer", "Auto-types code for you", "writing", 2000.0, 5, 2.0, 0.45))
 	_tools.append(_make_tool("auto_reviewer", "Auto-reviewer", "Automatically submits code reviews", "reviewing", 1500.0, 5, 2.5, 0.50))
 	_tools.append(_make_tool("merge_resolver", "Merge Resolver", "Picks the correct side in merge conflicts", "conflict", 1200.0, 4, 1.5, 0.40))
 	_tools.append(_make_tool("ci_fixer", "CI Fixer", "Reduces CI failure chance", "ci", 1000.0, 4, 4.0, 0.5))
 
-func _make_tool(id: String, tool_name: String, desc: String, target: String, base_cost: float, max_tier: int, cooldown: float, reliability: float) -> AiToolData:
+func _make_tool(id: String, tool_name: String, desc: String, target: String, base_cost: float, max_tier: int, cooldown: float, reliability: float, cost_mult: float = 2.2) -> AiToolData:
 	var t = AiToolData.new()
 	t.id = id
 	t.name = tool_name
 	t.description = desc
 	t.target_state = target
 	t.base_cost = base_cost
-	t.cost_multiplier = 2.2
+	# Remote config scales every tool's own multiplier rather than replacing it
+	t.cost_multiplier = cost_mult * float(CloudManager.config_value("ai_tool.cost_multiplier", 1.0))
 	t.max_tier = max_tier
 	t.base_cooldown = cooldown
-	t.base_reliability = reliability
+	t.base_reliability = float(CloudManager.config_value("ai_tool.%s.base_reliability" % id, reliability))
 	return t
 
 func get_all_tools() -> Array[AiToolData]:
@@ -43,6 +43,11 @@ func get_difficulty_modifier(contract: ClientContract, player_skills: Dictionary
 	return 1.0 + total_gap * 0.3
 
 func _generate_contract(tier: int, task_multiplier: float = 1.0) -> ClientContract:
+	var catalog = CloudManager.content("contracts")
+	if catalog is Dictionary:
+		var tiers: Array = catalog.get("tiers", [])
+		if tier >= 1 and tier <= tiers.size():
+			return _contract_from_catalog(catalog, tiers[tier - 1], task_multiplier)
 	var contract = ClientContract.new()
 	contract.client_name = CLIENT_NAMES[randi() % CLIENT_NAMES.size()]
 	contract.project_description = PROJECT_TYPES[randi() % PROJECT_TYPES.size()]
@@ -61,6 +66,24 @@ func _generate_contract(tier: int, task_multiplier: float = 1.0) -> ClientContra
 	contract.duration = 120.0 - contract.tier * 15.0
 	return contract
 
+# The server's ranges for a tier, in place of the formulas above
+func _contract_from_catalog(catalog: Dictionary, tier: Dictionary, task_multiplier: float) -> ClientContract:
+	var contract = ClientContract.new()
+	var client_names: Array = catalog["client_names"]
+	var project_types: Array = catalog["project_types"]
+	contract.client_name = client_names[randi() % client_names.size()]
+	contract.project_description = project_types[randi() % project_types.size()]
+	contract.tier = int(tier["tier"])
+	contract.task_count = int(randi_range(int(tier["min_tasks"]), int(tier["max_tasks"])) * task_multiplier)
+	contract.payout_per_task = randf_range(float(tier["min_payout_per_task"]), float(tier["max_payout_per_task"]))
+	var shuffled: Array = catalog["skill_pool"].duplicate()
+	shuffled.shuffle()
+	var num_skills = randi_range(1, mini(int(tier["max_required_skills"]), shuffled.size()))
+	for j in range(num_skills):
+		contract.required_skills[shuffled[j]] = randi_range(1, int(tier["max_required_level"]))
+	contract.duration = float(tier["offer_duration"])
+	return contract
+
 func generate_contracts(count: int, reputation: float) -> Array[ClientContract]:
 	var contracts: Array[ClientContract] = []
 	var max_tier = clampi(int(reputation / 20.0) + 1, 1, 4)
@@ -7,6 +7,11 @@ func _init():
 	_define_skills()
 
 func _define_skills():
+	var skills = CloudManager.content("skills")
+	if skills is Array and not skills.is_empty():
+		for s in skills:
+			_skills.append(_make_skill(s["id"], s["name"], s["description"], s["category"], float(s["cost"]), int(s["max_level"]), float(s["cost_multiplier"])))
+		return
 	_skills.append(_make_skill("coding_speed", "Coding Speed", "Write code faster — more progress per click", "soft_skill", 50.0, 5))
 	_skills.append(_make_skill("code_quality", "Code Quality", "Fewer review rejections", "soft_skill", 75.0, 5))
 	_skills.append(_make_skill("javascript", "JavaScript", "Unlocks web development contracts", "language", 100.0, 5))
@@ -15,13 +20,15 @@ func _define_skills():
 	_skills.append(_make_skill("frameworks", "Frameworks", "Unlocks higher-tier contracts", "framework", 120.0, 5))
 	_skills.append(_make_skill("negotiation", "Negotiation", "Better bidding success rates", "soft_skill", 80.0, 3))
 
-func _make_skill(id: String, skill_name: String, desc: String, cat: String, base_cost: float, max_lvl: int) -> SkillData:
+func _make_skill(id: String, skill_name: String, desc: String, cat: String, base_cost: float, max_lvl: int, cost_mult: float = 1.8) -> SkillData:
 	var s = SkillData.new()
 	s.id = id
 	s.name = skill_name
 	s.description = desc
 	s.category = cat
 	s.cost = base_cost
+	# Remote config scales every skill's own multiplier rather than replacing it
+	s.cost_multiplier = cost_mult * float(CloudManager.config_value("skill.cost_multiplier", 1.0))
 	s.max_level = max_lvl
 	return s
 
@@ -308,6 +308,7 @@ func _build_welcome_layer():
 
 	var name_edit = LineEdit.new()
 	name_edit.placeholder_text = "Enter your name..."
+	name_edit.max_length = CloudManager.MAX_DISPLAY_NAME_LENGTH
 	name_edit.custom_minimum_size = Vector2(250, 40)
 	name_edit.add_theme_font_size_override("font_size", 16)
 	name_edit.alignment = HORIZONTAL_ALIGNMENT_CENTER
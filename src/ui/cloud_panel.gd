@@ -10,6 +10,10 @@ var _leaderboard_toggle: CheckButton
 var _username_edit: LineEdit
 var _password_edit: LineEdit
 var _register_btn: Button
+var _login_btn: Button
+var _code_row: HBoxContainer
+var _code_edit: LineEdit
+var _code_btn: Button
 
 func _ready():
 	custom_minimum_size = Vector2(450, 500)
@@ -49,6 +53,7 @@ func _build_ui():
 
 	_name_edit = LineEdit.new()
 	_name_edit.placeholder_text = "Your name..."
+	_name_edit.max_length = CloudManager.MAX_DISPLAY_NAME_LENGTH
 	_name_edit.size_flags_horizontal = Control.SIZE_EXPAND_FILL
 	_name_edit.add_theme_font_size_override("font_size", UITheme.BODY)
 	name_row.add_child(_name_edit)
@@ -107,6 +112,7 @@ func _build_ui():
 
 	_username_edit = LineEdit.new()
 	_username_edit.placeholder_text = "Username"
+	_username_edit.max_length = CloudManager.MAX_USERNAME_LENGTH
 	_username_edit.add_theme_font_size_override("font_size", UITheme.BODY)
 	vbox.add_child(_username_edit)
 
@@ -116,11 +122,41 @@ func _build_ui():
 	_password_edit.add_theme_font_size_override("font_size", UITheme.BODY)
 	vbox.add_child(_password_edit)
 
+	var account_row = HBoxContainer.new()
+	account_row.add_theme_constant_override("separation", UITheme.NORMAL)
+	vbox.add_child(account_row)
+
 	_register_btn = Button.new()
 	_register_btn.text = "Create Account"
+	_register_btn.size_flags_horizontal = Control.SIZE_EXPAND_FILL
 	UITheme.style_button(_register_btn)
 	_register_btn.pressed.connect(_on_register)
-	vbox.add_child(_register_btn)
+	account_row.add_child(_register_btn)
+
+	_login_btn = Button.new()
+	_login_btn.text = "Log In"
+	_login_btn.size_flags_horizontal = Control.SIZE_EXPAND_FILL
+	UITheme.style_button(_login_btn)
+	_login_btn.pressed.connect(_on_login)
+	account_row.add_child(_login_btn)
+
+	# Shown when a login needs a two-factor code
+	_code_row = HBoxContainer.new()
+	_code_row.add_theme_constant_override("separation", UITheme.NORMAL)
+	_code_row.visible = false
+	vbox.add_child(_code_row)
+
+	_code_edit = LineEdit.new()
+	_code_edit.placeholder_text = "Authenticator or backup code"
+	_code_edit.size_flags_horizontal = Control.SIZE_EXPAND_FILL
+	_code_edit.add_theme_font_size_override("font_size", UITheme.BODY)
+	_code_row.add_child(_code_edit)
+
+	_code_btn = Button.new()
+	_code_btn.text = "Verify"
+	UITheme.style_button(_code_btn)
+	_code_btn.pressed.connect(_on_verify_code)
+	_code_row.add_child(_code_btn)
 
 func refresh():
 	# Display name
@@ -159,8 +195,11 @@ func _on_register():
 	var username = _username_edit.text.strip_edges()
 	var password = _password_edit.text
 	if username == "" or password == "":
-		_status_label.text = "Username and password required"
-		_status_label.add_theme_color_override("font_color", Color(0.9, 0.4, 0.4))
+		_show_error("Username and password required")
+		return
+	var error = CloudManager.credentials_error(username, password)
+	if error != "":
+		_show_error(error)
 		return
 	_register_btn.disabled = true
 	_register_btn.text = "Creating..."
@@ -175,3 +214,49 @@ func _on_register():
 	else:
 		_status_label.text = "Registration failed. Try a different username."
 		_status_label.add_theme_color_override("font_color", Color(0.9, 0.4, 0.4))
+
+func _on_login():
+	var username = _username_edit.text.strip_edges()
+	var password = _password_edit.text
+	if username == "" or password == "":
+		_show_error("Username and password required")
+		return
+	_login_btn.disabled = true
+	_login_btn.text = "Logging in..."
+	var success = await CloudManager.login(username, password)
+	_login_btn.disabled = false
+	_login_btn.text = "Log In"
+	if success:
+		_password_edit.text = ""
+		refresh()
+		_status_label.text = "Logged in!"
+		_status_label.add_theme_color_override("font_color", Color(0.4, 0.8, 0.4))
+	elif CloudManager.two_factor_challenge != "":
+		_password_edit.text = ""
+		_code_edit.text = ""
+		_code_row.visible = true
+		_status_label.text = "Enter the code from your authenticator app"
+		_status_label.add_theme_color_override("font_color", UITheme.TEXT_SECONDARY)
+	else:
+		_show_error("Login failed. Check your username and password.")
+
+func _on_verify_code():
+	var code = _code_edit.text.strip_edges()
+	if code == "":
+		_show_error("Code required")
+		return
+	_code_btn.disabled = true
+	var success = await CloudManager.login_two_factor(code)
+	_code_btn.disabled = false
+	if success:
+		_code_row.visible = false
+		refresh()
+		_status_label.text = "Logged in!"
+		_status_label.add_theme_color_override("font_color", Color(0.4, 0.8, 0.4))
+	else:
+		_code_edit.text = ""
+		_show_error("Wrong or expired code. Log in again if this keeps happening.")
+
+func _show_error(message: String):
+	_status_label.text = message
+	_status_label.add_theme_color_override("font_color", Color(0.9, 0.4, 0.4))
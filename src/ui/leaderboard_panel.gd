@@ -10,6 +10,7 @@ func _ready():
 	custom_minimum_size = Vector2(600, 500)
 	add_theme_stylebox_override("panel", UITheme.create_panel_style())
 	_build_ui()
+	visibility_changed.connect(_on_visibility_changed)
 
 func _build_ui():
 	var vbox = VBoxContainer.new()
@@ -75,19 +76,29 @@ func _build_ui():
 	vbox.add_child(player_card)
 
 func refresh():
+	_clear_entries()
 	_loading_label.visible = true
-	# Clear previous entries (keep loading label)
-	for child in _entry_list.get_children():
-		if child != _loading_label:
-			child.queue_free()
 	_player_row.visible = false
 
+	# Live while the panel is open
 	if not CloudManager.leaderboard_fetched.is_connected(_on_leaderboard_data):
-		CloudManager.leaderboard_fetched.connect(_on_leaderboard_data, CONNECT_ONE_SHOT)
-	CloudManager.fetch_leaderboard()
+		CloudManager.leaderboard_fetched.connect(_on_leaderboard_data)
+	CloudManager.watch_leaderboard()
+
+func _on_visibility_changed():
+	if not visible and CloudManager.leaderboard_fetched.is_connected(_on_leaderboard_data):
+		CloudManager.leaderboard_fetched.disconnect(_on_leaderboard_data)
+		CloudManager.stop_watching_leaderboard()
+
+# Remove all rows but the loading label
+func _clear_entries():
+	for child in _entry_list.get_children():
+		if child != _loading_label:
+			child.queue_free()
 
 func _on_leaderboard_data(data: Dictionary):
 	_loading_label.visible = false
+	_clear_entries()
 
 	var entries = data.get("entries", [])
 	for entry in entries:
@@ -93,7 +93,7 @@ func test_random_event_not_expired_when_fresh():
 
 func test_random_event_expired_after_timeout():
 	var event = RandomEvent.create("test", "Test", "Desc", [])
-	assert_true(event.is_expired(event.created_at + RandomEvent.EXPIRY_TIME + 1.0), "Old event should be expired")
+	assert_true(event.is_expired(event.created_at + RandomEvent.expiry_time() + 1.0), "Old event should be expired")
 
 func test_management_issue_not_expired_when_fresh():
 	var issue = ManagementIssue.create("test", "Test", "Desc", "c1", [])
@@ -101,7 +101,7 @@ func test_management_issue_not_expired_when_fresh():
 
 func test_management_issue_expired_after_timeout():
 	var issue = ManagementIssue.create("test", "Test", "Desc", "c1", [])
-	assert_true(issue.is_expired(issue.created_at + ManagementIssue.EXPIRY_TIME + 1.0), "Old issue should be expired")
+	assert_true(issue.is_expired(issue.created_at + ManagementIssue.expiry_time() + 1.0), "Old issue should be expired")
 
 func test_management_issue_choices_are_positive():
 	var cm = ConsultantManager.new()